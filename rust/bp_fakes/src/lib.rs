@@ -6,6 +6,7 @@ use buttplug::{
     client::{ButtplugClient, ButtplugClientDevice},
     core::{
         connector::{ButtplugConnector, ButtplugConnectorError, ButtplugConnectorResult},
+        errors::{ButtplugDeviceError, ButtplugError},
         message::*,
         message::{self, ButtplugMessage, DeviceList},
         message::{ActuatorType, ClientDeviceMessageAttributes},
@@ -14,7 +15,10 @@ use buttplug::{
 };
 
 use tokio::sync::mpsc::channel;
-use tokio::{sync::mpsc::Sender, time::sleep};
+use tokio::{
+    sync::mpsc::Sender,
+    time::{sleep, Instant},
+};
 
 use serde::Serialize;
 use serde_json::{self, Value};
@@ -28,11 +32,15 @@ use std::{collections::HashMap, sync::Arc};
 use tracing::{debug, error};
 use assert_float_eq::*;
 
-use std::time::Instant;
-
 #[derive(Clone)]
 pub struct FakeConnectorCallRegistry {
     pub actions: Arc<Mutex<HashMap<u32, Vec<FakeMessage>>>>,
+    /// Number of upcoming device commands that fail, per device
+    pub failures: Arc<Mutex<HashMap<u32, u32>>>,
+    /// Values that are returned when a sensor is read, by device and sensor index
+    pub sensors: Arc<Mutex<HashMap<(u32, u32), i32>>>,
+    /// Sends messages from the server to the client, available once connected
+    server_sender: Arc<Mutex<Option<Sender<ButtplugCurrentSpecServerMessage>>>>,
 }
 
 #[derive(Clone, Debug)]
@@ -93,6 +101,26 @@ impl FakeMessage {
         self
     }
 
+    /// Asserts the position within an absolute tolerance, for positions that depend on timing
+    pub fn assert_pos_near(&self, position: f64, tolerance: f64) -> &Self {
+        match self.message.clone() {
+            message::ButtplugSpecV3ClientMessage::LinearCmd(cmd) => {
+                for v in cmd.vectors().iter() {
+                    let actual: f64 = v.position();
+                    assert!(
+                        (position - actual).abs() <= tolerance,
+                        "{} is not {} +/-{}",
+                        actual,
+                        position,
+                        tolerance
+                    );
+                }
+            }
+            _ => panic!("Message is not linear cmd"),
+        }
+        self
+    }
+
     pub fn assert_duration(&self, duration: u32) -> &Self {
         match self.message.clone() {
             message::ButtplugSpecV3ClientMessage::LinearCmd(cmd) => {
@@ -125,6 +153,13 @@ impl FakeMessage {
         self
     }
 
+    /// Asserts the exact timestamp, for tests that run on a paused tokio clock
+    pub fn assert_exact_time(&self, time_ms: u64, start_instant: Instant) -> &Self {
+        let elapsed = self.time.duration_since(start_instant);
+        assert_eq!(elapsed, Duration::from_millis(time_ms), "timestamp");
+        self
+    }
+
     pub fn assert_rotation(&self, strength: f64) -> &Self {
         match self.message.clone() {
             message::ButtplugSpecV3ClientMessage::RotateCmd(cmd) => {
@@ -168,6 +203,9 @@ impl FakeConnectorCallRegistry {
     fn default() -> Self {
         Self {
             actions: Arc::new(Mutex::new(HashMap::new())),
+            failures: Arc::new(Mutex::new(HashMap::new())),
+            sensors: Arc::new(Mutex::new(HashMap::new())),
+            server_sender: Arc::new(Mutex::new(None)),
         }
     }
 
@@ -195,6 +233,61 @@ impl FakeConnectorCallRegistry {
     pub fn assert_unused(&self, device_id: u32) {
         assert_eq!(self.get_device(device_id).len(), 0);
     }
+
+    /// The next 'count' commands to the device are recorded but answered
+    /// with a device communication error
+    pub fn fail_next(&self, device_id: u32, count: u32) {
+        self.failures.lock().unwrap().insert(device_id, count);
+    }
+
+    /// Sets the value that is returned when the sensor is read, unset sensors return 0
+    pub fn set_sensor(&self, device_id: u32, sensor_index: u32, value: i32) {
+        self.sensors
+            .lock()
+            .unwrap()
+            .insert((device_id, sensor_index), value);
+    }
+
+    /// Sends a reading of a subscribed sensor to the connected client
+    pub fn send_sensor_reading(
+        &self,
+        device_id: u32,
+        sensor_index: u32,
+        sensor_type: SensorType,
+        value: i32,
+    ) {
+        let reading = SensorReading::new(device_id, sensor_index, sensor_type, vec![value]);
+        self.server_sender
+            .lock()
+            .unwrap()
+            .as_ref()
+            .expect("connector is not connected")
+            .try_send(ButtplugSpecV3ServerMessage::SensorReading(reading))
+            .unwrap();
+    }
+
+    fn get_sensor(&self, device_id: u32, sensor_index: u32) -> i32 {
+        self.sensors
+            .lock()
+            .unwrap()
+            .get(&(device_id, sensor_index))
+            .copied()
+            .unwrap_or(0)
+    }
+
+    fn take_failure<T>(&self, imp: &T) -> bool
+    where
+        T: Serialize,
+    {
+        let device_id: u32 = get_value(imp, "DeviceIndex").parse().unwrap();
+        match self.failures.lock().unwrap().get_mut(&device_id) {
+            Some(count) if *count > 0 => {
+                *count -= 1;
+                true
+            }
+            _ => false,
+        }
+    }
 }
 
 pub struct FakeDeviceConnector {
@@ -245,6 +338,26 @@ impl FakeDeviceConnector {
         }
         .boxed()
     }
+
+    fn response<T>(&self, imp: &T, msg_id: u32) -> buttplug::core::connector::ButtplugConnectorResultFuture
+    where
+        T: Serialize,
+    {
+        if !self.call_registry.take_failure(imp) {
+            return self.ok_response(msg_id);
+        }
+        let sender = self.server_outbound_sender.clone();
+        async move {
+            let err = ButtplugDeviceError::DeviceCommunicationError("fake failure".into());
+            let mut response = ButtplugSpecV3ServerMessage::Error(ButtplugError::from(err).into());
+            response.set_id(msg_id);
+            sender
+                .send(response)
+                .await
+                .map_err(|_| ButtplugConnectorError::ConnectorNotConnected)
+        }
+        .boxed()
+    }
 }
 
 impl ButtplugConnector<ButtplugCurrentSpecClientMessage, ButtplugCurrentSpecServerMessage>
@@ -254,6 +367,7 @@ impl ButtplugConnector<ButtplugCurrentSpecClientMessage, ButtplugCurrentSpecServ
         &mut self,
         message_sender: tokio::sync::mpsc::Sender<ButtplugCurrentSpecServerMessage>,
     ) -> BoxFuture<'static, Result<(), ButtplugConnectorError>> {
+        *self.call_registry.server_sender.lock().unwrap() = Some(message_sender.clone());
         self.server_outbound_sender = message_sender;
         async move {
             async_manager::spawn(async move {
@@ -316,14 +430,43 @@ impl ButtplugConnector<ButtplugCurrentSpecClientMessage, ButtplugCurrentSpecServ
             ButtplugCurrentSpecClientMessage::ScalarCmd(cmd) => {
                 self.call_registry
                     .store_record(&cmd, FakeMessage::new(msg_clone));
-                self.ok_response(msg_id)
+                self.response(&cmd, msg_id)
             }
             ButtplugCurrentSpecClientMessage::LinearCmd(cmd) => {
                 self.call_registry
                     .store_record(&cmd, FakeMessage::new(msg_clone));
-                self.ok_response(msg_id)
+                self.response(&cmd, msg_id)
             }
             ButtplugCurrentSpecClientMessage::RotateCmd(cmd) => {
+                self.call_registry
+                    .store_record(&cmd, FakeMessage::new(msg_clone));
+                self.response(&cmd, msg_id)
+            }
+            ButtplugCurrentSpecClientMessage::SensorReadCmd(cmd) => {
+                let value = self
+                    .call_registry
+                    .get_sensor(cmd.device_index(), *cmd.sensor_index());
+                async move {
+                    let mut response = ButtplugSpecV3ServerMessage::SensorReading(SensorReading::new(
+                        cmd.device_index(),
+                        *cmd.sensor_index(),
+                        *cmd.sensor_type(),
+                        vec![value],
+                    ));
+                    response.set_id(msg_id);
+                    sender
+                        .send(response)
+                        .await
+                        .map_err(|_| ButtplugConnectorError::ConnectorNotConnected)
+                }
+                .boxed()
+            }
+            ButtplugCurrentSpecClientMessage::SensorSubscribeCmd(cmd) => {
+                self.call_registry
+                    .store_record(&cmd, FakeMessage::new(msg_clone));
+                self.ok_response(msg_id)
+            }
+            ButtplugCurrentSpecClientMessage::SensorUnsubscribeCmd(cmd) => {
                 self.call_registry
                     .store_record(&cmd, FakeMessage::new(msg_clone));
                 self.ok_response(msg_id)
@@ -421,6 +564,61 @@ pub fn linear(id: u32, name: &str) -> DeviceAdded {
     )
 }
 
+/// Vibrator that can read its battery level (sensor 0) and signal strength (sensor 1)
+#[allow(dead_code)]
+pub fn vibrator_with_sensors(id: u32, name: &str) -> DeviceAdded {
+    let sensor = |sensor_type: &str, min: u32, max: u32| -> SensorDeviceMessageAttributes {
+        serde_json::from_value(serde_json::json!({
+            "FeatureDescriptor": sensor_type,
+            "SensorType": sensor_type,
+            "SensorRange": [[min, max]],
+        }))
+        .unwrap()
+    };
+    let mut builder = ServerDeviceMessageAttributesBuilder::default();
+    builder.scalar_cmd(&[ServerGenericDeviceMessageAttributes::new(
+        &format!("Vibrator {}", id),
+        &RangeInclusive::new(0, 10),
+        ActuatorType::Vibrate,
+    )]);
+    // sensor ranges are unsigned, the rssi readings themselves are negative
+    builder.sensor_read_cmd(&[sensor("Battery", 0, 100), sensor("RSSI", 0, 128)]);
+    let attributes = builder.finish();
+    DeviceAdded::new(
+        id,
+        name,
+        &None,
+        &None,
+        &ClientDeviceMessageAttributes::from(attributes),
+    )
+}
+
+/// Vibrator with a pressure sensor (sensor 0) that can be subscribed
+#[allow(dead_code)]
+pub fn vibrator_with_pressure(id: u32, name: &str) -> DeviceAdded {
+    let pressure: SensorDeviceMessageAttributes = serde_json::from_value(serde_json::json!({
+        "FeatureDescriptor": "Pressure",
+        "SensorType": "Pressure",
+        "SensorRange": [[0, 1000]],
+    }))
+    .unwrap();
+    let mut builder = ServerDeviceMessageAttributesBuilder::default();
+    builder.scalar_cmd(&[ServerGenericDeviceMessageAttributes::new(
+        &format!("Vibrator {}", id),
+        &RangeInclusive::new(0, 10),
+        ActuatorType::Vibrate,
+    )]);
+    builder.sensor_subscribe_cmd(&[pressure]);
+    let attributes = builder.finish();
+    DeviceAdded::new(
+        id,
+        name,
+        &None,
+        &None,
+        &ClientDeviceMessageAttributes::from(attributes),
+    )
+}
+
 #[allow(dead_code)]
 pub fn rotate(id: u32, name: &str) -> DeviceAdded {
     let attributes = ServerDeviceMessageAttributesBuilder::default()
@@ -510,6 +708,9 @@ impl ButtplugTestClient {
             message::ButtplugSpecV3ClientMessage::LinearCmd(cmd) => {
                 cmd.vectors().iter().next().unwrap().position()
             }
+            message::ButtplugSpecV3ClientMessage::RotateCmd(cmd) => {
+                cmd.rotations().iter().next().unwrap().speed()
+            }
             _ => panic!("Message is not supported"),
         }
     }
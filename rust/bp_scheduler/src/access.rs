@@ -1,130 +1,263 @@
-use buttplug::client::{ButtplugClientError, ScalarCommand};
 use std::collections::HashMap;
 
 use std::sync::Arc;
-use tracing::{debug, error, trace, instrument};
+use std::time::Duration;
+use tokio::time::Instant;
+use tracing::{debug, trace, instrument};
 
-use crate::{actuator::Actuator, speed::Speed};
+use crate::{
+    actuator::Actuator,
+    priority::{Arbitration, BlendMode, Priority},
+    speed::Speed,
+};
 
 /// Stores information about concurrent accesses to a buttplug actuator
-/// to calculate the actual vibration speed or linear movement
+/// to calculate the actual vibration speed, the returned `(speed, clockwise)`
+/// values are sent to the device by the worker
 pub struct DeviceEntry {
-    /// The amount of tasks that currently access this device,
-    pub task_count: usize,
-    /// Priority calculation work like a stack with the top of the stack
-    /// task being the used vibration speed
-    pub linear_tasks: Vec<(i32, Speed)>,
+    pub actuator: Arc<Actuator>,
+    /// All constant and pattern tasks with their current speed and priority,
+    /// the used vibration speed is calculated from these with the blend mode
+    pub tasks: Vec<(i32, Speed, Priority)>,
+    /// How the speeds of all tasks are combined, taken from the actuator settings
+    /// when the entry is created and kept until all of its tasks are stopped
+    pub blend_mode: BlendMode,
+    /// Rotation direction of each task, only used by rotate actuators
+    pub directions: HashMap<i32, bool>,
+}
+
+/// Last move of a linear actuator and the task that started it
+struct LinearMove {
+    /// Position at the start of the move, None if it was unknown
+    from: Option<f64>,
+    to: f64,
+    started: Instant,
+    duration: Duration,
+    handle: i32,
+}
+
+impl LinearMove {
+    /// Position the actuator reached by now, assuming it moves with a constant velocity.
+    /// Unknown while a move that started at an unknown position is still running
+    fn reached(&self) -> Option<f64> {
+        let elapsed = self.started.elapsed();
+        if elapsed >= self.duration {
+            return Some(self.to);
+        }
+        let progress = elapsed.as_secs_f64() / self.duration.as_secs_f64();
+        self.from.map(|from| from + (self.to - from) * progress)
+    }
 }
 
 pub struct DeviceAccess {
     device_actions: HashMap<String, DeviceEntry>,
+    positions: HashMap<String, LinearMove>,
+    /// See `PlayerSettings::handover_velocity`
+    handover_velocity: u32,
 }
 
 impl DeviceAccess {
-    pub fn default() -> Self {
+    pub fn new(handover_velocity: u32) -> Self {
         DeviceAccess {
             device_actions: HashMap::new(),
+            positions: HashMap::new(),
+            handover_velocity,
+        }
+    }
+
+    /// Remembers the move of a linear actuator and returns its duration. The first move
+    /// of a task is slowed down to the handover velocity, the distance is measured from the
+    /// position reached by the last move, or assumed to cross the full stroke if it is unknown
+    pub fn move_linear(
+        &mut self,
+        actuator: &Arc<Actuator>,
+        position: f64,
+        duration_ms: u32,
+        handle: i32,
+    ) -> u32 {
+        let last = self.positions.get(actuator.identifier());
+        let from = last.and_then(|last| last.reached());
+        let handover = !matches!(last, Some(last) if last.handle == handle);
+        let duration_ms = if self.handover_velocity > 0 && handover {
+            let distance = from.map(|from| (position - from).abs()).unwrap_or(1.0);
+            let min_ms = (distance * 100_000.0 / self.handover_velocity as f64).round() as u32;
+            if min_ms > duration_ms {
+                debug!(%actuator, ?distance, ?duration_ms, ?min_ms, "slowing down first move");
+            }
+            duration_ms.max(min_ms)
+        } else {
+            duration_ms
+        };
+        self.positions.insert(
+            actuator.identifier().into(),
+            LinearMove {
+                from,
+                to: position,
+                started: Instant::now(),
+                duration: Duration::from_millis(duration_ms.into()),
+                handle,
+            },
+        );
+        duration_ms
+    }
+
+    /// Stops the move of a linear actuator at the position it reached by now, returns
+    /// None if that position is unknown or another task moved the actuator last
+    pub fn hold_linear(&mut self, actuator: &Arc<Actuator>, handle: i32) -> Option<f64> {
+        let last = self.positions.get_mut(actuator.identifier())?;
+        if last.handle != handle {
+            return None;
         }
+        let position = last.reached()?;
+        *last = LinearMove {
+            from: Some(position),
+            to: position,
+            started: Instant::now(),
+            duration: Duration::ZERO,
+            handle,
+        };
+        Some(position)
     }
 
-    pub async fn start_scalar(
+    pub fn start_scalar(
         &mut self,
         actuator: &Arc<Actuator>,
         speed: Speed,
-        is_pattern: bool,
         handle: i32,
-    ) {
+        arbitration: Arbitration,
+    ) -> (Speed, bool) {
         trace!("start scalar {:?} {} {}", speed, actuator, handle);
+        self.add_task(actuator, speed, handle, arbitration);
+        let (speed, _) = self.get_priority_speed(actuator).unwrap_or((speed, true));
+        (speed, true)
+    }
+
+    /// Starts or updates the rotation of `handle`, rotations use the same
+    /// priority calculation as scalar tasks but also remember the direction
+    #[instrument(skip(self))]
+    pub fn rotate(
+        &mut self,
+        actuator: &Arc<Actuator>,
+        speed: Speed,
+        clockwise: bool,
+        handle: i32,
+        arbitration: Arbitration,
+    ) -> (Speed, bool) {
+        trace!("rotate");
+        let started = self
+            .device_actions
+            .get(actuator.identifier())
+            .is_some_and(|entry| entry.directions.contains_key(&handle));
+        if started {
+            self.update_task(actuator, speed, handle);
+        } else {
+            self.add_task(actuator, speed, handle, arbitration);
+        }
         self.device_actions
             .entry(actuator.identifier().into())
             .and_modify(|entry| {
-                entry.task_count += 1;
-                if ! is_pattern {
-                    entry.linear_tasks.push((handle, speed))
-                }
-            })
-            .or_insert_with(|| DeviceEntry {
-                task_count: 1,
-                linear_tasks: if is_pattern {
-                    vec![]
-                } else {
-                    vec![(handle, speed)]
-                },
+                entry.directions.insert(handle, clockwise);
             });
-        let _ = self.set_scalar(actuator, speed).await;
+        let (speed, clockwise) = self
+            .get_priority_speed(actuator)
+            .unwrap_or((speed, clockwise));
+        debug!("rotating {} with {} clockwise={}", actuator, speed, clockwise);
+        (speed, clockwise)
     }
 
+    /// Removes the task, returns None if the actuator was not accessed at all
     #[instrument(skip(self))]
-    pub async fn stop_scalar(
-        &mut self,
-        actuator: &Arc<Actuator>,
-        is_pattern: bool,
-        handle: i32,
-    ) -> Result<(), ButtplugClientError> {
+    pub fn stop_scalar(&mut self, actuator: &Arc<Actuator>, handle: i32) -> Option<(Speed, bool)> {
         trace!("stop scalar");
-        if let Some(mut entry) = self.device_actions.remove(actuator.identifier()) {
-            if ! is_pattern {
-                entry.linear_tasks.retain(|t| t.0 != handle);
-            }
-            let mut count = entry.task_count;
-            count = count.saturating_sub(1);
-            entry.task_count = count;
-            self.device_actions.insert(actuator.identifier().into(), entry);
-            if count == 0 {
-                // nothing else is controlling the device, stop it
-                return self.set_scalar(actuator, Speed::min()).await;
-            } else if let Some(last_speed) = self.get_priority_speed(actuator) {
-                let _ = self.set_scalar(actuator, last_speed).await;
-            }
+        let mut entry = self.device_actions.remove(actuator.identifier())?;
+        entry.tasks.retain(|t| t.0 != handle);
+        entry.directions.remove(&handle);
+        let remaining = entry.tasks.len();
+        self.device_actions.insert(actuator.identifier().into(), entry);
+        if remaining == 0 {
+            // nothing else is controlling the device, stop it
+            return Some((Speed::min(), true));
         }
-        Ok(())
+        // hand back to the remaining tasks
+        self.get_priority_speed(actuator)
     }
 
     #[instrument(skip(self))]
-    pub async fn update_scalar(&mut self, actuator: &Arc<Actuator>, new_speed: Speed, is_pattern: bool, handle: i32) {
+    pub fn update_scalar(
+        &mut self,
+        actuator: &Arc<Actuator>,
+        new_speed: Speed,
+        handle: i32,
+    ) -> (Speed, bool) {
         trace!("update scalar scalar");
-        if ! is_pattern {
-            self.device_actions.entry(actuator.identifier().into()).and_modify(|entry| {
-                entry.linear_tasks = entry.linear_tasks.iter().map(|t| {
-                    if t.0 == handle {
-                        return (handle, new_speed);
-                    }
-                    *t
-                }).collect()
-            });
-        }
-        let speed = self.get_priority_speed(actuator).unwrap_or(new_speed);
+        self.update_task(actuator, new_speed, handle);
+        let (speed, _) = self.get_priority_speed(actuator).unwrap_or((new_speed, true));
         debug!("updating {} speed to {}", actuator, speed);
-        let _ = self.set_scalar(actuator, speed).await;
+        (speed, true)
     }
 
-    #[instrument(skip(self))]
-    async fn set_scalar(
-        &self,
+    fn add_task(
+        &mut self,
         actuator: &Arc<Actuator>,
         speed: Speed,
-    ) -> Result<(), ButtplugClientError> {
-        let cmd = ScalarCommand::ScalarMap(HashMap::from([(
-            actuator.index_in_device,
-            (speed.as_float(), actuator.actuator),
-        )]));
-
-        if let Err(err) = actuator.device.scalar(&cmd).await {
-            error!("failed to set scalar speed {:?}", err);
-            return Err(err);
+        handle: i32,
+        arbitration: Arbitration,
+    ) {
+        let entry = self
+            .device_actions
+            .entry(actuator.identifier().into())
+            .or_insert_with(|| DeviceEntry {
+                actuator: actuator.clone(),
+                tasks: vec![],
+                blend_mode: arbitration.blend_mode,
+                directions: HashMap::new(),
+            });
+        if entry.tasks.is_empty() {
+            // an idle actuator picks up settings that changed since it was last used
+            entry.blend_mode = arbitration.blend_mode;
         }
-        Ok(())
+        entry.tasks.push((handle, speed, arbitration.priority));
     }
 
-    fn get_priority_speed(&self, actuator: &Arc<Actuator>) -> Option<Speed> {
-        if let Some(entry) = self.device_actions.get(actuator.identifier()) {
-            let mut sorted: Vec<(i32, Speed)> = entry.linear_tasks.clone();
-            sorted.sort_by_key(|b| b.0);
-            if let Some(tuple) = sorted.last() {
-                return Some(tuple.1);
+    fn update_task(&mut self, actuator: &Arc<Actuator>, new_speed: Speed, handle: i32) {
+        self.device_actions.entry(actuator.identifier().into()).and_modify(|entry| {
+            if let Some(task) = entry.tasks.iter_mut().find(|t| t.0 == handle) {
+                task.1 = new_speed;
             }
+        });
+    }
+
+    /// Blends the speeds of all tasks, also returns the direction of the dominant task
+    fn get_priority_speed(&self, actuator: &Arc<Actuator>) -> Option<(Speed, bool)> {
+        let entry = self.device_actions.get(actuator.identifier())?;
+        let (handle, speed) = entry.blend_mode.blend(&entry.tasks)?;
+        let clockwise = entry.directions.get(&handle).cloned().unwrap_or(true);
+        Some((speed, clockwise))
+    }
+
+    /// Current speed and direction of all scalar actuators that are used by any task
+    pub fn scalars(&self) -> Vec<(Arc<Actuator>, Speed, bool)> {
+        self.device_actions
+            .values()
+            .filter_map(|entry| {
+                let (speed, clockwise) = self.get_priority_speed(&entry.actuator)?;
+                Some((entry.actuator.clone(), speed, clockwise))
+            })
+            .collect()
+    }
+
+    /// Removes all tasks of the actuator, returns the handles of all tasks
+    /// that used it, including the task that moved a linear actuator last
+    pub fn release(&mut self, actuator: &Arc<Actuator>) -> Vec<i32> {
+        let mut handles = vec![];
+        if let Some(entry) = self.device_actions.get_mut(actuator.identifier()) {
+            handles.extend(entry.tasks.drain(..).map(|task| task.0));
+            entry.directions.clear();
+        }
+        if let Some(last_move) = self.positions.get(actuator.identifier()) {
+            handles.push(last_move.handle);
         }
-        None
+        handles
     }
 
     pub fn clear_all(&mut self) {
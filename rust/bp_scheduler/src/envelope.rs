@@ -0,0 +1,71 @@
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+use tokio::time::Instant;
+
+use crate::speed::Speed;
+
+/// Fade times of scalar tasks, all durations are in milliseconds
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Envelope {
+    /// Fades in from 0 when the task starts or is resumed
+    pub attack_ms: u32,
+    /// Fades out to 0 when the task ends or is stopped
+    pub release_ms: u32,
+    /// Glides from the current to the new speed on updates
+    pub glide_ms: u32,
+}
+
+impl Envelope {
+    pub fn attack(&self) -> Duration {
+        Duration::from_millis(self.attack_ms as u64)
+    }
+
+    pub fn release(&self) -> Duration {
+        Duration::from_millis(self.release_ms as u64)
+    }
+
+    pub fn glide(&self) -> Duration {
+        Duration::from_millis(self.glide_ms as u64)
+    }
+}
+
+/// Linear transition between two speeds that starts on creation
+#[derive(Debug, Clone, Copy)]
+pub struct Fade {
+    from: Speed,
+    to: Speed,
+    started: Instant,
+    duration: Duration,
+}
+
+impl Fade {
+    pub fn new(from: Speed, to: Speed, duration: Duration) -> Self {
+        Fade {
+            from,
+            to,
+            started: Instant::now(),
+            duration,
+        }
+    }
+
+    /// Fade that stays at 'speed'
+    pub fn constant(speed: Speed) -> Self {
+        Fade::new(speed, speed, Duration::ZERO)
+    }
+
+    /// Speed at the current point in time
+    pub fn speed(&self) -> Speed {
+        if self.is_done() {
+            return self.to;
+        }
+        let progress = self.started.elapsed().as_secs_f64() / self.duration.as_secs_f64();
+        let from = self.from.value as f64;
+        let to = self.to.value as f64;
+        Speed::new((from + (to - from) * progress).round() as i64)
+    }
+
+    pub fn is_done(&self) -> bool {
+        self.started.elapsed() >= self.duration
+    }
+}
@@ -0,0 +1,259 @@
+use std::{
+    f64::consts::PI,
+    fmt::{self, Display},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use funscript::{FSPoint, FScript};
+use tracing::error;
+
+/// Pattern names with this prefix are generated instead of being read from a file,
+/// i.e. `gen:sine:period=2,min=20,max=80`
+pub static GENERATOR_PREFIX: &str = "gen:";
+
+/// Distance between two generated points of continuous waves
+static SAMPLE_MS: i32 = 50;
+
+/// Longest accepted period, step or length, keeps the generated points
+/// within the `i32` time axis of funscripts and limits their number
+static MAX_DURATION: Duration = Duration::from_secs(600);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GeneratorKind {
+    /// Smooth wave from min to max and back
+    Sine,
+    /// Jumps between max and min, `duty` is the share of the period spent at max
+    Pulse,
+    /// Ramps from min to max and drops back to min
+    Sawtooth,
+    /// Two beats per period, the period is defined by `bpm`
+    Heartbeat,
+    /// Random changes of at most `variation` every `step`, repeats after `length`
+    RandomWalk,
+    /// Sine wave with an amplitude that grows from min to max over `length`
+    Build,
+}
+
+impl Display for GeneratorKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            GeneratorKind::Sine => write!(f, "sine"),
+            GeneratorKind::Pulse => write!(f, "pulse"),
+            GeneratorKind::Sawtooth => write!(f, "sawtooth"),
+            GeneratorKind::Heartbeat => write!(f, "heartbeat"),
+            GeneratorKind::RandomWalk => write!(f, "random"),
+            GeneratorKind::Build => write!(f, "build"),
+        }
+    }
+}
+
+/// Procedural pattern that produces a funscript from its parameters
+#[derive(Debug, Clone, PartialEq)]
+pub struct PatternGenerator {
+    pub kind: GeneratorKind,
+    pub period: Duration,
+    /// Lowest generated position (0-100)
+    pub min: i32,
+    /// Highest generated position (0-100)
+    pub max: i32,
+    pub duty: f64,
+    pub step: Duration,
+    pub variation: i32,
+    pub length: Duration,
+    /// Seed of the random walk, a time based seed is used if None
+    pub seed: Option<u64>,
+}
+
+impl PatternGenerator {
+    pub fn new(kind: GeneratorKind) -> Self {
+        PatternGenerator {
+            kind,
+            period: Duration::from_secs(1),
+            min: 0,
+            max: 100,
+            duty: 0.5,
+            step: Duration::from_millis(250),
+            variation: 20,
+            length: match kind {
+                GeneratorKind::Build => Duration::from_secs(30),
+                _ => Duration::from_secs(10),
+            },
+            seed: None,
+        }
+    }
+
+    /// Returns true if 'pattern_name' refers to a generator instead of a file
+    pub fn is_generator(pattern_name: &str) -> bool {
+        pattern_name
+            .trim()
+            .to_lowercase()
+            .starts_with(GENERATOR_PREFIX)
+    }
+
+    /// Reads a reserved pattern name `gen:<kind>[:<key>=<value>,...]`, where kind is one of
+    /// `sine`, `pulse`, `sawtooth`, `heartbeat`, `random` or `build`. Parameters can also be
+    /// separated by `;`, so that they can be used within comma separated lists
+    ///
+    /// Known keys are `period`, `step` and `length` in seconds (at most 600), `min` and
+    /// `max` in percent, `duty` (0-1), `bpm`, `variation` and `seed`
+    pub fn parse(pattern_name: &str) -> Option<PatternGenerator> {
+        let lower = pattern_name.trim().to_lowercase();
+        let definition = lower.strip_prefix(GENERATOR_PREFIX)?;
+        let (kind, params) = definition.split_once(':').unwrap_or((definition, ""));
+        let kind = match kind.trim() {
+            "sine" => GeneratorKind::Sine,
+            "pulse" | "square" => GeneratorKind::Pulse,
+            "sawtooth" => GeneratorKind::Sawtooth,
+            "heartbeat" => GeneratorKind::Heartbeat,
+            "random" => GeneratorKind::RandomWalk,
+            "build" => GeneratorKind::Build,
+            unknown => {
+                error!("unknown generator {:?}", unknown);
+                return None;
+            }
+        };
+        let mut generator = PatternGenerator::new(kind);
+        for param in params.split([',', ';']).filter(|x| !x.trim().is_empty()) {
+            let valid = match param.split_once('=') {
+                Some((key, value)) => generator.set(key.trim(), value.trim()),
+                None => false,
+            };
+            if !valid {
+                error!("invalid generator parameter {:?}", param);
+                return None;
+            }
+        }
+        if generator.min > generator.max {
+            std::mem::swap(&mut generator.min, &mut generator.max);
+        }
+        Some(generator)
+    }
+
+    fn set(&mut self, key: &str, value: &str) -> bool {
+        match key {
+            "period" => read_secs(value).map(|x| self.period = x).is_some(),
+            "step" => read_secs(value).map(|x| self.step = x).is_some(),
+            "length" => read_secs(value).map(|x| self.length = x).is_some(),
+            "bpm" => value
+                .parse::<f64>()
+                .ok()
+                .and_then(|x| to_duration(60.0 / x))
+                .map(|x| self.period = x)
+                .is_some(),
+            "min" => value.parse().map(|x: i32| self.min = x.clamp(0, 100)).is_ok(),
+            "max" => value.parse().map(|x: i32| self.max = x.clamp(0, 100)).is_ok(),
+            "duty" => value.parse().map(|x: f64| self.duty = x.clamp(0.0, 1.0)).is_ok(),
+            "variation" => value.parse().map(|x: i32| self.variation = x.abs()).is_ok(),
+            "seed" => value.parse().map(|x| self.seed = Some(x)).is_ok(),
+            _ => false,
+        }
+    }
+
+    /// Generates a single cycle of the pattern, players repeat it until their duration ends
+    pub fn generate(&self) -> FScript {
+        let period = (self.period.as_millis() as i32).max(SAMPLE_MS);
+        let length = (self.length.as_millis() as i32).max(SAMPLE_MS);
+        let mut points = vec![];
+        match self.kind {
+            GeneratorKind::Sine => {
+                for at in samples(period) {
+                    points.push((at, 0.5 - 0.5 * (2.0 * PI * at as f64 / period as f64).cos()));
+                }
+            }
+            GeneratorKind::Pulse => {
+                points.push((0, 1.0));
+                points.push(((period as f64 * self.duty) as i32, 0.0));
+                points.push((period, 1.0));
+            }
+            GeneratorKind::Sawtooth => {
+                for at in samples(period).filter(|at| *at < period) {
+                    points.push((at, at as f64 / period as f64));
+                }
+                points.push((period, 0.0));
+            }
+            GeneratorKind::Heartbeat => {
+                let beat = |share: f64| (period as f64 * share) as i32;
+                points.push((0, 1.0));
+                points.push((beat(0.1), 0.0));
+                points.push((beat(0.25), 0.7));
+                points.push((beat(0.35), 0.0));
+                points.push((period, 0.0));
+            }
+            GeneratorKind::RandomWalk => {
+                let mut random = XorShift::new(self.seed.unwrap_or_else(time_seed));
+                let step = (self.step.as_millis() as i32).max(SAMPLE_MS);
+                let range = (self.max - self.min).max(1) as f64;
+                let variation = self.variation as f64 / range;
+                let mut value = 0.5;
+                let mut at = 0;
+                while at < length {
+                    points.push((at, value));
+                    value = (value + (random.next_f64() * 2.0 - 1.0) * variation).clamp(0.0, 1.0);
+                    at += step;
+                }
+                points.push((length, 0.5));
+            }
+            GeneratorKind::Build => {
+                for at in samples(length) {
+                    let wave = 0.5 - 0.5 * (2.0 * PI * at as f64 / period as f64).cos();
+                    points.push((at, wave * at as f64 / length as f64));
+                }
+            }
+        }
+        let mut fscript = FScript::default();
+        for (at, value) in points {
+            let pos = self.min as f64 + (self.max - self.min) as f64 * value;
+            fscript.actions.push(FSPoint {
+                pos: pos.round() as i32,
+                at,
+            });
+        }
+        fscript
+    }
+}
+
+/// Points in time from 0 to 'end' (inclusive), spaced by the sample distance
+fn samples(end: i32) -> impl Iterator<Item = i32> {
+    (0..=end / SAMPLE_MS)
+        .map(|i| i * SAMPLE_MS)
+        .chain((end % SAMPLE_MS != 0).then_some(end))
+}
+
+fn read_secs(value: &str) -> Option<Duration> {
+    value.parse::<f64>().ok().and_then(to_duration)
+}
+
+/// None for values that are not positive, not finite or exceed the maximum duration
+fn to_duration(secs: f64) -> Option<Duration> {
+    if !secs.is_finite() || secs <= 0.0 {
+        return None;
+    }
+    Duration::try_from_secs_f64(secs)
+        .ok()
+        .filter(|x| *x <= MAX_DURATION)
+}
+
+fn time_seed() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|x| x.as_nanos() as u64)
+        .unwrap_or(1)
+}
+
+/// Small pseudo random generator, good enough for pattern variations
+struct XorShift {
+    state: u64,
+}
+
+impl XorShift {
+    fn new(seed: u64) -> Self {
+        XorShift { state: seed.max(1) }
+    }
+
+    fn next_f64(&mut self) -> f64 {
+        self.state ^= self.state << 13;
+        self.state ^= self.state >> 7;
+        self.state ^= self.state << 17;
+        (self.state >> 11) as f64 / (1u64 << 53) as f64
+    }
+}
@@ -0,0 +1,38 @@
+use std::fmt::{self, Display};
+
+use serde::{Deserialize, Serialize};
+
+/// Defines which values are sent between two points of a scalar funscript
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Interpolation {
+    /// Holds the value of each point until the next point
+    #[default]
+    Step,
+    /// Ramps linearly from one point to the next
+    Linear,
+    /// Ramps from one point to the next, accelerating at the start and slowing down at the end
+    Eased,
+}
+
+impl Interpolation {
+    /// Returns the value between `from` and `to` at `progress` (0.0 to 1.0)
+    pub fn interpolate(&self, from: f64, to: f64, progress: f64) -> f64 {
+        let progress = progress.clamp(0.0, 1.0);
+        let factor = match self {
+            Interpolation::Step => 0.0,
+            Interpolation::Linear => progress,
+            Interpolation::Eased => progress * progress * (3.0 - 2.0 * progress),
+        };
+        from + (to - from) * factor
+    }
+}
+
+impl Display for Interpolation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Interpolation::Step => write!(f, "step"),
+            Interpolation::Linear => write!(f, "linear"),
+            Interpolation::Eased => write!(f, "eased"),
+        }
+    }
+}
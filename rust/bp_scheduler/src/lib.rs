@@ -1,22 +1,36 @@
 use actuator::Actuator;
+use envelope::Envelope;
 use buttplug::client::ButtplugClientError;
-use player::PatternPlayer;
-use settings::ActuatorSettings;
+use interpolation::Interpolation;
+use player::{ActuatorError, ActuatorRecovery, PatternPlayer};
+use priority::Priority;
+use safety::SafetyViolation;
+use settings::{ActuatorSettings, RetrySettings, SafetyLimits};
 use speed::Speed;
 use std::collections::HashMap;
 use worker::{ButtplugWorker, WorkerTask};
 
 use std::{sync::Arc, time::Duration};
 use tokio::{
-    sync::mpsc::{unbounded_channel, UnboundedSender},
+    sync::{
+        broadcast,
+        mpsc::{unbounded_channel, UnboundedSender},
+        watch,
+    },
     time::sleep,
 };
 use tokio_util::sync::CancellationToken;
-use tracing::{debug, error};
+use tracing::{debug, error, trace};
 
 mod access;
 pub mod actuator;
+pub mod envelope;
+pub mod generator;
+pub mod interpolation;
 pub mod player;
+pub mod priority;
+mod queue;
+pub mod safety;
 pub mod speed;
 pub mod settings;
 mod worker;
@@ -27,30 +41,66 @@ pub struct ButtplugScheduler {
     settings: PlayerSettings,
     control_handles: HashMap<i32, ControlHandle>,
     last_handle: i32,
+    master_intensity: watch::Sender<Speed>,
+    violation_sender: broadcast::Sender<SafetyViolation>,
+    recovery_sender: broadcast::Sender<ActuatorRecovery>,
 }
 
 #[derive(Debug)]
 struct ControlHandle {
     cancellation_token: CancellationToken,
+    abort_token: CancellationToken,
     update_sender: UnboundedSender<Speed>,
+    direction_sender: watch::Sender<bool>,
+    pause_sender: watch::Sender<bool>,
+    release_sender: watch::Sender<Option<Duration>>,
+    keep_alive_sender: watch::Sender<()>,
 }
 
 #[derive(Debug)]
 pub struct PlayerSettings {
     pub scalar_resolution_ms: i32,
+    /// Commands to the same device are coalesced and sent at most once per interval
+    pub min_command_interval_ms: u32,
+    /// Maximum velocity of the first move of a linear task, and of the first move after
+    /// another task used the actuator, in percent of the full stroke per second (0 = unlimited)
+    pub handover_velocity: u32,
+    /// Tasks that exceed these limits are stopped by the worker
+    pub safety: SafetyLimits,
+    /// Device commands that fail with a transient error are sent again
+    pub retry: RetrySettings,
 }
 
 impl ButtplugScheduler {
     pub fn create(settings: PlayerSettings) -> (ButtplugScheduler, ButtplugWorker) {
         let (worker_task_sender, task_receiver) = unbounded_channel::<WorkerTask>();
+        let min_command_interval = Duration::from_millis(settings.min_command_interval_ms.into());
+        let handover_velocity = settings.handover_velocity;
+        let safety_limits = settings.safety;
+        let retry = settings.retry;
+        let (master_intensity, master_intensity_receiver) = watch::channel(Speed::max());
+        let (violation_sender, _) = broadcast::channel(64);
+        let (recovery_sender, _) = broadcast::channel(64);
         (
             ButtplugScheduler {
                 worker_task_sender,
                 settings,
                 control_handles: HashMap::new(),
                 last_handle: 0,
+                master_intensity,
+                violation_sender: violation_sender.clone(),
+                recovery_sender: recovery_sender.clone(),
+            },
+            ButtplugWorker {
+                task_receiver,
+                min_command_interval,
+                handover_velocity,
+                master_intensity: master_intensity_receiver,
+                safety_limits,
+                violation_sender,
+                retry,
+                recovery_sender,
             },
-            ButtplugWorker { task_receiver },
         )
     }
 
@@ -65,14 +115,22 @@ impl ButtplugScheduler {
             .retain(|_, handle| !handle.cancellation_token.is_cancelled());
     }
 
-    pub fn stop_task(&mut self, handle: i32) {
+    /// Returns true if the task was neither stopped nor finished
+    pub fn is_running(&self, handle: i32) -> bool {
+        self.control_handles
+            .get(&handle)
+            .is_some_and(|x| !x.cancellation_token.is_cancelled())
+    }
+
+    /// Stops a task, scalar tasks fade out over 'release' or the release time of their envelope
+    pub fn stop_task(&mut self, handle: i32, release: Option<Duration>) {
         if self.control_handles.contains_key(&handle) {
-            debug!("stop handle {}", handle);
-            self.control_handles
-                .remove(&handle)
-                .unwrap()
-                .cancellation_token
-                .cancel();
+            debug!("stop handle {} release={:?}", handle, release);
+            let control_handle = self.control_handles.remove(&handle).unwrap();
+            if release.is_some() {
+                control_handle.release_sender.send_replace(release);
+            }
+            control_handle.cancellation_token.cancel();
         } else {
             error!("Unknown handle {}", handle);
         }
@@ -81,12 +139,54 @@ impl ButtplugScheduler {
     pub fn update_task(&mut self, handle: i32, speed: Speed) -> bool {
         if self.control_handles.contains_key(&handle) {
             debug!("updating handle {}", handle);
-            let _ = self
-                .control_handles
-                .get(&handle)
-                .unwrap()
-                .update_sender
-                .send(speed);
+            let control_handle = self.control_handles.get(&handle).unwrap();
+            let _ = control_handle.update_sender.send(speed);
+            control_handle.keep_alive_sender.send_replace(());
+            true
+        } else {
+            error!("Unknown handle {}", handle);
+            false
+        }
+    }
+
+    /// Changes the direction of a running rotation task
+    pub fn update_task_direction(&mut self, handle: i32, clockwise: bool) -> bool {
+        if let Some(control_handle) = self.control_handles.get(&handle) {
+            debug!("updating direction of handle {}", handle);
+            let _ = control_handle.direction_sender.send(clockwise);
+            true
+        } else {
+            error!("Unknown handle {}", handle);
+            false
+        }
+    }
+
+    /// Pauses a running task, the actuators are released until it is resumed
+    pub fn pause_task(&mut self, handle: i32) -> bool {
+        self.set_task_paused(handle, true)
+    }
+
+    /// Resumes a paused task with its remaining duration and pattern position
+    pub fn resume_task(&mut self, handle: i32) -> bool {
+        self.set_task_paused(handle, false)
+    }
+
+    fn set_task_paused(&mut self, handle: i32, paused: bool) -> bool {
+        if let Some(control_handle) = self.control_handles.get(&handle) {
+            debug!("setting handle {} paused={}", handle, paused);
+            let _ = control_handle.pause_sender.send(paused);
+            true
+        } else {
+            error!("Unknown handle {}", handle);
+            false
+        }
+    }
+
+    /// Restarts the keep-alive timeout of a task, updates also count as keep-alive
+    pub fn keep_alive(&mut self, handle: i32) -> bool {
+        if let Some(control_handle) = self.control_handles.get(&handle) {
+            trace!("keep alive handle {}", handle);
+            control_handle.keep_alive_sender.send_replace(());
             true
         } else {
             error!("Unknown handle {}", handle);
@@ -94,6 +194,23 @@ impl ButtplugScheduler {
         }
     }
 
+    /// Scales the output of all scalar actuators and the stroke range of all
+    /// linear actuators, changes also apply to running tasks
+    pub fn set_master_intensity(&mut self, intensity: Speed) {
+        debug!("master intensity {}", intensity);
+        self.master_intensity.send_replace(intensity.bounded());
+    }
+
+    pub fn master_intensity(&self) -> Speed {
+        *self.master_intensity.borrow()
+    }
+
+    /// Receives actuators that accept commands again after their commands
+    /// failed with all retries
+    pub fn subscribe_recoveries(&self) -> broadcast::Receiver<ActuatorRecovery> {
+        self.recovery_sender.subscribe()
+    }
+
     pub fn stop_all(&mut self) {
         let queue_full_err = "Event sender full";
         self.worker_task_sender
@@ -101,11 +218,23 @@ impl ButtplugScheduler {
             .unwrap_or_else(|_| error!(queue_full_err));
         for entry in self.control_handles.drain() {
             debug!("stop-all - stopping handle {:?}", entry.0);
-            entry.1.cancellation_token.cancel();
+            entry.1.abort_token.cancel();
         }
         self.control_handles.clear();
     }
 
+    /// Aborts all tasks and stops the worker after it set all running
+    /// scalar actuators to 0, the worker thread returns afterwards
+    pub fn shutdown(&mut self) {
+        for (handle, control_handle) in self.control_handles.drain() {
+            debug!("shutdown - stopping handle {:?}", handle);
+            control_handle.abort_token.cancel();
+        }
+        self.worker_task_sender
+            .send(WorkerTask::Shutdown)
+            .unwrap_or_else(|_| error!("worker already stopped"));
+    }
+
     pub fn create_player(&mut self, actuators: Vec<Arc<Actuator>>) -> PatternPlayer {
         let empty_settings = actuators.iter().map(|_| ActuatorSettings::None).collect::<Vec<ActuatorSettings>>();
         self.create_player_with_settings(actuators, empty_settings)
@@ -113,27 +242,54 @@ impl ButtplugScheduler {
 
     pub fn create_player_with_settings(&mut self, actuators: Vec<Arc<Actuator>>, settings: Vec<ActuatorSettings>) -> PatternPlayer {
         let (update_sender, update_receiver) = unbounded_channel::<Speed>();
+        let (direction_sender, direction_receiver) = watch::channel(true);
+        let (pause_sender, pause_receiver) = watch::channel(false);
+        let (release_sender, release_receiver) = watch::channel(None);
+        let (keep_alive_sender, keep_alive_receiver) = watch::channel(());
 
-        let cancellation_token = CancellationToken::new();
+        let abort_token = CancellationToken::new();
+        let cancellation_token = abort_token.child_token();
         let handle = self.get_next_handle();
         self.control_handles.insert(
             handle,
             ControlHandle {
                 cancellation_token: cancellation_token.clone(),
+                abort_token: abort_token.clone(),
                 update_sender,
+                direction_sender,
+                pause_sender,
+                release_sender,
+                keep_alive_sender,
             },
         );
 
         let (result_sender, result_receiver) =
             unbounded_channel::<Result<(), ButtplugClientError>>();
+        let (error_sender, error_receiver) = unbounded_channel::<ActuatorError>();
         PatternPlayer {
             actuators,
             settings,
             result_sender,
             result_receiver,
+            error_sender,
+            error_receiver,
             update_receiver,
+            direction_receiver,
+            pause_receiver,
+            release_receiver,
+            keep_alive_receiver,
+            keep_alive: None,
+            expired_token: CancellationToken::new(),
+            master_intensity: self.master_intensity.subscribe(),
+            violation_sender: self.violation_sender.clone(),
+            violated_limit: watch::channel(None).0,
+            clockwise: true,
+            priority: Priority::Normal,
+            interpolation: Interpolation::Step,
+            envelope: Envelope::default(),
             handle,
             cancellation_token,
+            abort_token,
             worker_task_sender: self.worker_task_sender.clone(),
             scalar_resolution_ms: self.settings.scalar_resolution_ms,
         }
@@ -151,29 +307,42 @@ async fn cancellable_wait(duration: Duration, cancel: &CancellationToken) -> boo
     }
 }
 
+/// Waits until the task is paused or resumed, returns false if the task handle was dropped
+async fn wait_for_pause(pause: &mut watch::Receiver<bool>, paused: bool) -> bool {
+    pause.wait_for(|state| *state == paused).await.is_ok()
+}
+
 #[cfg(test)]
 mod tests {
     use crate::actuator::get_actuators;
+    use crate::envelope::Envelope;
+    use crate::generator::{GeneratorKind, PatternGenerator};
+    use crate::interpolation::Interpolation;
     use crate::player::PatternPlayer;
+    use crate::priority::{BlendMode, Priority};
+    use crate::safety::SafetyLimit;
     use crate::settings::ActuatorSettings;
     use crate::settings::LinearRange;
+    use crate::settings::RetrySettings;
+    use crate::settings::SafetyLimits;
+    use crate::settings::ScalarRange;
+    use crate::settings::ScalarScaling;
     use crate::speed::Speed;
     use bp_fakes::get_test_client;
     use bp_fakes::FakeMessage;
     use bp_fakes::*;
     use std::sync::Arc;
-    use std::thread;
-    use std::time::{Duration, Instant};
+    use std::time::Duration;
 
     use funscript::{FSPoint, FScript};
     use futures::future::join_all;
 
     use buttplug::client::ButtplugClientDevice;
-    use buttplug::core::message::ActuatorType;
+    use buttplug::core::message::{ActuatorType, ButtplugSpecV3ClientMessage};
 
     use tokio::runtime::Handle;
     use tokio::task::JoinHandle;
-    use tokio::time::timeout;
+    use tokio::time::{timeout, Instant};
 
     use super::{Actuator, ButtplugScheduler, PlayerSettings};
 
@@ -189,6 +358,10 @@ mod tests {
                 all_devices,
                 PlayerSettings {
                     scalar_resolution_ms: 1,
+                    min_command_interval_ms: 0,
+                    handover_velocity: 0,
+                    safety: SafetyLimits::default(),
+                    retry: RetrySettings::default(),
                 },
             )
         }
@@ -242,6 +415,24 @@ mod tests {
             }));
         }
 
+        fn play_scalar_arbitrated(
+            &mut self,
+            duration: Duration,
+            speed: Speed,
+            priority: Priority,
+            blend_mode: BlendMode,
+        ) {
+            let settings = ActuatorSettings::Scalar(ScalarRange {
+                blend_mode,
+                ..Default::default()
+            });
+            let mut player = self.get_player_with_settings(vec![settings]);
+            player.priority = priority;
+            self.handles.push(Handle::current().spawn(async move {
+                player.play_scalar(duration, speed).await.unwrap();
+            }));
+        }
+
         fn get_player(&mut self) -> PatternPlayer {
             self.scheduler
                 .create_player(get_actuators(self.all_devices.clone()))
@@ -256,7 +447,7 @@ mod tests {
                 .scheduler
                 .create_player(get_actuators(self.all_devices.clone()));
             player
-                .play_linear(duration, funscript)
+                .play_linear(duration, funscript, Speed::max())
                 .await
                 .unwrap();
         }
@@ -271,7 +462,7 @@ mod tests {
     }
 
     /// Linear
-    #[tokio::test]
+    #[tokio::test(start_paused = true)]
     async fn test_no_devices_does_not_block() {
         // arrange
         let client = get_test_client(vec![]).await;
@@ -300,11 +491,11 @@ mod tests {
         );
     }
 
-    #[tokio::test]
+    #[tokio::test(start_paused = true)]
     async fn test_oscillate_linear_1() {
         let (client, _) = test_oscillate(
             Speed::new(100),
-            LinearRange{ min_pos: 0.0, max_pos: 1.0, min_ms: 50, max_ms: 400, invert: false, scaling: crate::settings::LinearSpeedScaling::Linear },
+            LinearRange{ min_pos: 0.0, max_pos: 1.0, min_ms: 50, max_ms: 400, invert: false, scaling: crate::settings::LinearSpeedScaling::Linear, max_velocity: 0 },
         )
         .await;
 
@@ -314,11 +505,11 @@ mod tests {
         calls[2].assert_duration(50);
     }
 
-    #[tokio::test]
+    #[tokio::test(start_paused = true)]
     async fn test_oscillate_linear_2() {
         let (client, _) = test_oscillate(
             Speed::new(0),
-            LinearRange{ min_pos: 1.0, max_pos: 0.0, min_ms: 10, max_ms: 100, invert: false, scaling: crate::settings::LinearSpeedScaling::Linear }
+            LinearRange{ min_pos: 1.0, max_pos: 0.0, min_ms: 10, max_ms: 100, invert: false, scaling: crate::settings::LinearSpeedScaling::Linear, max_velocity: 0 }
         )
         .await;
 
@@ -328,11 +519,11 @@ mod tests {
         calls[2].assert_duration(100).assert_pos(0.0);
     }
 
-    #[tokio::test]
+    #[tokio::test(start_paused = true)]
     async fn test_oscillate_linear_3() {
         let (client, _) = test_oscillate(
             Speed::new(75),
-            LinearRange{ min_pos: 0.2, max_pos: 0.7, min_ms: 100, max_ms: 200, invert: false, scaling: crate::settings::LinearSpeedScaling::Linear }
+            LinearRange{ min_pos: 0.2, max_pos: 0.7, min_ms: 100, max_ms: 200, invert: false, scaling: crate::settings::LinearSpeedScaling::Linear, max_velocity: 0 }
         )
         .await;
 
@@ -342,11 +533,11 @@ mod tests {
         calls[2].assert_duration(125).assert_pos(0.7);
     }
 
-    #[tokio::test]
+    #[tokio::test(start_paused = true)]
     async fn test_oscillate_linear_invert() {
         let (client, _) = test_oscillate(
             Speed::new(100),
-            LinearRange{ min_pos: 0.2, max_pos: 0.7, min_ms: 50, max_ms: 50, invert: true, scaling: crate::settings::LinearSpeedScaling::Linear }
+            LinearRange{ min_pos: 0.2, max_pos: 0.7, min_ms: 50, max_ms: 50, invert: true, scaling: crate::settings::LinearSpeedScaling::Linear, max_velocity: 0 }
         )
         .await;
 
@@ -356,7 +547,7 @@ mod tests {
         calls[2].assert_pos(0.3);
     }
 
-    #[tokio::test]
+    #[tokio::test(start_paused = true)]
     async fn test_oscillate_update() {
         let client: ButtplugTestClient = get_test_client(vec![linear(1, "lin1")]).await;
         let mut test = PlayerTest::setup(&client.created_devices);
@@ -375,7 +566,8 @@ mod tests {
                         min_ms: 10, 
                         max_ms: 100, 
                         invert: true, 
-                        scaling: crate::settings::LinearSpeedScaling::Linear
+                        scaling: crate::settings::LinearSpeedScaling::Linear,
+                        max_velocity: 0
                     })
                 .await;
         });
@@ -390,6 +582,65 @@ mod tests {
         calls[2].assert_duration(100);
     }
 
+    #[tokio::test(start_paused = true)]
+    async fn test_oscillate_finishes_after_update_channel_closed() {
+        let client = get_test_client(vec![linear(1, "lin1")]).await;
+        let mut test = PlayerTest::setup(&client.created_devices);
+
+        // act
+        let start = Instant::now();
+        let player = test.get_player();
+        drop(test);
+        let result = timeout(
+            Duration::from_secs(1),
+            player.play_oscillate_linear(Duration::from_millis(250), Speed::new(100), LinearRange::max()),
+        )
+        .await;
+
+        // assert
+        assert!(result.is_ok());
+        assert_eq!(start.elapsed().as_millis(), 250);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_oscillate_independent_timing_per_actuator() {
+        let client = get_test_client(vec![linear(1, "lin1"), linear(2, "lin2")]).await;
+        let mut test = PlayerTest::setup(&client.created_devices);
+        let range = |ms| LinearRange {
+            min_pos: 0.0,
+            max_pos: 1.0,
+            min_ms: ms,
+            max_ms: 1000,
+            invert: false,
+            scaling: crate::settings::LinearSpeedScaling::Linear,
+            max_velocity: 0,
+        };
+
+        // act
+        let start = Instant::now();
+        let player = test.get_player_with_settings(vec![
+            ActuatorSettings::Linear(range(100)),
+            ActuatorSettings::Linear(range(200)),
+        ]);
+        let _ = player
+            .play_oscillate_linear(Duration::from_millis(450), Speed::new(100), LinearRange::max())
+            .await;
+
+        // assert
+        client.print_device_calls(start);
+        let calls = client.get_device_calls(1);
+        calls[0].assert_duration(100).assert_pos(1.0).assert_exact_time(0, start);
+        calls[1].assert_duration(100).assert_pos(0.0).assert_exact_time(100, start);
+        calls[2].assert_duration(100).assert_pos(1.0).assert_exact_time(200, start);
+        calls[3].assert_duration(100).assert_pos(0.0).assert_exact_time(300, start);
+        calls[4].assert_duration(100).assert_pos(1.0).assert_exact_time(400, start);
+        let calls = client.get_device_calls(2);
+        calls[0].assert_duration(200).assert_pos(1.0).assert_exact_time(0, start);
+        calls[1].assert_duration(200).assert_pos(0.0).assert_exact_time(200, start);
+        calls[2].assert_duration(200).assert_pos(1.0).assert_exact_time(400, start);
+        assert_eq!(calls.len(), 3);
+    }
+
     async fn test_oscillate(speed: Speed, range: LinearRange) -> (ButtplugTestClient, Instant) {
         let client = get_test_client(vec![linear(1, "lin1")]).await;
         let mut test = PlayerTest::setup(&client.created_devices);
@@ -406,7 +657,7 @@ mod tests {
         (client, start)
     }
 
-    #[tokio::test]
+    #[tokio::test(start_paused = true)]
     async fn test_linear_empty_pattern_finishes_and_does_not_panic() {
         let client = get_test_client(vec![linear(1, "lin1")]).await;
         let mut player = PlayerTest::setup(&client.created_devices);
@@ -424,7 +675,7 @@ mod tests {
             .await;
     }
 
-    #[tokio::test]
+    #[tokio::test(start_paused = true)]
     async fn test_linear_funscript() {
         // arrange
         let client = get_test_client(vec![linear(1, "lin1")]).await;
@@ -445,14 +696,104 @@ mod tests {
         client.get_device_calls(1)[0]
             .assert_pos(0.0)
             .assert_duration(200)
-            .assert_time(0, start);
+            .assert_exact_time(0, start);
         client.get_device_calls(1)[1]
             .assert_pos(1.0)
             .assert_duration(200)
-            .assert_time(200, start);
+            .assert_exact_time(200, start);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_linear_funscript_double_tempo() {
+        let (client, start) = test_linear_playback_rate(Speed::new_unbounded(200)).await;
+
+        let calls = client.get_device_calls(1);
+        calls[0].assert_pos(0.0).assert_duration(100).assert_exact_time(0, start);
+        calls[1].assert_pos(1.0).assert_duration(100).assert_exact_time(100, start);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_linear_funscript_half_tempo() {
+        let (client, start) = test_linear_playback_rate(Speed::new(50)).await;
+
+        let calls = client.get_device_calls(1);
+        calls[0].assert_pos(0.0).assert_duration(400).assert_exact_time(0, start);
+        calls[1].assert_pos(1.0).assert_duration(400).assert_exact_time(400, start);
+    }
+
+    async fn test_linear_playback_rate(speed: Speed) -> (ButtplugTestClient, Instant) {
+        let client = get_test_client(vec![linear(1, "lin1")]).await;
+        let mut test = PlayerTest::setup(&client.created_devices);
+
+        let mut fscript = FScript::default();
+        fscript.actions.push(FSPoint { pos: 0, at: 200 });
+        fscript.actions.push(FSPoint { pos: 100, at: 400 });
+        let duration = Duration::from_millis((400.0 / speed.as_float()) as u64 - 50);
+
+        let start = Instant::now();
+        test.get_player()
+            .play_linear(duration, fscript, speed)
+            .await
+            .unwrap();
+
+        client.print_device_calls(start);
+        (client, start)
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_linear_funscript_rate_update_keeps_target() {
+        // arrange
+        let client = get_test_client(vec![linear(1, "lin1")]).await;
+        let mut test = PlayerTest::setup(&client.created_devices);
+
+        let mut fscript = FScript::default();
+        fscript.actions.push(FSPoint { pos: 100, at: 400 });
+
+        // act
+        let start = Instant::now();
+        let player = test.get_player();
+        let handle = player.handle;
+        let join = Handle::current().spawn(async move {
+            player
+                .play_linear(Duration::from_millis(700), fscript, Speed::max())
+                .await
+                .unwrap();
+        });
+        wait_ms(200).await;
+        test.scheduler.update_task(handle, Speed::new(50));
+        let _ = join.await;
+
+        // assert
+        client.print_device_calls(start);
+        let calls = client.get_device_calls(1);
+        calls[0].assert_pos(1.0).assert_duration(400).assert_exact_time(0, start);
+        calls[1].assert_pos(1.0).assert_duration(400).assert_exact_time(200, start);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_linear_stopped_rate_ends_when_update_channel_closed() {
+        // arrange
+        let client = get_test_client(vec![linear(1, "lin1")]).await;
+        let mut test = PlayerTest::setup(&client.created_devices);
+
+        let mut fscript = FScript::default();
+        fscript.actions.push(FSPoint { pos: 100, at: 400 });
+
+        // act
+        let player = test.get_player();
+        drop(test);
+        let result = timeout(
+            Duration::from_secs(1),
+            player.play_linear(Duration::from_millis(700), fscript, Speed::new(0)),
+        )
+        .await;
+
+        // assert
+        assert!(result.is_ok());
+        assert!(client.get_device_calls(1).is_empty());
     }
 
-    #[tokio::test]
+    #[tokio::test(start_paused = true)]
     async fn test_linear_timing_remains_synced_with_clock() {
         // arrange
         let n = 40;
@@ -474,7 +815,7 @@ mod tests {
         check_timing(client.get_device_calls(1), n, start);
     }
 
-    #[tokio::test]
+    #[tokio::test(start_paused = true)]
     async fn test_linear_repeats_until_duration_ends() {
         // arrange
         let client = get_test_client(vec![linear(1, "lin1")]).await;
@@ -493,13 +834,13 @@ mod tests {
         client.print_device_calls(start);
 
         let calls = client.get_device_calls(1);
-        calls[0].assert_pos(1.0).assert_time(0, start);
-        calls[1].assert_pos(0.0).assert_time(200, start);
-        calls[2].assert_pos(1.0).assert_time(400, start);
-        calls[3].assert_pos(0.0).assert_time(600, start);
+        calls[0].assert_pos(1.0).assert_exact_time(0, start);
+        calls[1].assert_pos(0.0).assert_exact_time(200, start);
+        calls[2].assert_pos(1.0).assert_exact_time(400, start);
+        calls[3].assert_pos(0.0).assert_exact_time(600, start);
     }
 
-    #[tokio::test]
+    #[tokio::test(start_paused = true)]
     async fn test_linear_cancels_after_duration() {
         // arrange
         let client = get_test_client(vec![linear(1, "lin1")]).await;
@@ -525,8 +866,55 @@ mod tests {
         );
     }
 
+    #[tokio::test(start_paused = true)]
+    async fn test_linear_max_velocity_clips_fast_moves() {
+        // arrange
+        let client = get_test_client(vec![linear(1, "lin1")]).await;
+        let mut test = PlayerTest::setup(&client.created_devices);
+
+        let mut fscript = FScript::default();
+        fscript.actions.push(FSPoint { pos: 0, at: 200 });
+        fscript.actions.push(FSPoint { pos: 100, at: 400 });
+
+        // act
+        let start = Instant::now();
+        let player = test.get_player_with_settings(vec![ActuatorSettings::Linear(LinearRange {
+            max_velocity: 100,
+            ..LinearRange::max()
+        })]);
+        player
+            .play_linear(Duration::from_millis(400), fscript, Speed::max())
+            .await
+            .unwrap();
+
+        // assert
+        client.print_device_calls(start);
+        let calls = client.get_device_calls(1);
+        calls[0].assert_pos(0.0).assert_duration(200).assert_exact_time(0, start);
+        calls[1].assert_pos_near(0.2, 0.01).assert_duration(200).assert_exact_time(200, start);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_oscillate_max_velocity_slows_down_strokes() {
+        let (client, start) = test_oscillate(
+            Speed::new(100),
+            LinearRange {
+                min_ms: 100,
+                max_ms: 180,
+                max_velocity: 500,
+                ..LinearRange::max()
+            },
+        )
+        .await;
+
+        let calls = client.get_device_calls(1);
+        calls[0].assert_pos(1.0).assert_duration(200).assert_exact_time(0, start);
+        calls[1].assert_pos(0.0).assert_duration(200).assert_exact_time(200, start);
+        assert_eq!(calls.len(), 3);
+    }
+
     /// Scalar
-    #[tokio::test]
+    #[tokio::test(start_paused = true)]
     async fn test_scalar_empty_pattern_finishes_and_does_not_panic() {
         // arrange
         let client = get_test_client(vec![scalar(1, "vib1", ActuatorType::Vibrate)]).await;
@@ -547,7 +935,7 @@ mod tests {
             .await;
     }
 
-    #[tokio::test]
+    #[tokio::test(start_paused = true)]
     async fn test_scalar_pattern_actuator_selection() {
         // arrange
         let client = get_test_client(vec![scalars(1, "vib1", ActuatorType::Vibrate, 2)]).await;
@@ -584,13 +972,15 @@ mod tests {
         // assert
         client.print_device_calls(start);
         let calls = client.get_device_calls(1);
+        // the last points are immediately replaced by the restarted loop
         calls[0].assert_strengths(vec![(1, 0.1)]);
-        calls[1].assert_strengths(vec![(1, 0.2)]);
-        calls[4].assert_strengths(vec![(0, 0.3)]);
-        calls[5].assert_strengths(vec![(0, 0.4)]);
+        calls[1].assert_strengths(vec![(1, 0.0)]);
+        calls[2].assert_strengths(vec![(0, 0.3)]);
+        calls[3].assert_strengths(vec![(0, 0.0)]);
+        assert_eq!(calls.len(), 4);
     }
 
-    #[tokio::test]
+    #[tokio::test(start_paused = true)]
     async fn test_scalar_pattern_repeats_until_duration_ends() {
         // arrange
         let client = get_test_client(vec![scalar(1, "vib1", ActuatorType::Vibrate)]).await;
@@ -610,15 +1000,15 @@ mod tests {
         // assert
         client.print_device_calls(start);
         let calls = client.get_device_calls(1);
+        // the last point is immediately replaced by the restarted loop
         calls[0].assert_strenth(1.0);
         calls[1].assert_strenth(0.5);
-        calls[2].assert_strenth(0.7);
-        calls[3].assert_strenth(1.0);
-        calls[4].assert_strenth(0.0).assert_time(125, start);
-        assert_eq!(calls.len(), 5)
+        calls[2].assert_strenth(1.0).assert_exact_time(100, start);
+        calls[3].assert_strenth(0.0).assert_exact_time(125, start);
+        assert_eq!(calls.len(), 4)
     }
 
-    #[tokio::test]
+    #[tokio::test(start_paused = true)]
     async fn test_scalar_timing_remains_synced_with_clock() {
         // arrange
         let n = 40;
@@ -637,7 +1027,7 @@ mod tests {
         check_timing(client.get_device_calls(1), n, start);
     }
 
-    #[tokio::test]
+    #[tokio::test(start_paused = true)]
     async fn test_scalar_points_below_min_resolution() {
         // arrange
         let client = get_test_client(vec![scalar(1, "vib1", ActuatorType::Vibrate)]).await;
@@ -645,6 +1035,10 @@ mod tests {
             &client.created_devices,
             PlayerSettings {
                 scalar_resolution_ms: 100,
+                min_command_interval_ms: 0,
+                handover_velocity: 0,
+                safety: SafetyLimits::default(),
+                retry: RetrySettings::default(),
             },
         );
 
@@ -663,11 +1057,67 @@ mod tests {
         // assert
         client.print_device_calls(start);
         let calls = client.get_device_calls(1);
-        calls[0].assert_strenth(0.42).assert_time(0, start);
-        calls[1].assert_strenth(0.42).assert_time(100, start);
+        calls[0].assert_strenth(0.42).assert_exact_time(0, start);
+        // the 42% at 100ms equals the last sent speed and is skipped by the worker
+        calls[1].assert_strenth(0.0).assert_exact_time(150, start);
+        assert_eq!(calls.len(), 2);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_scalar_pattern_linear_interpolation() {
+        let (client, start) = test_interpolation(Interpolation::Linear).await;
+
+        let calls = client.get_device_calls(1);
+        calls[0].assert_strenth(0.0).assert_exact_time(0, start);
+        calls[1].assert_strenth(0.25).assert_exact_time(100, start);
+        calls[2].assert_strenth(0.5).assert_exact_time(200, start);
+        calls[3].assert_strenth(0.75).assert_exact_time(300, start);
+        calls[4].assert_strenth(0.0).assert_exact_time(350, start);
+        assert_eq!(calls.len(), 5);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_scalar_pattern_eased_interpolation() {
+        let (client, start) = test_interpolation(Interpolation::Eased).await;
+
+        let calls = client.get_device_calls(1);
+        calls[0].assert_strenth(0.0).assert_exact_time(0, start);
+        calls[1].assert_strenth(0.16).assert_exact_time(100, start);
+        calls[2].assert_strenth(0.5).assert_exact_time(200, start);
+        calls[3].assert_strenth(0.84).assert_exact_time(300, start);
+        assert_eq!(calls.len(), 5);
+    }
+
+    async fn test_interpolation(interpolation: Interpolation) -> (ButtplugTestClient, Instant) {
+        let client = get_test_client(vec![scalar(1, "vib1", ActuatorType::Vibrate)]).await;
+        let mut test = PlayerTest::setup_with_settings(
+            &client.created_devices,
+            PlayerSettings {
+                scalar_resolution_ms: 100,
+                min_command_interval_ms: 0,
+                handover_velocity: 0,
+                safety: SafetyLimits::default(),
+                retry: RetrySettings::default(),
+            },
+        );
+
+        let mut fs = FScript::default();
+        fs.actions.push(FSPoint { pos: 0, at: 0 });
+        fs.actions.push(FSPoint { pos: 100, at: 400 });
+
+        let start = Instant::now();
+        let mut player = test.get_player();
+        player.interpolation = interpolation;
+        player
+            .play_scalar_pattern(Duration::from_millis(350), fs, Speed::max())
+            .await
+            .unwrap();
+
+        client.print_device_calls(start);
+        (client, start)
     }
 
-    #[tokio::test]
+    #[tokio::test(start_paused = true)]
     async fn test_scalar_pattern_control() {
         // arrange
         let client = get_test_client(vec![scalar(1, "vib1", ActuatorType::Vibrate)]).await;
@@ -692,7 +1142,7 @@ mod tests {
         calls[2].assert_strenth(0.0);
     }
 
-    #[tokio::test]
+    #[tokio::test(start_paused = true)]
     async fn test_scalar_constant_control() {
         // arrange
         let client = get_test_client(vec![scalar(1, "vib1", ActuatorType::Vibrate)]).await;
@@ -710,19 +1160,19 @@ mod tests {
         client.print_device_calls(start);
         client.get_device_calls(1)[0]
             .assert_strenth(1.0)
-            .assert_time(0, start);
+            .assert_exact_time(0, start);
         client.get_device_calls(1)[1]
             .assert_strenth(0.5)
-            .assert_time(100, start);
+            .assert_exact_time(100, start);
         client.get_device_calls(1)[2]
             .assert_strenth(0.1)
-            .assert_time(200, start);
+            .assert_exact_time(200, start);
         client.get_device_calls(1)[3]
             .assert_strenth(0.0)
-            .assert_time(300, start);
+            .assert_exact_time(300, start);
     }
 
-    #[tokio::test]
+    #[tokio::test(start_paused = true)]
     async fn test_clean_finished_tasks() {
         // arrange
         let start = Instant::now();
@@ -745,7 +1195,7 @@ mod tests {
 
     // Concurrency Tests
 
-    #[tokio::test]
+    #[tokio::test(start_paused = true)]
     async fn test_concurrent_linear_access_2_threads() {
         // call1  |111111111111111111111-->|
         // call2         |2222->|
@@ -772,7 +1222,7 @@ mod tests {
         assert_eq!(client.call_registry.get_device(1).len(), 4);
     }
 
-    #[tokio::test]
+    #[tokio::test(start_paused = true)]
     async fn test_concurrent_linear_access_3_threads() {
         // call1  |111111111111111111111111111-->|
         // call2       |22222222222222->|
@@ -806,7 +1256,7 @@ mod tests {
         assert_eq!(client.call_registry.get_device(1).len(), 6);
     }
 
-    #[tokio::test]
+    #[tokio::test(start_paused = true)]
     async fn test_concurrent_linear_access_3_threads_2() {
         // call1  |111111111111111111111111111-->|
         // call2       |22222222222->|
@@ -827,7 +1277,7 @@ mod tests {
 
         player.play_scalar(Duration::from_secs(1), Speed::new(80), None);
         player.await_last().await;
-        thread::sleep(Duration::from_secs(2));
+        wait_ms(2000).await;
         player.await_all().await;
 
         // assert
@@ -835,13 +1285,12 @@ mod tests {
         client.get_device_calls(1)[0].assert_strenth(0.2);
         client.get_device_calls(1)[1].assert_strenth(0.4);
         client.get_device_calls(1)[2].assert_strenth(0.8);
-        client.get_device_calls(1)[3].assert_strenth(0.8);
-        client.get_device_calls(1)[4].assert_strenth(0.2);
-        client.get_device_calls(1)[5].assert_strenth(0.0);
-        assert_eq!(client.call_registry.get_device(1).len(), 6);
+        client.get_device_calls(1)[3].assert_strenth(0.2);
+        client.get_device_calls(1)[4].assert_strenth(0.0);
+        assert_eq!(client.call_registry.get_device(1).len(), 5);
     }
 
-    #[tokio::test]
+    #[tokio::test(start_paused = true)]
     async fn test_concurrency_linear_and_pattern() {
         // lin1   |11111111111111111-->|
         // pat1       |23452345234523452345234-->|
@@ -872,7 +1321,45 @@ mod tests {
         assert!(client.call_registry.get_device(1).len() > 3);
     }
 
-    #[tokio::test]
+    #[tokio::test(start_paused = true)]
+    async fn test_concurrency_pattern_hands_back_to_constant() {
+        // lin1   |22222222222222222222222-->|
+        // pat1       |567567->|
+        // result |2222567567222222222222222-->|
+
+        // arrange
+        let client = get_test_client(vec![scalar(1, "vib1", ActuatorType::Vibrate)]).await;
+        let mut player = PlayerTest::setup(&client.created_devices);
+
+        // act
+        let mut fscript = FScript::default();
+        for i in 0..3 {
+            fscript.actions.push(FSPoint {
+                pos: 50 + 10 * i,
+                at: 50 * i,
+            });
+        }
+
+        let start = Instant::now();
+        player.play_scalar(Duration::from_millis(400), Speed::new(20), None);
+        wait_ms(50).await;
+        player
+            .play_scalar_pattern(Duration::from_millis(150), fscript, Speed::max(), None)
+            .await;
+        player.await_all().await;
+
+        // assert
+        client.print_device_calls(start);
+        let calls = client.get_device_calls(1);
+        calls[0].assert_strenth(0.2);
+        calls[1].assert_strenth(0.5).assert_exact_time(50, start);
+        calls[2].assert_strenth(0.6);
+        calls[3].assert_strenth(0.5);
+        calls[calls.len() - 2].assert_strenth(0.2).assert_exact_time(200, start);
+        calls[calls.len() - 1].assert_strenth(0.0).assert_exact_time(400, start);
+    }
+
+    #[tokio::test(start_paused = true)]
     async fn test_concurrency_two_devices_simulatenously_both_are_started_and_stopped() {
         let client = get_test_client(vec![
             scalar(1, "vib1", ActuatorType::Vibrate),
@@ -904,39 +1391,1501 @@ mod tests {
         client.get_device_calls(2)[1].assert_strenth(0.0);
     }
 
-    async fn wait_ms(ms: u64) {
-        tokio::time::sleep(Duration::from_millis(ms)).await;
-    }
+    #[tokio::test(start_paused = true)]
+    async fn test_blend_additive_background_and_normal() {
+        // call1  |111111111111111111111-->|
+        // call2         |2222->|
+        // result |1111111333333111111111->|
 
-    fn get_duration_ms(fs: &FScript) -> Duration {
-        Duration::from_millis(fs.actions.last().unwrap().at as u64)
-    }
+        // arrange
+        let client = get_test_client(vec![scalar(1, "vib1", ActuatorType::Vibrate)]).await;
+        let mut player = PlayerTest::setup(&client.created_devices);
 
-    fn check_timing(device_calls: Vec<FakeMessage>, n: usize, start: Instant) {
-        for i in 0..n - 1 {
-            device_calls[i].assert_time((i * 100) as i32, start);
-        }
-    }
+        // act
+        let start = Instant::now();
+        player.play_scalar_arbitrated(
+            Duration::from_millis(300),
+            Speed::new(30),
+            Priority::Background,
+            BlendMode::Additive,
+        );
+        wait_ms(100).await;
+        player.play_scalar_arbitrated(
+            Duration::from_millis(100),
+            Speed::new(50),
+            Priority::Normal,
+            BlendMode::Additive,
+        );
+        player.await_all().await;
 
-    fn get_repeated_pattern(n: usize) -> FScript {
-        let mut fscript = FScript::default();
-        for i in 0..n {
-            fscript.actions.push(FSPoint {
-                pos: (i % 100) as i32,
-                at: (i * 100) as i32,
-            });
-        }
-        fscript
+        // assert
+        client.print_device_calls(start);
+        client.get_device_calls(1)[0].assert_strenth(0.3);
+        client.get_device_calls(1)[1].assert_strenth(0.8);
+        client.get_device_calls(1)[2].assert_strenth(0.3);
+        client.get_device_calls(1)[3].assert_strenth(0.0);
+        assert_eq!(client.call_registry.get_device(1).len(), 4);
     }
 
-    // Utils
+    #[tokio::test(start_paused = true)]
+    async fn test_blend_additive_is_clamped() {
+        // arrange
+        let client = get_test_client(vec![scalar(1, "vib1", ActuatorType::Vibrate)]).await;
+        let mut player = PlayerTest::setup(&client.created_devices);
 
-    #[test]
-    fn speed_correct_conversion() {
-        assert_eq!(Speed::new(-1000).as_float(), 0.0);
-        assert_eq!(Speed::new(0).as_float(), 0.0);
-        assert_eq!(Speed::new(9).as_float(), 0.09);
+        // act
+        let start = Instant::now();
+        for _ in 0..2 {
+            player.play_scalar_arbitrated(
+                Duration::from_millis(200),
+                Speed::new(70),
+                Priority::Normal,
+                BlendMode::Additive,
+            );
+            wait_ms(50).await;
+        }
+        player.await_all().await;
+
+        // assert
+        client.print_device_calls(start);
+        client.get_device_calls(1)[0].assert_strenth(0.7);
+        client.get_device_calls(1)[1].assert_strenth(1.0);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_blend_max_keeps_fastest() {
+        // call1  |888888888888888888888-->|
+        // call2         |2222->|
+        // result |888888888888888888888-->|
+
+        // arrange
+        let client = get_test_client(vec![scalar(1, "vib1", ActuatorType::Vibrate)]).await;
+        let mut player = PlayerTest::setup(&client.created_devices);
+
+        // act
+        let start = Instant::now();
+        player.play_scalar_arbitrated(
+            Duration::from_millis(300),
+            Speed::new(80),
+            Priority::Normal,
+            BlendMode::Max,
+        );
+        wait_ms(100).await;
+        player.play_scalar_arbitrated(
+            Duration::from_millis(100),
+            Speed::new(20),
+            Priority::Normal,
+            BlendMode::Max,
+        );
+        player.await_all().await;
+
+        // assert
+        client.print_device_calls(start);
+        let calls = client.get_device_calls(1);
+        for call in &calls[..calls.len() - 1] {
+            call.assert_strenth(0.8);
+        }
+        calls.last().unwrap().assert_strenth(0.0);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_blend_mode_is_not_changed_by_later_tasks() {
+        // call1  |888888888888888888888-->|
+        // call2         |2222->|
+        // result |888888888888888888888-->|
+
+        // arrange
+        let client = get_test_client(vec![scalar(1, "vib1", ActuatorType::Vibrate)]).await;
+        let mut player = PlayerTest::setup(&client.created_devices);
+
+        // act
+        let start = Instant::now();
+        player.play_scalar_arbitrated(
+            Duration::from_millis(300),
+            Speed::new(80),
+            Priority::Normal,
+            BlendMode::Max,
+        );
+        wait_ms(100).await;
+        player.play_scalar_arbitrated(
+            Duration::from_millis(100),
+            Speed::new(20),
+            Priority::Normal,
+            BlendMode::Latest,
+        );
+        player.await_all().await;
+
+        // assert
+        client.print_device_calls(start);
+        let calls = client.get_device_calls(1);
+        for call in &calls[..calls.len() - 1] {
+            call.assert_strenth(0.8);
+        }
+        calls.last().unwrap().assert_strenth(0.0);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_override_masks_later_normal_task() {
+        // call1  |222222222222222222222-->|
+        // call2         |8888->|
+        // result |222222222222222222222-->|
+
+        // arrange
+        let client = get_test_client(vec![scalar(1, "vib1", ActuatorType::Vibrate)]).await;
+        let mut player = PlayerTest::setup(&client.created_devices);
+
+        // act
+        let start = Instant::now();
+        player.play_scalar_arbitrated(
+            Duration::from_millis(300),
+            Speed::new(20),
+            Priority::Override,
+            BlendMode::HighestPriority,
+        );
+        wait_ms(100).await;
+        player.play_scalar_arbitrated(
+            Duration::from_millis(100),
+            Speed::new(80),
+            Priority::Normal,
+            BlendMode::HighestPriority,
+        );
+        player.await_all().await;
+
+        // assert
+        client.print_device_calls(start);
+        let calls = client.get_device_calls(1);
+        for call in &calls[..calls.len() - 1] {
+            call.assert_strenth(0.2);
+        }
+        calls.last().unwrap().assert_strenth(0.0);
+    }
+
+    /// Pause
+
+    #[tokio::test(start_paused = true)]
+    async fn test_pause_scalar_keeps_remaining_duration() {
+        // arrange
+        let client = get_test_client(vec![scalar(1, "vib1", ActuatorType::Vibrate)]).await;
+        let mut test = PlayerTest::setup(&client.created_devices);
+
+        // act
+        let start = Instant::now();
+        let player = test.get_player();
+        let handle = player.handle;
+        let join = Handle::current().spawn(async move {
+            player
+                .play_scalar(Duration::from_millis(300), Speed::new(50))
+                .await
+                .unwrap();
+        });
+        wait_ms(100).await;
+        assert!(test.scheduler.pause_task(handle));
+        wait_ms(200).await;
+        assert!(test.scheduler.resume_task(handle));
+        let _ = join.await;
+
+        // assert
+        client.print_device_calls(start);
+        let calls = client.get_device_calls(1);
+        calls[0].assert_strenth(0.5).assert_exact_time(0, start);
+        calls[1].assert_strenth(0.0).assert_exact_time(100, start);
+        calls[2].assert_strenth(0.5).assert_exact_time(300, start);
+        calls[3].assert_strenth(0.0).assert_exact_time(500, start);
+        assert_eq!(calls.len(), 4);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_pause_scalar_pattern_keeps_position() {
+        // arrange
+        let client = get_test_client(vec![scalar(1, "vib1", ActuatorType::Vibrate)]).await;
+        let mut test = PlayerTest::setup(&client.created_devices);
+
+        let mut fs = FScript::default();
+        for i in 0..4 {
+            fs.actions.push(FSPoint {
+                pos: 10 * (i + 1),
+                at: 100 * i,
+            });
+        }
+
+        // act
+        let start = Instant::now();
+        let player = test.get_player();
+        let handle = player.handle;
+        let join = Handle::current().spawn(async move {
+            player
+                .play_scalar_pattern(Duration::from_millis(350), fs, Speed::max())
+                .await
+                .unwrap();
+        });
+        wait_ms(150).await;
+        test.scheduler.pause_task(handle);
+        wait_ms(200).await;
+        test.scheduler.resume_task(handle);
+        let _ = join.await;
+
+        // assert
+        client.print_device_calls(start);
+        let calls = client.get_device_calls(1);
+        calls[0].assert_strenth(0.1).assert_exact_time(0, start);
+        calls[1].assert_strenth(0.2).assert_exact_time(100, start);
+        calls[2].assert_strenth(0.0).assert_exact_time(150, start);
+        calls[3].assert_strenth(0.2).assert_exact_time(350, start);
+        calls[4].assert_strenth(0.3).assert_exact_time(400, start);
+        calls[5].assert_strenth(0.1).assert_exact_time(500, start);
+        calls[6].assert_strenth(0.0).assert_exact_time(550, start);
+        assert_eq!(calls.len(), 7);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_pause_linear_holds_position() {
+        // arrange
+        let client = get_test_client(vec![linear(1, "lin1")]).await;
+        let mut test = PlayerTest::setup(&client.created_devices);
+
+        let mut fs = FScript::default();
+        fs.actions.push(FSPoint { pos: 0, at: 100 });
+        fs.actions.push(FSPoint { pos: 100, at: 500 });
+
+        // act
+        let start = Instant::now();
+        let player = test.get_player();
+        let handle = player.handle;
+        let join = Handle::current().spawn(async move {
+            player
+                .play_linear(Duration::from_millis(450), fs, Speed::max())
+                .await
+                .unwrap();
+        });
+        wait_ms(300).await;
+        assert!(test.scheduler.pause_task(handle));
+        wait_ms(200).await;
+        assert!(test.scheduler.resume_task(handle));
+        let _ = join.await;
+
+        // assert
+        client.print_device_calls(start);
+        let calls = client.get_device_calls(1);
+        calls[0].assert_pos(0.0).assert_duration(100).assert_exact_time(0, start);
+        calls[1].assert_pos(1.0).assert_duration(400).assert_exact_time(100, start);
+        calls[2].assert_pos(0.5).assert_exact_time(300, start);
+        calls[3].assert_pos(1.0).assert_duration(200).assert_exact_time(500, start);
+        assert_eq!(calls.len(), 4);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_pause_hands_back_to_other_task() {
+        // arrange
+        let client = get_test_client(vec![scalar(1, "vib1", ActuatorType::Vibrate)]).await;
+        let mut test = PlayerTest::setup(&client.created_devices);
+
+        // act
+        let start = Instant::now();
+        test.play_scalar(Duration::from_millis(400), Speed::new(20), None);
+        wait_ms(50).await;
+        test.play_scalar(Duration::from_millis(200), Speed::new(80), None);
+        wait_ms(50).await;
+        test.scheduler.pause_task(2);
+        wait_ms(100).await;
+        test.scheduler.resume_task(2);
+        test.await_all().await;
+
+        // assert
+        client.print_device_calls(start);
+        let calls = client.get_device_calls(1);
+        calls[0].assert_strenth(0.2).assert_exact_time(0, start);
+        calls[1].assert_strenth(0.8).assert_exact_time(50, start);
+        calls[2].assert_strenth(0.2).assert_exact_time(100, start);
+        calls[3].assert_strenth(0.8).assert_exact_time(200, start);
+        calls[4].assert_strenth(0.2).assert_exact_time(350, start);
+        calls[5].assert_strenth(0.0).assert_exact_time(400, start);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_pause_unknown_handle() {
+        let client = get_test_client(vec![]).await;
+        let mut test = PlayerTest::setup(&client.created_devices);
+        assert!(!test.scheduler.pause_task(42));
+        assert!(!test.scheduler.resume_task(42));
+    }
+
+    /// Worker
+
+    #[tokio::test(start_paused = true)]
+    async fn test_commands_are_rate_limited_per_device() {
+        // arrange
+        let client = get_test_client(vec![scalar(1, "vib1", ActuatorType::Vibrate)]).await;
+        let mut test = PlayerTest::setup_with_settings(
+            &client.created_devices,
+            PlayerSettings {
+                scalar_resolution_ms: 1,
+                min_command_interval_ms: 100,
+                handover_velocity: 0,
+                safety: SafetyLimits::default(),
+                retry: RetrySettings::default(),
+            },
+        );
+
+        // act
+        let start = Instant::now();
+        let player = test.get_player();
+        let handle = player.handle;
+        let join = Handle::current().spawn(async move {
+            player
+                .play_scalar(Duration::from_millis(450), Speed::new(10))
+                .await
+                .unwrap();
+        });
+        for speed in 11..=30 {
+            wait_ms(10).await;
+            test.scheduler.update_task(handle, Speed::new(speed));
+        }
+        let _ = join.await;
+
+        // assert
+        client.print_device_calls(start);
+        let calls = client.get_device_calls(1);
+        // the update that arrives together with the flush is sent in the next interval
+        calls[0].assert_strenth(0.1).assert_exact_time(0, start);
+        calls[1].assert_strenth(0.19).assert_exact_time(100, start);
+        calls[2].assert_strenth(0.29).assert_exact_time(200, start);
+        calls[3].assert_strenth(0.3).assert_exact_time(300, start);
+        calls[4].assert_strenth(0.0).assert_exact_time(450, start);
+        assert_eq!(calls.len(), 5);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_equal_values_are_not_sent_again() {
+        // arrange
+        let client = get_test_client(vec![scalar(1, "vib1", ActuatorType::Vibrate)]).await;
+        let mut test = PlayerTest::setup(&client.created_devices);
+
+        // act
+        let player = test.get_player();
+        let handle = player.handle;
+        let join = Handle::current().spawn(async move {
+            player
+                .play_scalar(Duration::from_millis(200), Speed::new(50))
+                .await
+                .unwrap();
+        });
+        wait_ms(50).await;
+        test.scheduler.update_task(handle, Speed::new(50));
+        wait_ms(50).await;
+        test.scheduler.update_task(handle, Speed::new(50));
+        let _ = join.await;
+
+        // assert
+        let calls = client.get_device_calls(1);
+        calls[0].assert_strenth(0.5);
+        calls[1].assert_strenth(0.0);
+        assert_eq!(calls.len(), 2);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_multi_motor_device_receives_single_command() {
+        // arrange
+        let client = get_test_client(vec![scalars(1, "vib1", ActuatorType::Vibrate, 3)]).await;
+        let mut test = PlayerTest::setup(&client.created_devices);
+
+        // act
+        test.play_scalar(Duration::from_millis(100), Speed::new(70), None);
+        test.await_last().await;
+
+        // assert
+        let calls = client.get_device_calls(1);
+        calls[0].assert_strengths(vec![(0, 0.7), (1, 0.7), (2, 0.7)]);
+        calls[1].assert_strengths(vec![(0, 0.0), (1, 0.0), (2, 0.0)]);
+        assert_eq!(calls.len(), 2);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_shutdown_stops_actuators_and_worker() {
+        // arrange
+        let client = get_test_client(vec![scalar(1, "vib1", ActuatorType::Vibrate)]).await;
+        let (mut scheduler, mut worker) = ButtplugScheduler::create(PlayerSettings {
+            scalar_resolution_ms: 1,
+            min_command_interval_ms: 0,
+            handover_velocity: 0,
+            safety: SafetyLimits::default(),
+            retry: RetrySettings::default(),
+        });
+        let worker_task = Handle::current().spawn(async move {
+            worker.run_worker_thread().await;
+        });
+        let player = scheduler.create_player(get_actuators(client.created_devices.clone()));
+        let handle = player.handle;
+        let task = Handle::current().spawn(async move {
+            let _ = player.play_scalar(Duration::MAX, Speed::new(50)).await;
+        });
+        wait_ms(100).await;
+
+        // act
+        let start = Instant::now();
+        scheduler.shutdown();
+
+        // assert
+        timeout(Duration::from_secs(1), worker_task).await.unwrap().unwrap();
+        timeout(Duration::from_secs(1), task).await.unwrap().unwrap();
+        assert!(!scheduler.is_running(handle));
+        let calls = client.get_device_calls(1);
+        calls[0].assert_strenth(0.5);
+        calls[1].assert_strenth(0.0).assert_exact_time(0, start);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_shutdown_sends_rate_limited_stop() {
+        // arrange
+        let client = get_test_client(vec![scalar(1, "vib1", ActuatorType::Vibrate)]).await;
+        let (mut scheduler, mut worker) = ButtplugScheduler::create(PlayerSettings {
+            scalar_resolution_ms: 1,
+            min_command_interval_ms: 100,
+            handover_velocity: 0,
+            safety: SafetyLimits::default(),
+            retry: RetrySettings::default(),
+        });
+        let worker_task = Handle::current().spawn(async move {
+            worker.run_worker_thread().await;
+        });
+        let player = scheduler.create_player(get_actuators(client.created_devices.clone()));
+        let start = Instant::now();
+        Handle::current().spawn(async move {
+            let _ = player.play_scalar(Duration::MAX, Speed::new(50)).await;
+        });
+        wait_ms(50).await;
+
+        // act
+        scheduler.shutdown();
+
+        // assert
+        timeout(Duration::from_secs(1), worker_task).await.unwrap().unwrap();
+        client.print_device_calls(start);
+        let calls = client.get_device_calls(1);
+        calls[0].assert_strenth(0.5).assert_exact_time(0, start);
+        calls[1].assert_strenth(0.0).assert_exact_time(100, start);
+        assert_eq!(calls.len(), 2);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_player_stops_after_worker_shutdown() {
+        // arrange
+        let client = get_test_client(vec![scalar(1, "vib1", ActuatorType::Vibrate)]).await;
+        let mut test = PlayerTest::setup(&client.created_devices);
+        let player = test.get_player();
+
+        // act
+        test.scheduler.shutdown();
+        wait_ms(1).await;
+        let result = timeout(
+            Duration::from_secs(1),
+            player.play_scalar(Duration::MAX, Speed::new(50)),
+        )
+        .await;
+
+        // assert
+        assert!(result.is_ok());
+        assert!(client.get_device_calls(1).is_empty());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_task_reports_each_failed_actuator_once() {
+        // arrange
+        let client = get_test_client(vec![
+            scalar(1, "vib1", ActuatorType::Vibrate),
+            scalar(2, "vib2", ActuatorType::Vibrate),
+        ])
+        .await;
+        let mut test = PlayerTest::setup(&client.created_devices);
+        client.client.disconnect().await.unwrap();
+
+        // act
+        let start = Instant::now();
+        let result = test
+            .get_player()
+            .play_scalar_pattern(
+                Duration::from_millis(1000),
+                get_repeated_pattern(10),
+                Speed::max(),
+            )
+            .await;
+
+        // assert
+        let report = result.unwrap_err();
+        let failed = report
+            .errors
+            .iter()
+            .map(|x| x.actuator.identifier().to_owned())
+            .collect::<Vec<String>>();
+        assert_eq!(failed.len(), 2);
+        assert!(failed.iter().any(|x| x.starts_with("vib1")));
+        assert!(failed.iter().any(|x| x.starts_with("vib2")));
+        assert!(report.errors.iter().all(|x| x.time == start));
+    }
+
+    /// Handover
+
+    #[tokio::test(start_paused = true)]
+    async fn test_linear_first_move_is_limited_by_handover_velocity() {
+        // arrange
+        let client = get_test_client(vec![linear(1, "lin1")]).await;
+        let mut test = setup_handover_player(&client, 200);
+        let mut fscript = FScript::default();
+        fscript.actions.push(FSPoint { pos: 0, at: 200 });
+        fscript.actions.push(FSPoint { pos: 100, at: 800 });
+
+        // act
+        let start = Instant::now();
+        test.play_linear(fscript, Duration::from_millis(700)).await;
+
+        // assert
+        client.print_device_calls(start);
+        let calls = client.get_device_calls(1);
+        calls[0].assert_pos(0.0).assert_duration(500).assert_exact_time(0, start);
+        calls[1].assert_pos(1.0).assert_duration(300).assert_exact_time(500, start);
+        assert_eq!(calls.len(), 2);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_linear_move_after_handover_is_limited_by_distance() {
+        // arrange
+        let client = get_test_client(vec![linear(1, "lin1")]).await;
+        let mut test = setup_handover_player(&client, 100);
+        let mut first = FScript::default();
+        first.actions.push(FSPoint { pos: 0, at: 1000 });
+        first.actions.push(FSPoint { pos: 100, at: 2000 });
+        let mut second = FScript::default();
+        second.actions.push(FSPoint { pos: 70, at: 100 });
+        second.actions.push(FSPoint { pos: 0, at: 400 });
+
+        // act
+        let start = Instant::now();
+        test.play_linear(first, Duration::from_millis(1500)).await;
+        test.play_linear(second, Duration::from_millis(350)).await;
+
+        // assert
+        client.print_device_calls(start);
+        let calls = client.get_device_calls(1);
+        calls[0].assert_pos(0.0).assert_duration(1000).assert_exact_time(0, start);
+        calls[1].assert_pos(1.0).assert_duration(1000).assert_exact_time(1000, start);
+        // the first task is stopped half way at 0.5
+        calls[2].assert_pos(0.7).assert_duration(200).assert_exact_time(1500, start);
+        calls[3].assert_pos(0.0).assert_duration(200).assert_exact_time(1700, start);
+        assert_eq!(calls.len(), 4);
+    }
+
+    /// Keep-alive
+
+    #[tokio::test(start_paused = true)]
+    async fn test_keep_alive_timeout_stops_task() {
+        // arrange
+        let client = get_test_client(vec![scalar(1, "vib1", ActuatorType::Vibrate)]).await;
+        let mut test = PlayerTest::setup(&client.created_devices);
+
+        // act
+        let start = Instant::now();
+        let mut player = test.get_player();
+        player.keep_alive = Some(Duration::from_millis(100));
+        let expired = player.expired_token.clone();
+        let handle = player.handle;
+        let join = Handle::current().spawn(async move {
+            player.play_scalar(Duration::MAX, Speed::new(50)).await.unwrap();
+        });
+        wait_ms(50).await;
+        assert!(test.scheduler.keep_alive(handle));
+        wait_ms(50).await;
+        test.scheduler.update_task(handle, Speed::new(60));
+        let _ = timeout(Duration::from_secs(1), join).await.unwrap();
+
+        // assert
+        assert!(expired.is_cancelled());
+        assert!(!test.scheduler.is_running(handle));
+        let calls = client.get_device_calls(1);
+        calls[0].assert_strenth(0.5);
+        calls[1].assert_strenth(0.6);
+        calls[2].assert_strenth(0.0).assert_exact_time(200, start);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_keep_alive_is_not_required_by_default() {
+        // arrange
+        let client = get_test_client(vec![scalar(1, "vib1", ActuatorType::Vibrate)]).await;
+        let mut test = PlayerTest::setup(&client.created_devices);
+
+        // act
+        let player = test.get_player();
+        let expired = player.expired_token.clone();
+        let handle = player.handle;
+        Handle::current().spawn(async move {
+            player.play_scalar(Duration::MAX, Speed::new(50)).await.unwrap();
+        });
+        wait_ms(300).await;
+
+        // assert
+        assert!(test.scheduler.is_running(handle));
+        assert!(!expired.is_cancelled());
+        test.scheduler.stop_task(handle, None);
+    }
+
+    /// Envelope
+
+    #[tokio::test(start_paused = true)]
+    async fn test_envelope_attack_fades_in() {
+        // arrange
+        let client = get_test_client(vec![scalar(1, "vib1", ActuatorType::Vibrate)]).await;
+        let (_test, player) = setup_envelope_player(
+            &client,
+            Envelope {
+                attack_ms: 200,
+                ..Default::default()
+            },
+        );
+
+        // act
+        let start = Instant::now();
+        player
+            .play_scalar(Duration::from_millis(350), Speed::max())
+            .await
+            .unwrap();
+
+        // assert
+        client.print_device_calls(start);
+        let calls = client.get_device_calls(1);
+        calls[0].assert_strenth(0.0).assert_exact_time(0, start);
+        assert_strength_near(calls[1].assert_exact_time(100, start), 0.5);
+        calls[2].assert_strenth(1.0).assert_exact_time(200, start);
+        calls[3].assert_strenth(0.0).assert_exact_time(350, start);
+        assert_eq!(calls.len(), 4);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_envelope_release_on_stop() {
+        // arrange
+        let client = get_test_client(vec![scalar(1, "vib1", ActuatorType::Vibrate)]).await;
+        let (mut test, player) = setup_envelope_player(
+            &client,
+            Envelope {
+                release_ms: 200,
+                ..Default::default()
+            },
+        );
+
+        // act
+        let start = Instant::now();
+        let handle = player.handle;
+        let join = Handle::current().spawn(async move {
+            player.play_scalar(Duration::MAX, Speed::max()).await.unwrap();
+        });
+        wait_ms(100).await;
+        test.scheduler.stop_task(handle, None);
+        let _ = join.await;
+
+        // assert
+        client.print_device_calls(start);
+        let calls = client.get_device_calls(1);
+        calls[0].assert_strenth(1.0).assert_exact_time(0, start);
+        let fading = calls
+            .iter()
+            .find(|x| get_strength(x) > 0.0 && get_strength(x) < 1.0)
+            .expect("fades out");
+        assert_strength_near(fading.assert_exact_time(200, start), 0.5);
+        calls.last().unwrap().assert_strenth(0.0).assert_exact_time(300, start);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_release_on_stop_overrides_envelope() {
+        // arrange
+        let client = get_test_client(vec![scalar(1, "vib1", ActuatorType::Vibrate)]).await;
+        let (mut test, player) = setup_envelope_player(
+            &client,
+            Envelope {
+                release_ms: 1000,
+                ..Default::default()
+            },
+        );
+
+        // act
+        let start = Instant::now();
+        let handle = player.handle;
+        let join = Handle::current().spawn(async move {
+            player.play_scalar(Duration::MAX, Speed::max()).await.unwrap();
+        });
+        wait_ms(100).await;
+        test.scheduler.stop_task(handle, Some(Duration::from_millis(200)));
+        let _ = join.await;
+
+        // assert
+        client.print_device_calls(start);
+        let calls = client.get_device_calls(1);
+        calls[0].assert_strenth(1.0).assert_exact_time(0, start);
+        let fading = calls
+            .iter()
+            .find(|x| get_strength(x) > 0.0 && get_strength(x) < 1.0)
+            .expect("fades out");
+        assert_strength_near(fading.assert_exact_time(200, start), 0.5);
+        calls.last().unwrap().assert_strenth(0.0).assert_exact_time(300, start);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_envelope_glide_on_update() {
+        // arrange
+        let client = get_test_client(vec![scalar(1, "vib1", ActuatorType::Vibrate)]).await;
+        let (mut test, player) = setup_envelope_player(
+            &client,
+            Envelope {
+                glide_ms: 200,
+                ..Default::default()
+            },
+        );
+
+        // act
+        let start = Instant::now();
+        let handle = player.handle;
+        let join = Handle::current().spawn(async move {
+            player
+                .play_scalar(Duration::from_millis(400), Speed::min())
+                .await
+                .unwrap();
+        });
+        wait_ms(50).await;
+        test.scheduler.update_task(handle, Speed::max());
+        let _ = join.await;
+
+        // assert
+        client.print_device_calls(start);
+        let calls = client.get_device_calls(1);
+        calls[0].assert_strenth(0.0).assert_exact_time(0, start);
+        let gliding = calls
+            .iter()
+            .find(|x| get_strength(x) > 0.0 && get_strength(x) < 1.0)
+            .expect("glides");
+        assert_strength_near(gliding.assert_exact_time(150, start), 0.5);
+        calls
+            .iter()
+            .find(|x| get_strength(x) == 1.0)
+            .expect("reaches target")
+            .assert_exact_time(250, start);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_stop_all_aborts_release() {
+        // arrange
+        let client = get_test_client(vec![scalar(1, "vib1", ActuatorType::Vibrate)]).await;
+        let (mut test, player) = setup_envelope_player(
+            &client,
+            Envelope {
+                release_ms: 5000,
+                ..Default::default()
+            },
+        );
+
+        // act
+        let join = Handle::current().spawn(async move {
+            player.play_scalar(Duration::MAX, Speed::max()).await.unwrap();
+        });
+        wait_ms(100).await;
+        test.scheduler.stop_all();
+
+        // assert
+        assert!(timeout(Duration::from_secs(1), join).await.is_ok());
+    }
+
+    fn setup_handover_player(client: &ButtplugTestClient, handover_velocity: u32) -> PlayerTest {
+        PlayerTest::setup_with_settings(
+            &client.created_devices,
+            PlayerSettings {
+                scalar_resolution_ms: 100,
+                min_command_interval_ms: 0,
+                handover_velocity,
+                safety: SafetyLimits::default(),
+                retry: RetrySettings::default(),
+            },
+        )
+    }
+
+    fn setup_envelope_player(
+        client: &ButtplugTestClient,
+        envelope: Envelope,
+    ) -> (PlayerTest, PatternPlayer) {
+        let mut test = PlayerTest::setup_with_settings(
+            &client.created_devices,
+            PlayerSettings {
+                scalar_resolution_ms: 100,
+                min_command_interval_ms: 0,
+                handover_velocity: 0,
+                safety: SafetyLimits::default(),
+                retry: RetrySettings::default(),
+            },
+        );
+        let mut player = test.get_player();
+        player.envelope = envelope;
+        (test, player)
+    }
+
+    fn assert_strength_near(call: &FakeMessage, strength: f64) {
+        let actual = get_strength(call);
+        assert!((actual - strength).abs() < 0.1, "{} != {} +/-0.1", actual, strength);
+    }
+
+    fn get_strength(call: &FakeMessage) -> f64 {
+        match &call.message {
+            ButtplugSpecV3ClientMessage::ScalarCmd(cmd) => cmd.scalars()[0].scalar(),
+            _ => panic!("not a scalar cmd"),
+        }
+    }
+
+    /// Rotate
+
+    #[tokio::test(start_paused = true)]
+    async fn test_rotate_direction_update() {
+        // arrange
+        let client = get_test_client(vec![rotate(1, "rot1")]).await;
+        let mut test = PlayerTest::setup(&client.created_devices);
+
+        // act
+        let start = Instant::now();
+        let player = test.get_player();
+        let join = Handle::current().spawn(async move {
+            player
+                .play_rotate(Duration::from_millis(200), Speed::new(50), false)
+                .await
+                .unwrap();
+        });
+        wait_ms(100).await;
+        test.scheduler.update_task_direction(1, true);
+        let _ = join.await;
+
+        // assert
+        client.print_device_calls(start);
+        let calls = client.get_device_calls(1);
+        calls[0].assert_rotation(0.5).assert_direction(false);
+        calls[1]
+            .assert_rotation(0.5)
+            .assert_direction(true)
+            .assert_exact_time(100, start);
+        calls[2].assert_rotation(0.0).assert_exact_time(200, start);
+        assert_eq!(calls.len(), 3);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_rotate_concurrent_restores_direction() {
+        // call1  |<<<<<<<<<<<<<<<<<<<<<-->|
+        // call2         |>>>>->|
+        // result |<<<<<<<>>>>>><<<<<<<<-->|
+
+        // arrange
+        let client = get_test_client(vec![rotate(1, "rot1")]).await;
+        let mut test = PlayerTest::setup(&client.created_devices);
+        let actuators = get_actuators(client.created_devices.clone());
+
+        // act
+        let start = Instant::now();
+        let player = test.scheduler.create_player(actuators.clone());
+        test.handles.push(Handle::current().spawn(async move {
+            player
+                .play_rotate(Duration::from_millis(300), Speed::new(20), false)
+                .await
+                .unwrap();
+        }));
+        wait_ms(100).await;
+        let player = test.scheduler.create_player(actuators);
+        test.handles.push(Handle::current().spawn(async move {
+            player
+                .play_rotate(Duration::from_millis(100), Speed::new(80), true)
+                .await
+                .unwrap();
+        }));
+        test.await_all().await;
+
+        // assert
+        client.print_device_calls(start);
+        let calls = client.get_device_calls(1);
+        calls[0].assert_rotation(0.2).assert_direction(false);
+        calls[1].assert_rotation(0.8).assert_direction(true);
+        calls[2].assert_rotation(0.2).assert_direction(false);
+        calls[3].assert_rotation(0.0);
+        assert_eq!(calls.len(), 4);
+    }
+
+    async fn wait_ms(ms: u64) {
+        tokio::time::sleep(Duration::from_millis(ms)).await;
+    }
+
+    fn get_duration_ms(fs: &FScript) -> Duration {
+        Duration::from_millis(fs.actions.last().unwrap().at as u64)
+    }
+
+    fn check_timing(device_calls: Vec<FakeMessage>, n: usize, start: Instant) {
+        for (i, call) in device_calls.iter().take(n - 1).enumerate() {
+            call.assert_exact_time(i as u64 * 100, start);
+        }
+    }
+
+    fn get_repeated_pattern(n: usize) -> FScript {
+        let mut fscript = FScript::default();
+        for i in 0..n {
+            fscript.actions.push(FSPoint {
+                pos: (i % 100) as i32,
+                at: (i * 100) as i32,
+            });
+        }
+        fscript
+    }
+
+    // Utils
+
+    #[test]
+    fn speed_correct_conversion() {
+        assert_eq!(Speed::new(-1000).as_float(), 0.0);
+        assert_eq!(Speed::new(0).as_float(), 0.0);
+        assert_eq!(Speed::new(9).as_float(), 0.09);
         assert_eq!(Speed::new(100).as_float(), 1.0);
         assert_eq!(Speed::new(1000).as_float(), 1.0);
     }
+
+    #[test]
+    fn scalar_scaling_curves() {
+        let apply = |scaling: ScalarScaling, speed: i64| scaling.apply(Speed::new(speed)).value;
+        assert_eq!(apply(ScalarScaling::Linear, 50), 50);
+        assert_eq!(apply(ScalarScaling::Quadratic, 50), 25);
+        assert_eq!(apply(ScalarScaling::QuadraticFraction, 25), 50);
+
+        let custom = || ScalarScaling::Custom(vec![(10, 20), (50, 80), (90, 100)]);
+        assert_eq!(apply(custom(), 0), 20);
+        assert_eq!(apply(custom(), 10), 20);
+        assert_eq!(apply(custom(), 30), 50);
+        assert_eq!(apply(custom(), 70), 90);
+        assert_eq!(apply(custom(), 100), 100);
+        assert_eq!(apply(ScalarScaling::Custom(vec![]), 42), 42);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_scalar_scaling_is_applied() {
+        // arrange
+        let client = get_test_client(vec![scalar(1, "vib1", ActuatorType::Vibrate)]).await;
+        let mut test = PlayerTest::setup(&client.created_devices);
+        let player = test.get_player_with_settings(vec![ActuatorSettings::Scalar(ScalarRange {
+            scaling: ScalarScaling::Quadratic,
+            ..Default::default()
+        })]);
+
+        // act
+        player
+            .play_scalar(Duration::from_millis(50), Speed::new(50))
+            .await
+            .unwrap();
+
+        // assert
+        client.get_device_calls(1)[0].assert_strenth(0.25);
+    }
+
+    /// Generator
+
+    #[test]
+    fn test_generator_parse() {
+        let generator = PatternGenerator::parse("Gen:Sine:period=2;min=80,max=20").unwrap();
+        assert_eq!(generator.kind, GeneratorKind::Sine);
+        assert_eq!(generator.period, Duration::from_secs(2));
+        assert_eq!((generator.min, generator.max), (20, 80));
+
+        let generator = PatternGenerator::parse("gen:heartbeat:bpm=120").unwrap();
+        assert_eq!(generator.period, Duration::from_millis(500));
+
+        assert!(PatternGenerator::parse("gen:unknown").is_none());
+        assert!(PatternGenerator::parse("gen:sine:period=-1").is_none());
+        assert!(PatternGenerator::parse("gen:sine:foo=1").is_none());
+        assert!(PatternGenerator::parse("02_Cruel-Tease").is_none());
+    }
+
+    #[test]
+    fn test_generator_rejects_invalid_durations() {
+        assert!(PatternGenerator::parse("gen:sine:period=inf").is_none());
+        assert!(PatternGenerator::parse("gen:sine:period=NaN").is_none());
+        assert!(PatternGenerator::parse("gen:sine:period=1e20").is_none());
+        assert!(PatternGenerator::parse("gen:random:step=inf").is_none());
+        assert!(PatternGenerator::parse("gen:build:length=1e20").is_none());
+        assert!(PatternGenerator::parse("gen:heartbeat:bpm=1e-300").is_none());
+        assert!(PatternGenerator::parse("gen:heartbeat:bpm=0").is_none());
+        assert!(PatternGenerator::parse("gen:build:length=601").is_none());
+        let generator = PatternGenerator::parse("gen:build:length=600").unwrap();
+        assert_eq!(generator.generate().actions.last().unwrap().at, 600_000);
+    }
+
+    #[test]
+    fn test_generator_sine_and_pulse() {
+        let sine = PatternGenerator::parse("gen:sine:period=1").unwrap().generate();
+        assert_eq!(sine.actions.len(), 21);
+        assert_eq!(sine.actions[0].pos, 0);
+        assert_eq!(sine.actions[10].pos, 100);
+        assert_eq!(sine.actions[20].at, 1000);
+
+        let pulse = PatternGenerator::parse("gen:pulse:period=2,duty=0.25,min=10")
+            .unwrap()
+            .generate();
+        let points = pulse.actions.iter().map(|x| (x.at, x.pos)).collect::<Vec<_>>();
+        assert_eq!(points, vec![(0, 100), (500, 10), (2000, 100)]);
+    }
+
+    #[test]
+    fn test_generator_random_walk_is_seeded_and_bounded() {
+        let name = "gen:random:seed=42,step=0.5,length=20,min=20,max=60,variation=10";
+        let points = |fscript: &FScript| {
+            fscript.actions.iter().map(|x| (x.at, x.pos)).collect::<Vec<_>>()
+        };
+        let first = PatternGenerator::parse(name).unwrap().generate();
+        let second = PatternGenerator::parse(name).unwrap().generate();
+        assert_eq!(points(&first), points(&second));
+        assert_eq!(first.actions.len(), 41);
+        for pair in first.actions.windows(2) {
+            assert!((20..=60).contains(&pair[1].pos));
+            assert!((pair[1].pos - pair[0].pos).abs() <= 10 || pair[1].at == 20000);
+        }
+    }
+
+    #[test]
+    fn test_generator_build_escalates() {
+        let build = PatternGenerator::parse("gen:build:period=1,length=10")
+            .unwrap()
+            .generate();
+        let peak = |second: i32| {
+            build
+                .actions
+                .iter()
+                .filter(|x| x.at >= second * 1000 && x.at < (second + 1) * 1000)
+                .map(|x| x.pos)
+                .max()
+                .unwrap()
+        };
+        assert!(peak(1) < peak(5));
+        assert!(peak(5) < peak(9));
+        assert!(peak(9) > 85);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_generator_plays_as_scalar_pattern() {
+        // arrange
+        let client = get_test_client(vec![scalar(1, "vib1", ActuatorType::Vibrate)]).await;
+        let mut test = PlayerTest::setup(&client.created_devices);
+        let fscript = PatternGenerator::parse("gen:pulse:period=0.2").unwrap().generate();
+
+        // act
+        let start = Instant::now();
+        let player = test.get_player();
+        player
+            .play_scalar_pattern(Duration::from_millis(350), fscript, Speed::max())
+            .await
+            .unwrap();
+
+        // assert
+        client.print_device_calls(start);
+        let calls = client.get_device_calls(1);
+        calls[0].assert_strenth(1.0).assert_exact_time(0, start);
+        calls[1].assert_strenth(0.0).assert_exact_time(100, start);
+        calls[2].assert_strenth(1.0).assert_exact_time(200, start);
+        calls[3].assert_strenth(0.0).assert_exact_time(300, start);
+    }
+
+    /// Clock
+
+    #[tokio::test(start_paused = true)]
+    async fn test_long_pattern_runs_on_virtual_clock() {
+        // arrange
+        let client = get_test_client(vec![scalar(1, "vib1", ActuatorType::Vibrate)]).await;
+        let mut test = PlayerTest::setup(&client.created_devices);
+        let fscript = PatternGenerator::parse("gen:pulse:period=1").unwrap().generate();
+
+        // act
+        let start = Instant::now();
+        test.get_player()
+            .play_scalar_pattern(Duration::from_secs(600), fscript, Speed::max())
+            .await
+            .unwrap();
+
+        // assert
+        assert_eq!(start.elapsed(), Duration::from_secs(600));
+        let calls = client.get_device_calls(1);
+        // the loop that would start at 600s is not played, the
+        // stop equals the last sent 0% and is skipped by the worker
+        assert_eq!(calls.len(), 1200);
+        for (i, call) in calls.iter().enumerate() {
+            let strength = if i % 2 == 0 { 1.0 } else { 0.0 };
+            call.assert_strenth(strength).assert_exact_time(i as u64 * 500, start);
+        }
+    }
+
+    /// Safety
+
+    #[tokio::test(start_paused = true)]
+    async fn test_master_intensity_scales_running_scalar_task() {
+        // arrange
+        let client = get_test_client(vec![scalar(1, "vib1", ActuatorType::Vibrate)]).await;
+        let mut test = PlayerTest::setup(&client.created_devices);
+
+        // act
+        let start = Instant::now();
+        test.play_scalar(Duration::from_millis(300), Speed::new(80), None);
+        wait_ms(100).await;
+        test.scheduler.set_master_intensity(Speed::new(50));
+        test.await_last().await;
+
+        // assert
+        client.print_device_calls(start);
+        let calls = client.get_device_calls(1);
+        calls[0].assert_strenth(0.8).assert_exact_time(0, start);
+        calls[1].assert_strenth(0.4).assert_exact_time(100, start);
+        calls[2].assert_strenth(0.0).assert_exact_time(300, start);
+        assert_eq!(test.scheduler.master_intensity(), Speed::new(50));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_master_intensity_shrinks_linear_stroke() {
+        // arrange
+        let client = get_test_client(vec![linear(1, "lin1")]).await;
+        let mut test = PlayerTest::setup(&client.created_devices);
+        let mut fscript = FScript::default();
+        fscript.actions.push(FSPoint { pos: 0, at: 100 });
+        fscript.actions.push(FSPoint { pos: 100, at: 200 });
+
+        // act
+        test.scheduler.set_master_intensity(Speed::new(50));
+        test.play_linear(fscript, Duration::from_millis(200)).await;
+
+        // assert
+        let calls = client.get_device_calls(1);
+        calls[0].assert_pos(0.25);
+        calls[1].assert_pos(0.75);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_max_run_time_stops_task_and_starts_cooldown() {
+        // arrange
+        let client = get_test_client(vec![scalar(1, "vib1", ActuatorType::Vibrate)]).await;
+        let mut test = setup_safety_player(
+            &client,
+            SafetyLimits {
+                max_run_time_ms: 200,
+                max_session_time_ms: 0,
+                cooldown_ms: 300,
+            },
+        );
+
+        // act
+        let start = Instant::now();
+        let player = test.get_player();
+        let violated_limit = player.violated_limit.subscribe();
+        timeout(Duration::from_secs(1), player.play_scalar(Duration::MAX, Speed::new(50)))
+            .await
+            .unwrap()
+            .unwrap();
+        let stopped = start.elapsed();
+        let player = test.get_player();
+        let refused_limit = player.violated_limit.subscribe();
+        timeout(Duration::from_secs(1), player.play_scalar(Duration::MAX, Speed::new(50)))
+            .await
+            .unwrap()
+            .unwrap();
+
+        // assert
+        client.print_device_calls(start);
+        assert_eq!(stopped, Duration::from_millis(200));
+        assert_eq!(*violated_limit.borrow(), Some(SafetyLimit::RunTime));
+        assert_eq!(*refused_limit.borrow(), Some(SafetyLimit::Cooldown));
+        let calls = client.get_device_calls(1);
+        calls[0].assert_strenth(0.5).assert_exact_time(0, start);
+        calls[1].assert_strenth(0.0).assert_exact_time(200, start);
+        assert_eq!(calls.len(), 2);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_max_session_time_adds_up_tasks() {
+        // arrange
+        let client = get_test_client(vec![scalar(1, "vib1", ActuatorType::Vibrate)]).await;
+        let mut test = setup_safety_player(
+            &client,
+            SafetyLimits {
+                max_run_time_ms: 0,
+                max_session_time_ms: 300,
+                cooldown_ms: 0,
+            },
+        );
+
+        // act
+        let start = Instant::now();
+        test.get_player()
+            .play_scalar(Duration::from_millis(200), Speed::new(50))
+            .await
+            .unwrap();
+        wait_ms(100).await;
+        let player = test.get_player();
+        let violated_limit = player.violated_limit.subscribe();
+        player
+            .play_scalar(Duration::from_millis(200), Speed::new(60))
+            .await
+            .unwrap();
+
+        // assert
+        client.print_device_calls(start);
+        assert_eq!(*violated_limit.borrow(), Some(SafetyLimit::SessionTime));
+        let calls = client.get_device_calls(1);
+        calls[0].assert_strenth(0.5).assert_exact_time(0, start);
+        calls[1].assert_strenth(0.0).assert_exact_time(200, start);
+        calls[2].assert_strenth(0.6).assert_exact_time(300, start);
+        calls[3].assert_strenth(0.0).assert_exact_time(400, start);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_max_session_time_without_cooldown_refuses_new_tasks() {
+        // arrange
+        let client = get_test_client(vec![scalar(1, "vib1", ActuatorType::Vibrate)]).await;
+        let mut test = setup_safety_player(
+            &client,
+            SafetyLimits {
+                max_run_time_ms: 0,
+                max_session_time_ms: 300,
+                cooldown_ms: 0,
+            },
+        );
+
+        // act
+        let start = Instant::now();
+        test.get_player()
+            .play_scalar(Duration::from_millis(400), Speed::new(50))
+            .await
+            .unwrap();
+        wait_ms(100).await;
+        let player = test.get_player();
+        let refused_limit = player.violated_limit.subscribe();
+        timeout(Duration::from_secs(1), player.play_scalar(Duration::MAX, Speed::new(60)))
+            .await
+            .unwrap()
+            .unwrap();
+
+        // assert
+        client.print_device_calls(start);
+        assert_eq!(*refused_limit.borrow(), Some(SafetyLimit::SessionTime));
+        let calls = client.get_device_calls(1);
+        calls[0].assert_strenth(0.5).assert_exact_time(0, start);
+        calls[1].assert_strenth(0.0).assert_exact_time(300, start);
+        assert_eq!(calls.len(), 2);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_cooldown_does_not_stop_running_tasks() {
+        // arrange
+        let client = get_test_client(vec![
+            scalar(1, "vib1", ActuatorType::Vibrate),
+            scalar(2, "vib2", ActuatorType::Vibrate),
+        ])
+        .await;
+        let mut test = setup_safety_player(
+            &client,
+            SafetyLimits {
+                max_run_time_ms: 200,
+                max_session_time_ms: 0,
+                cooldown_ms: 1000,
+            },
+        );
+        let actuators = get_actuators(client.created_devices.clone());
+
+        // act
+        let start = Instant::now();
+        let violating = test.scheduler.create_player(vec![actuators[0].clone()]);
+        let violated_limit = violating.violated_limit.subscribe();
+        let violating = Handle::current().spawn(violating.play_scalar(Duration::MAX, Speed::new(50)));
+        wait_ms(150).await;
+        let running = test.scheduler.create_player(vec![actuators[1].clone()]);
+        let handle = running.handle;
+        let running_limit = running.violated_limit.subscribe();
+        let running = Handle::current()
+            .spawn(running.play_scalar(Duration::from_millis(150), Speed::new(50)));
+        wait_ms(100).await;
+        test.scheduler.update_task(handle, Speed::new(70));
+        violating.await.unwrap().unwrap();
+        running.await.unwrap().unwrap();
+
+        // assert
+        client.print_device_calls(start);
+        assert_eq!(*violated_limit.borrow(), Some(SafetyLimit::RunTime));
+        assert_eq!(*running_limit.borrow(), None);
+        let calls = client.get_device_calls(1);
+        calls[0].assert_strenth(0.5).assert_exact_time(0, start);
+        calls[1].assert_strenth(0.0).assert_exact_time(200, start);
+        assert_eq!(calls.len(), 2);
+        let calls = client.get_device_calls(2);
+        calls[0].assert_strenth(0.5).assert_exact_time(150, start);
+        calls[1].assert_strenth(0.7).assert_exact_time(250, start);
+        calls[2].assert_strenth(0.0).assert_exact_time(300, start);
+        assert_eq!(calls.len(), 3);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_max_session_time_starts_over_after_cooldown() {
+        // arrange
+        let client = get_test_client(vec![scalar(1, "vib1", ActuatorType::Vibrate)]).await;
+        let mut test = setup_safety_player(
+            &client,
+            SafetyLimits {
+                max_run_time_ms: 0,
+                max_session_time_ms: 300,
+                cooldown_ms: 100,
+            },
+        );
+
+        // act
+        let start = Instant::now();
+        let player = test.get_player();
+        let violated_limit = player.violated_limit.subscribe();
+        player
+            .play_scalar(Duration::from_millis(400), Speed::new(50))
+            .await
+            .unwrap();
+        wait_ms(150).await;
+        let player = test.get_player();
+        let next_limit = player.violated_limit.subscribe();
+        player
+            .play_scalar(Duration::from_millis(200), Speed::new(60))
+            .await
+            .unwrap();
+
+        // assert
+        client.print_device_calls(start);
+        assert_eq!(*violated_limit.borrow(), Some(SafetyLimit::SessionTime));
+        assert_eq!(*next_limit.borrow(), None);
+        let calls = client.get_device_calls(1);
+        calls[0].assert_strenth(0.5).assert_exact_time(0, start);
+        calls[1].assert_strenth(0.0).assert_exact_time(300, start);
+        calls[2].assert_strenth(0.6).assert_exact_time(450, start);
+        calls[3].assert_strenth(0.0).assert_exact_time(650, start);
+        assert_eq!(calls.len(), 4);
+    }
+
+    fn setup_safety_player(client: &ButtplugTestClient, safety: SafetyLimits) -> PlayerTest {
+        PlayerTest::setup_with_settings(
+            &client.created_devices,
+            PlayerSettings {
+                scalar_resolution_ms: 100,
+                min_command_interval_ms: 0,
+                handover_velocity: 0,
+                safety,
+                retry: RetrySettings::default(),
+            },
+        )
+    }
+
+    /// Retry
+
+    #[tokio::test(start_paused = true)]
+    async fn test_transient_error_is_retried_with_backoff() {
+        // arrange
+        let client = get_test_client(vec![scalar(1, "vib1", ActuatorType::Vibrate)]).await;
+        let mut test = PlayerTest::setup(&client.created_devices);
+        client.call_registry.fail_next(1, 2);
+
+        // act
+        let start = Instant::now();
+        test.get_player()
+            .play_scalar(Duration::from_millis(1000), Speed::new(50))
+            .await
+            .unwrap();
+
+        // assert
+        client.print_device_calls(start);
+        let calls = client.get_device_calls(1);
+        calls[0].assert_strenth(0.5).assert_exact_time(0, start);
+        calls[1].assert_strenth(0.5).assert_exact_time(100, start);
+        calls[2].assert_strenth(0.5).assert_exact_time(300, start);
+        calls[3].assert_strenth(0.0).assert_exact_time(1000, start);
+        assert_eq!(calls.len(), 4);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_failed_linear_move_is_retried() {
+        // arrange
+        let client = get_test_client(vec![linear(1, "lin1")]).await;
+        let mut test = PlayerTest::setup(&client.created_devices);
+        client.call_registry.fail_next(1, 1);
+
+        let mut fscript = FScript::default();
+        fscript.actions.push(FSPoint { pos: 100, at: 400 });
+
+        // act
+        let start = Instant::now();
+        test.play_linear(fscript, Duration::from_millis(400)).await;
+
+        // assert
+        client.print_device_calls(start);
+        let calls = client.get_device_calls(1);
+        calls[0].assert_pos(1.0).assert_duration(400).assert_exact_time(0, start);
+        calls[1].assert_pos(1.0).assert_duration(400).assert_exact_time(100, start);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_retry_is_replaced_by_newer_command() {
+        // arrange
+        let client = get_test_client(vec![scalar(1, "vib1", ActuatorType::Vibrate)]).await;
+        let mut test = PlayerTest::setup(&client.created_devices);
+        client.call_registry.fail_next(1, 1);
+
+        // act
+        let start = Instant::now();
+        let player = test.get_player();
+        let handle = player.handle;
+        let join = Handle::current().spawn(async move {
+            player
+                .play_scalar(Duration::from_millis(500), Speed::new(50))
+                .await
+                .unwrap();
+        });
+        wait_ms(50).await;
+        test.scheduler.update_task(handle, Speed::new(70));
+        let _ = join.await;
+
+        // assert
+        client.print_device_calls(start);
+        let calls = client.get_device_calls(1);
+        calls[0].assert_strenth(0.5).assert_exact_time(0, start);
+        calls[1].assert_strenth(0.7).assert_exact_time(100, start);
+        calls[2].assert_strenth(0.0).assert_exact_time(500, start);
+        assert_eq!(calls.len(), 3);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_actuator_recovers_after_all_retries_failed() {
+        // arrange
+        let client = get_test_client(vec![scalar(1, "vib1", ActuatorType::Vibrate)]).await;
+        let mut test = PlayerTest::setup_with_settings(
+            &client.created_devices,
+            PlayerSettings {
+                scalar_resolution_ms: 1,
+                min_command_interval_ms: 0,
+                handover_velocity: 0,
+                safety: SafetyLimits::default(),
+                retry: RetrySettings {
+                    max_retries: 1,
+                    backoff_ms: 100,
+                },
+            },
+        );
+        let mut recoveries = test.scheduler.subscribe_recoveries();
+        client.call_registry.fail_next(1, 2);
+
+        // act
+        let start = Instant::now();
+        let result = test
+            .get_player()
+            .play_scalar(Duration::from_millis(500), Speed::new(50))
+            .await;
+
+        // assert
+        client.print_device_calls(start);
+        let report = result.unwrap_err();
+        assert_eq!(report.errors.len(), 1);
+        assert_eq!(report.errors[0].time, start + Duration::from_millis(100));
+        let recovery = recoveries.try_recv().unwrap();
+        assert_eq!(recovery.actuator.identifier(), report.errors[0].actuator.identifier());
+        assert_eq!(recovery.time, start + Duration::from_millis(500));
+        let calls = client.get_device_calls(1);
+        calls[0].assert_strenth(0.5).assert_exact_time(0, start);
+        calls[1].assert_strenth(0.5).assert_exact_time(100, start);
+        calls[2].assert_strenth(0.0).assert_exact_time(500, start);
+    }
 }
@@ -1,20 +1,72 @@
-use funscript::FScript;
+use buttplug::core::message::ActuatorType;
+use funscript::{FSPoint, FScript};
+use futures::future::join_all;
 use tokio::runtime::Handle;
+use tokio::sync::{broadcast, watch};
 use tokio::task::JoinHandle;
 
-use std::{fmt, sync::Arc, time::Duration};
+use buttplug::client::ButtplugClientError;
+use std::{fmt::{self, Display}, sync::Arc, time::Duration};
 use tokio::{
-    sync::mpsc::{UnboundedReceiver, UnboundedSender},
+    sync::mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender},
     time::{sleep, Instant},
 };
 use tokio_util::sync::CancellationToken;
 use tracing::{debug, error, info, instrument, trace};
 
 use crate::{
-    actuator::Actuator, cancellable_wait, settings::{ActuatorSettings, LinearRange, LinearSpeedScaling}, speed::Speed, worker::{ButtplugClientResult, WorkerTask}
+    actuator::Actuator, cancellable_wait, envelope::{Envelope, Fade}, wait_for_pause, interpolation::Interpolation, priority::{Arbitration, Priority}, safety::{SafetyLimit, SafetyViolation}, settings::{ActuatorSettings, LinearRange, LinearSpeedScaling}, speed::Speed, worker::{ButtplugClientResult, TaskHandle, WorkerTask}
 };
 
+/// Failed command of a single actuator
+#[derive(Debug)]
+pub struct ActuatorError {
+    pub actuator: Arc<Actuator>,
+    pub error: ButtplugClientError,
+    /// When the command failed
+    pub time: Instant,
+}
+
+/// Sent when an actuator accepts commands again after a command failed with all retries
+#[derive(Debug, Clone)]
+pub struct ActuatorRecovery {
+    pub actuator: Arc<Actuator>,
+    /// When the first successful command was sent
+    pub time: Instant,
+}
+
+/// Every actuator that failed during a task, with its first error
+#[derive(Debug, Default)]
+pub struct TaskReport {
+    pub errors: Vec<ActuatorError>,
+}
+
+impl TaskReport {
+    fn add(&mut self, error: ActuatorError) {
+        let id = error.actuator.identifier();
+        if !self.errors.iter().any(|x| x.actuator.identifier() == id) {
+            self.errors.push(error);
+        }
+    }
+}
+
+impl Display for TaskReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let errors = self
+            .errors
+            .iter()
+            .map(|x| format!("{}: {}", x.actuator, x.error))
+            .collect::<Vec<String>>();
+        write!(f, "{}", errors.join(", "))
+    }
+}
+
+pub type TaskResult = Result<(), TaskReport>;
+
 /// Pattern executor that can be passed from the schedulers main-thread to a sub-thread
+///
+/// All timing is based on `tokio::time`, so tests can run the player on a paused,
+/// virtual clock that advances instantly whenever all tasks wait
 pub struct PatternPlayer {
     pub handle: i32,
     pub scalar_resolution_ms: i32,
@@ -22,73 +74,175 @@ pub struct PatternPlayer {
     pub settings: Vec<ActuatorSettings>,
     pub result_sender: UnboundedSender<ButtplugClientResult>,
     pub result_receiver: UnboundedReceiver<ButtplugClientResult>,
+    /// Receives the errors of all commands that were sent by this task
+    pub error_sender: UnboundedSender<ActuatorError>,
+    pub error_receiver: UnboundedReceiver<ActuatorError>,
     pub update_receiver: UnboundedReceiver<Speed>,
+    pub direction_receiver: watch::Receiver<bool>,
+    pub pause_receiver: watch::Receiver<bool>,
+    /// Release time set when the task is stopped, overrides the release of the envelope
+    pub release_receiver: watch::Receiver<Option<Duration>>,
+    pub keep_alive_receiver: watch::Receiver<()>,
+    /// Stops the task if no keep-alive arrives within this timeout, see `ButtplugScheduler::keep_alive`
+    pub keep_alive: Option<Duration>,
+    /// Cancelled if the task was stopped because the keep-alive timed out
+    pub expired_token: CancellationToken,
+    /// See `ButtplugScheduler::set_master_intensity`
+    pub master_intensity: watch::Receiver<Speed>,
+    /// Subscribed to when the task starts, to receive the tasks stopped by the worker
+    pub violation_sender: broadcast::Sender<SafetyViolation>,
+    /// Set if the task was stopped because it exceeded a safety limit
+    pub violated_limit: watch::Sender<Option<SafetyLimit>>,
+    pub clockwise: bool,
+    pub priority: Priority,
+    pub interpolation: Interpolation,
+    pub envelope: Envelope,
+    /// Ends the task, scalar tasks fade out over the release time of the envelope
     pub cancellation_token: CancellationToken,
+    /// Ends the task immediately, also aborts a fade out
+    pub abort_token: CancellationToken,
     pub worker_task_sender: UnboundedSender<WorkerTask>,
 }
 
 impl PatternPlayer {
+    /// Strokes all actuators between their min and max position for 'duration'
+    /// and consumes the player. Each actuator runs its own loop, so actuators
+    /// with different settings keep their individual timing
     pub async fn play_oscillate_linear(
         mut self,
         duration: Duration,
         speed: Speed,
         settings: LinearRange
-    ) -> ButtplugClientResult {
+    ) -> TaskResult {
         debug!(?settings, "oscillation started");
         let waiter = self.stop_after(duration);
-        let mut current_speed = speed;
-        while !self.cancelled() {
-            self.try_update(&mut current_speed);
-            self.do_oscillate(true, current_speed, &settings).await.unwrap();
-            if self.cancelled() {
-                break;
+        let (speed_sender, speed_receiver) = watch::channel(speed);
+        let strokes = self
+            .actuators
+            .iter()
+            .enumerate()
+            .map(|(i, actuator)| {
+                let actual_settings = settings.merge(&self.settings[ i ].linear_or_max());
+                Handle::current().spawn(oscillate(
+                    actuator.clone(),
+                    self.task_handle(),
+                    actual_settings,
+                    speed_receiver.clone(),
+                    self.master_intensity.clone(),
+                    self.pause_receiver.clone(),
+                    self.cancellation_token.clone(),
+                    self.worker_task_sender.clone(),
+                ))
+            })
+            .collect::<Vec<JoinHandle<ButtplugClientResult>>>();
+        loop {
+            tokio::select! {
+                _ = self.cancellation_token.cancelled() => {
+                    break;
+                }
+                update = self.update_receiver.recv() => {
+                    match update {
+                        Some(speed) => {
+                            let _ = speed_sender.send(speed.bounded());
+                        }
+                        None => {
+                            // no more updates, keep stroking until the task ends
+                            self.cancellation_token.cancelled().await;
+                            break;
+                        }
+                    }
+                }
+            };
+        }
+        for stroke in join_all(strokes).await {
+            if let Err(err) = stroke {
+                error!("oscillation failed {:?}", err);
             }
-            self.try_update(&mut current_speed);
-            self.do_oscillate(false, current_speed, &settings).await.unwrap();
         }
         waiter.abort();
-        Ok(())
+        self.report()
     }
 
     /// Executes the linear 'fscript' for 'duration' and consumes the player
+    ///
+    /// 'speed' is the playback rate, 100% is the original timing of the
+    /// script, 50% half and 200% double the tempo. Updates change the rate of
+    /// the current movement without changing its target position
     #[instrument(skip(fscript))]
     pub async fn play_linear(
         mut self,
         duration: Duration,
         fscript: FScript,
-    ) -> ButtplugClientResult {
+        speed: Speed,
+    ) -> TaskResult {
         info!("linear pattern started");
-        let mut last_result = Ok(());
         if fscript.actions.is_empty() || fscript.actions.iter().all(|x| x.at == 0) {
-            return last_result;
+            return Ok(());
         }
         let waiter = self.stop_after(duration);
-        while !self.cancellation_token.is_cancelled() {
-            let started = Instant::now();
+        let mut rate = speed;
+        let mut positions = vec![None; self.actuators.len()];
+        'playback: while !self.cancellation_token.is_cancelled() {
+            // the time axis of the script advances with the playback rate,
+            // it is re-anchored to the clock whenever the rate changes
+            let mut anchor = Instant::now();
+            let mut anchor_ms = 0.0;
             for point in fscript.actions.iter() {
                 let point_as_float = Speed::from_fs(point).as_float();
-                if let Some(waiting_time) =
-                    Duration::from_millis(point.at as u64).checked_sub(started.elapsed())
-                {
-                    let token = &self.cancellation_token.clone();
-                    if let Some(result) = tokio::select! {
-                        _ = token.cancelled() => { None }
-                        result = async {
-                            self.do_linear(point_as_float, waiting_time.as_millis() as u32).await
-                        } => {
-                            Some(result)
+                loop {
+                    let script_ms = script_time(anchor, anchor_ms, rate);
+                    let token = self.cancellation_token.clone();
+                    if rate.value == 0 {
+                        tokio::select! {
+                            _ = token.cancelled() => break 'playback,
+                            update = self.update_receiver.recv() => {
+                                let Some(update) = update else {
+                                    break 'playback;
+                                };
+                                (anchor, anchor_ms) = (Instant::now(), script_ms);
+                                rate = update;
+                                continue;
+                            }
                         }
-                    } {
-                        last_result = result;
-                    } else {
+                    }
+                    let remaining_ms = ((point.at as f64 - script_ms) / rate.as_float()).round() as u32;
+                    if remaining_ms == 0 {
                         break;
                     }
+                    self.do_linear(point_as_float, remaining_ms, &mut positions);
+                    let result_receiver = &mut self.result_receiver;
+                    let mut pause = self.pause_receiver.clone();
+                    tokio::select! {
+                        _ = token.cancelled() => break 'playback,
+                        _ = async {
+                            sleep(Duration::from_millis(remaining_ms.into())).await;
+                            result_receiver.recv().await
+                        } => {}
+                        update = self.update_receiver.recv() => {
+                            let Some(update) = update else {
+                                break 'playback;
+                            };
+                            (anchor, anchor_ms) = (Instant::now(), script_time(anchor, anchor_ms, rate));
+                            rate = update;
+                            debug!(?rate, "linear rate updated");
+                        }
+                        true = wait_for_pause(&mut pause, true) => {
+                            // the stroker stops at its current position while paused, the
+                            // interrupted move is sent again with its remaining time on resume
+                            self.do_hold();
+                            anchor_ms = script_time(anchor, anchor_ms, rate);
+                            if !wait_for_pause(&mut pause, false).await {
+                                break 'playback;
+                            }
+                            anchor = Instant::now();
+                        }
+                    }
                 }
             }
         }
         waiter.abort();
         info!("linear pattern done");
-        last_result
+        self.report()
     }
 
     /// Executes the scalar 'fscript' for 'duration' and consumes the player
@@ -98,7 +252,7 @@ impl PatternPlayer {
         duration: Duration,
         fscript: FScript,
         speed: Speed,
-    ) -> ButtplugClientResult {
+    ) -> TaskResult {
         if fscript.actions.is_empty() || fscript.actions.iter().all(|x| x.at == 0) {
             return Ok(());
         }
@@ -108,124 +262,257 @@ impl PatternPlayer {
         let mut started = false;
         let mut loop_started = Instant::now();
         let mut i: usize = 0;
-        let mut current_speed = speed;
+        let mut attack = Fade::new(Speed::min(), Speed::max(), self.envelope.attack());
+        let mut factor = Fade::constant(speed);
+        let mut last_speed = Speed::min();
+        // no point is started at the moment the duration ends
+        let mut end = Instant::now().checked_add(duration);
         loop {
+            if self.is_paused() {
+                match self.wait_for_resume().await {
+                    Some(paused) => {
+                        loop_started += paused;
+                        end = end.and_then(|end| end.checked_add(paused));
+                    }
+                    None => break,
+                }
+                started = false;
+                attack = Fade::new(Speed::min(), Speed::max(), self.envelope.attack());
+            }
+            if end.is_some_and(|end| Instant::now() >= end) {
+                break;
+            }
             let mut j = 1;
-            while j + i < action_len - 1
-                && (fscript.actions[i + j].at - fscript.actions[i].at) < self.scalar_resolution_ms
+            while (i % action_len) + j < action_len - 1
+                && (fscript.actions[(i + j) % action_len].at - fscript.actions[i % action_len].at)
+                    < self.scalar_resolution_ms
             {
                 j += 1;
             }
             let current = &fscript.actions[i % action_len];
             let next = &fscript.actions[(i + j) % action_len];
             if let Ok(update) = self.update_receiver.try_recv() {
-                current_speed = update;
+                factor = Fade::new(factor.speed(), update.bounded(), self.envelope.glide());
             }
+            self.try_update_direction();
 
+            let current_speed = factor.speed().multiply(&attack.speed());
             let speed = Speed::from_fs(current).multiply(&current_speed);
             if !started {
-                self.do_scalar(speed, true);
+                self.do_scalar(speed);
                 started = true;
             } else {
-                self.do_update(speed, true);
+                self.do_update(speed);
             }
-            if let Some(waiting_time) =
-                Duration::from_millis(next.at as u64).checked_sub(loop_started.elapsed())
-            {
+            last_speed = speed;
+            let mut waited = self.interpolate(current, next, current_speed, loop_started).await;
+            if let (Wait::Elapsed, Some(waiting_time)) = (
+                &waited,
+                Duration::from_millis(next.at as u64).checked_sub(loop_started.elapsed()),
+            ) {
                 debug!(?speed, ?waiting_time, "vibrating");
-                if !(cancellable_wait(waiting_time, &self.cancellation_token).await) {
+                waited = self.wait(waiting_time).await;
+            }
+            match waited {
+                Wait::Cancelled => {
                     debug!("scalar pattern cancelled");
                     break;
                 }
+                // continues at the same point after resuming
+                Wait::Paused => continue,
+                Wait::Elapsed => {}
             }
             i += j;
-            if (i % action_len) == 0 {
+            if i.is_multiple_of(action_len) {
                 loop_started = Instant::now();
             }
         }
         waiter.abort();
-        let result = self.do_stop(true).await;
+        if !self.is_paused() {
+            self.release(last_speed).await;
+        }
+        self.do_stop().await;
         info!("scalar pattern done");
-        result
+        self.report()
+    }
+
+    /// Executes the scalar 'fscript' as a rotation in the given direction
+    /// for 'duration' and consumes the player
+    pub async fn play_rotate_pattern(
+        mut self,
+        duration: Duration,
+        fscript: FScript,
+        speed: Speed,
+        clockwise: bool,
+    ) -> TaskResult {
+        self.clockwise = clockwise;
+        self.play_scalar_pattern(duration, fscript, speed).await
+    }
+
+    /// Executes a constant rotation with 'speed' in the given direction
+    /// for 'duration' and consumes the player
+    pub async fn play_rotate(
+        mut self,
+        duration: Duration,
+        speed: Speed,
+        clockwise: bool,
+    ) -> TaskResult {
+        self.clockwise = clockwise;
+        self.play_scalar(duration, speed).await
     }
 
     /// Executes a constant movement with 'speed' for 'duration' and consumes the player
     #[instrument]
-    pub async fn play_scalar(mut self, duration: Duration, speed: Speed) -> ButtplugClientResult {
+    pub async fn play_scalar(mut self, duration: Duration, speed: Speed) -> TaskResult {
         info!("scalar started");
         let waiter = self.stop_after(duration);
-        self.do_scalar(speed, false);
+        let resolution = Duration::from_millis(self.scalar_resolution_ms as u64);
+        let mut current_speed = speed;
+        let mut fade = Fade::new(Speed::min(), speed, self.envelope.attack());
+        self.do_scalar(fade.speed());
         loop {
             tokio::select! {
                 _ = self.cancellation_token.cancelled() => {
                     break;
                 }
+                _ = sleep(resolution), if !fade.is_done() && !self.is_paused() => {
+                    self.do_update(fade.speed());
+                }
                 update = self.update_receiver.recv() => {
                     if let Some(speed) = update {
-                        self.do_update(speed, false);
+                        current_speed = speed.bounded();
+                        fade = Fade::new(fade.speed(), current_speed, self.envelope.glide());
+                        if !self.is_paused() {
+                            self.do_update(fade.speed());
+                        }
+                    }
+                }
+                Ok(_) = self.direction_receiver.changed() => {
+                    self.clockwise = *self.direction_receiver.borrow_and_update();
+                    if !self.is_paused() {
+                        self.do_update(fade.speed());
+                    }
+                }
+                Ok(_) = self.pause_receiver.changed() => {
+                    if *self.pause_receiver.borrow_and_update() {
+                        info!("paused");
+                        self.do_stop().await;
+                    } else {
+                        info!("resumed");
+                        fade = Fade::new(Speed::min(), current_speed, self.envelope.attack());
+                        self.do_scalar(fade.speed());
                     }
                 }
             };
         }
         waiter.abort();
-        let result = self.do_stop(false).await;
+        if !self.is_paused() {
+            self.release(fade.speed()).await;
+        }
+        self.do_stop().await;
         info!("scalar done");
-        result
+        self.report()
     }
 
-    fn do_update(&self, speed: Speed, is_pattern: bool) {
+    fn do_update(&self, speed: Speed) {
         for (i, actuator) in self.actuators.iter().enumerate() {
             trace!("do_update {} {:?}", speed, actuator);
+            let speed = apply_scalar_settings(speed, &self.settings[ i ]);
             self.worker_task_sender
-                .send(WorkerTask::Update(
-                    actuator.clone(),
-                    apply_scalar_settings(speed, &self.settings[ i ]),
-                    is_pattern,
-                    self.handle,
-                ))
+                .send(match actuator.actuator {
+                    ActuatorType::Rotate => WorkerTask::Rotate(
+                        actuator.clone(),
+                        speed,
+                        self.clockwise,
+                        self.task_handle(),
+                        self.arbitration(i),
+                    ),
+                    _ => WorkerTask::Update(actuator.clone(), speed, self.task_handle()),
+                })
                 .unwrap_or_else(|err| error!("queue err {:?}", err));
         }
     }
 
     #[instrument(skip(self))]
-    fn do_scalar(&self, speed: Speed, is_pattern: bool) {
+    fn do_scalar(&self, speed: Speed) {
         for (i, actuator) in self.actuators.iter().enumerate() {
             trace!("do_scalar");
+            let speed = apply_scalar_settings(speed, &self.settings[ i ]);
             self.worker_task_sender
-                .send(WorkerTask::Start(
-                    actuator.clone(),
-                    apply_scalar_settings(speed, &self.settings[ i ]),
-                    is_pattern,
-                    self.handle,
-                ))
+                .send(match actuator.actuator {
+                    ActuatorType::Rotate => WorkerTask::Rotate(
+                        actuator.clone(),
+                        speed,
+                        self.clockwise,
+                        self.task_handle(),
+                        self.arbitration(i),
+                    ),
+                    _ => WorkerTask::Start(
+                        actuator.clone(),
+                        speed,
+                        self.task_handle(),
+                        self.arbitration(i),
+                    ),
+                })
                 .unwrap_or_else(|err| error!("queue err {:?}", err));
         }
     }
 
+    /// Ends the task on all actuators and waits until the worker handled it,
+    /// returns early if the worker stopped
     #[instrument(skip(self))]
-    async fn do_stop(mut self, is_pattern: bool) -> ButtplugClientResult {
+    async fn do_stop(&mut self) {
         for actuator in self.actuators.iter() {
             trace!("do_stop");
             self.worker_task_sender
                 .send(WorkerTask::End(
                     actuator.clone(),
-                    is_pattern,
-                    self.handle,
+                    self.task_handle(),
                     self.result_sender.clone(),
                 ))
                 .unwrap_or_else(|err| error!("queue err {:?}", err));
         }
-        let mut last_result = Ok(());
         for _ in self.actuators.iter() {
-            last_result = self.result_receiver.recv().await.unwrap();
+            tokio::select! {
+                _ = self.result_receiver.recv() => {}
+                _ = self.worker_task_sender.closed() => {
+                    debug!("worker stopped, not waiting for the stop");
+                    break;
+                }
+            }
+        }
+    }
+
+    /// Collects the errors of all commands that were sent so far
+    fn report(&mut self) -> TaskResult {
+        let mut report = TaskReport::default();
+        while let Ok(error) = self.error_receiver.try_recv() {
+            report.add(error);
+        }
+        if report.errors.is_empty() {
+            return Ok(());
+        }
+        error!(%report, "task failed");
+        Err(report)
+    }
+
+    fn task_handle(&self) -> TaskHandle {
+        TaskHandle {
+            id: self.handle,
+            errors: self.error_sender.clone(),
         }
-        last_result
     }
 
-    async fn do_linear(&mut self, mut pos: f64, duration_ms: u32) -> ButtplugClientResult {
+    /// Moves all actuators to 'pos', moves that exceed the max velocity of an actuator
+    /// are clipped. 'positions' holds the last position of each actuator
+    fn do_linear(&mut self, pos: f64, duration_ms: u32, positions: &mut [Option<f64>]) {
         for (i, actuator) in self.actuators.iter().enumerate() {
             let settings = &self.settings[ i ].linear_or_max();
-            pos = settings.apply_pos(pos);
+            let mut pos = scale_script_pos(settings.apply_pos(pos), *self.master_intensity.borrow());
+            if let Some(last) = positions[i] {
+                pos = settings.clip_pos(last, pos, duration_ms);
+            }
+            positions[i] = Some(pos);
             debug!(?duration_ms, ?pos, ?settings, "linear");
             self.worker_task_sender
                 .send(WorkerTask::Move(
@@ -233,56 +520,258 @@ impl PatternPlayer {
                     pos,
                     duration_ms,
                     true,
+                    self.task_handle(),
                     self.result_sender.clone(),
                 ))
                 .unwrap_or_else(|err| error!("queue err {:?}", err));
         }
-        sleep(Duration::from_millis(duration_ms as u64)).await;
-        self.result_receiver.recv().await.unwrap()
     }
 
-    async fn do_oscillate(&mut self, start: bool, mut speed: Speed, settings: &LinearRange) -> ButtplugClientResult {
-        let mut wait_ms = 0;
-        for (i, actuator) in self.actuators.iter().enumerate() {
-            let actual_settings = settings.merge(&self.settings[ i ].linear_or_max());
-            speed = actual_settings.scaling.apply(speed);
-            wait_ms = actual_settings.get_duration_ms(speed);
-            let target_pos = actual_settings.get_pos(start);
-            debug!(?wait_ms, ?target_pos, ?actual_settings, "stroke");
+    /// Stops all linear actuators at the position they reached
+    fn do_hold(&self) {
+        for actuator in self.actuators.iter() {
             self.worker_task_sender
-                .send(WorkerTask::Move(
-                    actuator.clone(),
-                    target_pos,
-                    wait_ms,
-                    true,
-                    self.result_sender.clone(),
-                ))
+                .send(WorkerTask::Hold(actuator.clone(), self.task_handle()))
                 .unwrap_or_else(|err| error!("queue err {:?}", err));
         }
-        // breaks with multiple devices that have different settings
-        sleep(Duration::from_millis(wait_ms as u64)).await;
-        self.result_receiver.recv().await.unwrap()
     }
 
+    /// Fades out from 'speed' to 0 over the release time of the stop or the envelope,
+    /// returns early if the player is aborted
+    async fn release(&self, speed: Speed) {
+        let release = self.release_receiver.borrow().unwrap_or(self.envelope.release());
+        let fade = Fade::new(speed, Speed::min(), release);
+        let resolution = Duration::from_millis(self.scalar_resolution_ms as u64);
+        while !fade.is_done() {
+            debug!(?fade, "releasing");
+            self.do_update(fade.speed());
+            tokio::select! {
+                _ = self.abort_token.cancelled() => break,
+                _ = sleep(resolution) => {}
+            }
+        }
+    }
+
+    /// Cancels the player after 'duration', the time spent paused is not counted
     fn stop_after(&self, duration: Duration) -> JoinHandle<()> {
+        self.expire_without_keep_alive();
+        self.stop_on_safety_violation();
         let cancellation_clone = self.cancellation_token.clone();
+        let mut pause = self.pause_receiver.clone();
         Handle::current().spawn(async move {
-            sleep(duration).await;
+            let mut remaining = duration;
+            loop {
+                let started = Instant::now();
+                tokio::select! {
+                    _ = sleep(remaining) => break,
+                    true = wait_for_pause(&mut pause, true) => {
+                        remaining = remaining.saturating_sub(started.elapsed());
+                        if !wait_for_pause(&mut pause, false).await {
+                            return;
+                        }
+                    }
+                }
+            }
             cancellation_clone.cancel();
         })
     }
 
-    fn try_update(&mut self, speed: &mut Speed) {
-        if let Ok(update) = self.update_receiver.try_recv() {
-            *speed = update;
+    /// Cancels the player when no keep-alive arrives within the keep-alive timeout,
+    /// the timeout also runs while the player is paused
+    fn expire_without_keep_alive(&self) {
+        let Some(timeout) = self.keep_alive else {
+            return;
+        };
+        let cancellation_clone = self.cancellation_token.clone();
+        let expired_clone = self.expired_token.clone();
+        let mut keep_alive = self.keep_alive_receiver.clone();
+        Handle::current().spawn(async move {
+            loop {
+                tokio::select! {
+                    _ = cancellation_clone.cancelled() => break,
+                    changed = keep_alive.changed() => {
+                        if changed.is_err() {
+                            break;
+                        }
+                    }
+                    _ = sleep(timeout) => {
+                        info!(?timeout, "no keep-alive received, stopping");
+                        expired_clone.cancel();
+                        cancellation_clone.cancel();
+                        break;
+                    }
+                }
+            }
+        });
+    }
+
+    /// Aborts the player when the worker stops it because of a safety limit
+    fn stop_on_safety_violation(&self) {
+        // subscribe before the first command is sent, so that no violation is missed
+        let mut violations = self.violation_sender.subscribe();
+        let handle = self.handle;
+        let cancellation_clone = self.cancellation_token.clone();
+        let abort_clone = self.abort_token.clone();
+        let violated_limit = self.violated_limit.clone();
+        Handle::current().spawn(async move {
+            loop {
+                tokio::select! {
+                    _ = cancellation_clone.cancelled() => break,
+                    violation = violations.recv() => match violation {
+                        Ok(violation) if violation.handles.contains(&handle) => {
+                            info!(limit=%violation.limit, "safety limit exceeded, stopping");
+                            violated_limit.send_replace(Some(violation.limit));
+                            abort_clone.cancel();
+                            break;
+                        }
+                        Ok(_) | Err(broadcast::error::RecvError::Lagged(_)) => {}
+                        Err(broadcast::error::RecvError::Closed) => break,
+                    }
+                }
+            }
+        });
+    }
+
+    /// Sends the intermediate values between `current` and `next` at the scalar resolution,
+    /// stops early if the player was paused or cancelled in the meantime
+    async fn interpolate(
+        &self,
+        current: &FSPoint,
+        next: &FSPoint,
+        speed: Speed,
+        loop_started: Instant,
+    ) -> Wait {
+        if self.interpolation == Interpolation::Step || next.at <= current.at {
+            return Wait::Elapsed;
+        }
+        let mut at = current.at + self.scalar_resolution_ms;
+        while at < next.at {
+            match Duration::from_millis(at as u64).checked_sub(loop_started.elapsed()) {
+                Some(waiting_time) => match self.wait(waiting_time).await {
+                    Wait::Elapsed => {}
+                    interrupted => return interrupted,
+                },
+                // skip values that are already outdated, i.e. after resuming
+                None if loop_started.elapsed().as_millis() as i32 > at + self.scalar_resolution_ms => {
+                    at += self.scalar_resolution_ms;
+                    continue;
+                }
+                None => {}
+            }
+            let progress = (at - current.at) as f64 / (next.at - current.at) as f64;
+            let pos = self
+                .interpolation
+                .interpolate(current.pos as f64, next.pos as f64, progress);
+            self.do_update(Speed::new(pos.round() as i64).multiply(&speed));
+            at += self.scalar_resolution_ms;
+        }
+        Wait::Elapsed
+    }
+
+    /// Waits for 'duration' unless the player is paused or cancelled before
+    async fn wait(&self, duration: Duration) -> Wait {
+        let mut pause = self.pause_receiver.clone();
+        tokio::select! {
+            _ = self.cancellation_token.cancelled() => Wait::Cancelled,
+            true = wait_for_pause(&mut pause, true) => Wait::Paused,
+            _ = sleep(duration) => Wait::Elapsed,
+        }
+    }
+
+    fn is_paused(&self) -> bool {
+        *self.pause_receiver.borrow()
+    }
+
+    /// Releases all actuators while the player is paused and waits until it is resumed,
+    /// returns the time spent paused, or None if the player was cancelled in the meantime
+    async fn wait_for_resume(&mut self) -> Option<Duration> {
+        info!("paused");
+        let paused_at = Instant::now();
+        self.do_stop().await;
+        let mut pause = self.pause_receiver.clone();
+        let resumed = tokio::select! {
+            _ = self.cancellation_token.cancelled() => None,
+            true = wait_for_pause(&mut pause, false) => Some(paused_at.elapsed()),
+            else => None,
+        };
+        info!("resumed");
+        resumed
+    }
+
+    fn arbitration(&self, actuator_index: usize) -> Arbitration {
+        Arbitration {
+            priority: self.priority,
+            blend_mode: self.settings[ actuator_index ].blend_mode(),
         }
     }
 
-    fn cancelled(&self) -> bool {
-        self.cancellation_token.is_cancelled()
+    fn try_update_direction(&mut self) {
+        if let Ok(true) = self.direction_receiver.has_changed() {
+            self.clockwise = *self.direction_receiver.borrow_and_update();
+        }
     }
 }
 
+impl Drop for PatternPlayer {
+    /// Marks the handle as finished, even if the task ended before its duration
+    fn drop(&mut self) {
+        self.cancellation_token.cancel();
+        // the worker may already be stopped
+        let _ = self.worker_task_sender.send(WorkerTask::Finish(self.handle));
+    }
+}
+
+/// Moves a single actuator up and down until the task is cancelled
+#[allow(clippy::too_many_arguments)]
+async fn oscillate(
+    actuator: Arc<Actuator>,
+    handle: TaskHandle,
+    settings: LinearRange,
+    speed: watch::Receiver<Speed>,
+    master_intensity: watch::Receiver<Speed>,
+    mut pause: watch::Receiver<bool>,
+    cancellation_token: CancellationToken,
+    worker_task_sender: UnboundedSender<WorkerTask>,
+) -> ButtplugClientResult {
+    let (result_sender, mut result_receiver) = unbounded_channel::<ButtplugClientResult>();
+    let mut move_up = true;
+    while !cancellation_token.is_cancelled() {
+        if *pause.borrow() {
+            tokio::select! {
+                _ = cancellation_token.cancelled() => break,
+                _ = wait_for_pause(&mut pause, false) => {}
+            }
+        }
+        let settings = settings.scale_stroke(*master_intensity.borrow());
+        let current_speed = settings.scaling.apply(*speed.borrow());
+        let stroke = settings.max_pos - settings.min_pos;
+        let wait_ms = settings
+            .get_duration_ms(current_speed)
+            .max(settings.min_duration_ms(stroke));
+        let target_pos = settings.get_pos(move_up);
+        debug!(?wait_ms, ?target_pos, ?settings, %actuator, "stroke");
+        worker_task_sender
+            .send(WorkerTask::Move(
+                actuator.clone(),
+                target_pos,
+                wait_ms,
+                true,
+                handle.clone(),
+                result_sender.clone(),
+            ))
+            .unwrap_or_else(|err| error!("queue err {:?}", err));
+        if !(cancellable_wait(Duration::from_millis(wait_ms as u64), &cancellation_token).await) {
+            break;
+        }
+        if let Some(Err(err)) = result_receiver.recv().await {
+            error!(%actuator, "oscillation stopped {:?}", err);
+            return Err(err);
+        }
+        move_up = !move_up;
+    }
+    Ok(())
+}
+
 impl LinearRange {
     fn merge(&self, settings: &LinearRange) -> LinearRange {   
         LinearRange {
@@ -298,6 +787,10 @@ impl LinearRange {
                 },
                 LinearSpeedScaling::Parabolic(n) =>  LinearSpeedScaling::Parabolic(n),
             },
+            max_velocity: match (self.max_velocity, settings.max_velocity) {
+                (0, velocity) | (velocity, 0) => velocity,
+                (a, b) => a.min(b),
+            },
         }
     }
     pub fn get_pos(&self, move_up: bool) -> f64 {
@@ -316,6 +809,16 @@ impl LinearRange {
     }
 }
 
+/// Scripts use the full stroke, the master intensity shrinks it towards the center
+fn scale_script_pos(pos: f64, intensity: Speed) -> f64 {
+    0.5 + (pos - 0.5) * intensity.as_float()
+}
+
+/// Position on the time axis of a script that started playing at `anchor_ms` on `anchor`
+fn script_time(anchor: Instant, anchor_ms: f64, rate: Speed) -> f64 {
+    anchor_ms + anchor.elapsed().as_secs_f64() * 1000.0 * rate.as_float()
+}
+
 fn apply_scalar_settings(speed: Speed, settings: &ActuatorSettings) -> Speed {
     if speed.value == 0 {
         return speed;
@@ -323,6 +826,7 @@ fn apply_scalar_settings(speed: Speed, settings: &ActuatorSettings) -> Speed {
     match settings {
         ActuatorSettings::Scalar(settings) => {
             trace!("applying {settings:?}");
+            let speed = settings.scaling.apply(speed);
             let speed = Speed::from_float(speed.as_float() * settings.factor);
             if speed.value < settings.min_speed as u16 {
                 Speed::new(settings.min_speed)
@@ -336,6 +840,13 @@ fn apply_scalar_settings(speed: Speed, settings: &ActuatorSettings) -> Speed {
     }
 }
 
+/// Outcome of waiting in a player
+enum Wait {
+    Elapsed,
+    Paused,
+    Cancelled,
+}
+
 impl fmt::Debug for PatternPlayer {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         f.debug_struct("PatternPlayer")
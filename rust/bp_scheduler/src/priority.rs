@@ -0,0 +1,85 @@
+use std::fmt::{self, Display};
+
+use serde::{Deserialize, Serialize};
+
+use crate::speed::Speed;
+
+/// Priority class of a task, used to arbitrate between tasks
+/// that access the same actuator
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Default)]
+pub enum Priority {
+    Background,
+    #[default]
+    Normal,
+    /// Masks all tasks with a lower priority, regardless of the blend mode
+    Override,
+}
+
+/// Defines how the speeds of concurrent tasks on one actuator are combined
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum BlendMode {
+    /// The fastest task wins
+    Max,
+    /// Speeds of all tasks are summed up and clamped to 100%
+    Additive,
+    /// The last started task wins
+    Latest,
+    /// The last started task of the highest priority class wins
+    #[default]
+    HighestPriority,
+}
+
+impl BlendMode {
+    /// Combines the `(handle, speed, priority)` of all tasks into a single speed,
+    /// also returns the handle of the task that dominates the result
+    pub fn blend(&self, tasks: &[(i32, Speed, Priority)]) -> Option<(i32, Speed)> {
+        let highest = tasks.iter().map(|t| t.2).max()?;
+        let considered = tasks
+            .iter()
+            .filter(|t| match self {
+                BlendMode::HighestPriority => t.2 == highest,
+                _ => highest != Priority::Override || t.2 == Priority::Override,
+            })
+            .collect::<Vec<&(i32, Speed, Priority)>>();
+        let latest = considered.iter().max_by_key(|t| t.0)?;
+        match self {
+            BlendMode::Max => considered
+                .iter()
+                .max_by_key(|t| (t.1.value, t.0))
+                .map(|t| (t.0, t.1)),
+            BlendMode::Additive => {
+                let sum = considered.iter().map(|t| t.1.value as i64).sum();
+                Some((latest.0, Speed::new(sum)))
+            }
+            BlendMode::Latest | BlendMode::HighestPriority => Some((latest.0, latest.1)),
+        }
+    }
+}
+
+impl Display for Priority {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Priority::Background => write!(f, "background"),
+            Priority::Normal => write!(f, "normal"),
+            Priority::Override => write!(f, "override"),
+        }
+    }
+}
+
+impl Display for BlendMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BlendMode::Max => write!(f, "max"),
+            BlendMode::Additive => write!(f, "additive"),
+            BlendMode::Latest => write!(f, "latest"),
+            BlendMode::HighestPriority => write!(f, "priority"),
+        }
+    }
+}
+
+/// Describes how a task is arbitrated against other tasks on an actuator
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Arbitration {
+    pub priority: Priority,
+    pub blend_mode: BlendMode,
+}
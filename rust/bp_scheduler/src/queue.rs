@@ -0,0 +1,393 @@
+use buttplug::client::{ButtplugClientError, LinearCommand, RotateCommand, ScalarCommand};
+use buttplug::core::{
+    connector::ButtplugConnectorError,
+    errors::{ButtplugDeviceError, ButtplugError},
+    message::ActuatorType,
+};
+use std::{
+    collections::{HashMap, HashSet},
+    sync::Arc,
+    time::Duration,
+};
+
+use tokio::{
+    runtime::Handle,
+    sync::{broadcast, mpsc::UnboundedSender},
+    time::Instant,
+};
+use tracing::{error, info, trace, warn};
+
+use crate::{
+    actuator::Actuator,
+    player::{ActuatorError, ActuatorRecovery},
+    settings::RetrySettings,
+    speed::Speed,
+    worker::{ButtplugClientResult, TaskHandle},
+};
+
+/// Value that is sent to a single actuator
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Command {
+    /// Speed and rotation direction, the direction is ignored by non-rotate actuators
+    Scalar(Speed, bool),
+    /// Position and duration in ms
+    Linear(f64, u32),
+}
+
+pub struct Pending {
+    actuator: Arc<Actuator>,
+    command: Command,
+    /// Receive the result of the command that is eventually sent
+    waiters: Vec<UnboundedSender<ButtplugClientResult>>,
+    /// Errors of all tasks whose commands were coalesced into this one
+    reporters: Vec<UnboundedSender<ActuatorError>>,
+    /// Number of times the command was sent again after a transient error
+    retries: u32,
+}
+
+/// Commands that were sent in the background, and their result
+pub type Completion = (Vec<Pending>, ButtplugClientResult);
+
+/// Coalesces the commands of all actuators before they are sent to the devices
+///
+/// Only the latest command of each actuator is kept, each device receives
+/// commands at most once per `min_interval`, and scalar values that are equal
+/// to the last sent value are skipped
+///
+/// Commands that fail with a transient error are queued again after a backoff,
+/// unless a newer command of the same actuator replaced them in the meantime
+pub struct CommandQueue {
+    min_interval: Duration,
+    retry: RetrySettings,
+    pending: HashMap<u32, HashMap<String, Pending>>,
+    last_sent: HashMap<String, Command>,
+    last_flush: HashMap<u32, Instant>,
+    /// Devices that may not receive commands before the backoff of a retry ends
+    retry_at: HashMap<u32, Instant>,
+    /// Actuators whose last command failed, even after all retries
+    failed: HashSet<String>,
+    /// Notified when a failed actuator accepts a command again
+    recovery_sender: broadcast::Sender<ActuatorRecovery>,
+    /// Results of commands that are sent in the background
+    completion_sender: UnboundedSender<Completion>,
+}
+
+impl CommandQueue {
+    pub fn new(
+        min_interval: Duration,
+        retry: RetrySettings,
+        recovery_sender: broadcast::Sender<ActuatorRecovery>,
+        completion_sender: UnboundedSender<Completion>,
+    ) -> Self {
+        CommandQueue {
+            min_interval,
+            retry,
+            pending: HashMap::new(),
+            last_sent: HashMap::new(),
+            last_flush: HashMap::new(),
+            retry_at: HashMap::new(),
+            failed: HashSet::new(),
+            recovery_sender,
+            completion_sender,
+        }
+    }
+
+    pub fn scalar(
+        &mut self,
+        actuator: &Arc<Actuator>,
+        speed: Speed,
+        clockwise: bool,
+        handle: Option<TaskHandle>,
+        waiter: Option<UnboundedSender<ButtplugClientResult>>,
+    ) {
+        self.push(actuator, Command::Scalar(speed, clockwise), handle, waiter);
+    }
+
+    pub fn linear(
+        &mut self,
+        actuator: &Arc<Actuator>,
+        position: f64,
+        duration_ms: u32,
+        handle: TaskHandle,
+        waiter: Option<UnboundedSender<ButtplugClientResult>>,
+    ) {
+        self.push(actuator, Command::Linear(position, duration_ms), Some(handle), waiter);
+    }
+
+    fn push(
+        &mut self,
+        actuator: &Arc<Actuator>,
+        command: Command,
+        handle: Option<TaskHandle>,
+        waiter: Option<UnboundedSender<ButtplugClientResult>>,
+    ) {
+        let pending = self
+            .pending
+            .entry(actuator.device.index())
+            .or_default()
+            .entry(actuator.identifier().into())
+            .or_insert_with(|| Pending {
+                actuator: actuator.clone(),
+                command,
+                waiters: vec![],
+                reporters: vec![],
+                retries: 0,
+            });
+        if pending.command != command {
+            trace!(?pending.command, ?command, "coalesced");
+        }
+        pending.command = command;
+        pending.waiters.extend(waiter);
+        if let Some(handle) = handle {
+            if !pending.reporters.iter().any(|x| x.same_channel(&handle.errors)) {
+                pending.reporters.push(handle.errors);
+            }
+        }
+    }
+
+    /// Point in time when the next device may receive its pending commands
+    pub fn next_flush(&self) -> Option<Instant> {
+        let now = Instant::now();
+        self.pending
+            .keys()
+            .map(|device| self.ready_at(*device).unwrap_or(now))
+            .min()
+    }
+
+    /// Returns None if the device never received a command and is not waiting for a retry
+    fn ready_at(&self, device: u32) -> Option<Instant> {
+        let next_interval = self
+            .last_flush
+            .get(&device)
+            .map(|last| *last + self.min_interval);
+        next_interval.max(self.retry_at.get(&device).copied())
+    }
+
+    /// Sends the pending commands of all devices that are not rate limited
+    pub async fn flush(&mut self) {
+        let now = Instant::now();
+        let ready = self
+            .pending
+            .keys()
+            .filter(|device| self.ready_at(**device).is_none_or(|at| at <= now))
+            .cloned()
+            .collect::<Vec<u32>>();
+        for device in ready {
+            self.retry_at.remove(&device);
+            let pending = self.pending.remove(&device).unwrap_or_default();
+            if self.send(pending.into_values().collect()).await {
+                self.last_flush.insert(device, now);
+            }
+        }
+    }
+
+    /// Sends all commands of a single device, each command type is batched
+    /// into a single message that covers all actuator indices of the device
+    ///
+    /// Returns false if all commands were skipped
+    async fn send(&mut self, pending: Vec<Pending>) -> bool {
+        let mut scalars = vec![];
+        let mut rotations = vec![];
+        let mut moves = vec![];
+        for pending in pending {
+            let id = pending.actuator.identifier();
+            match pending.command {
+                Command::Scalar(_, _) if self.last_sent.get(id) == Some(&pending.command) => {
+                    trace!(?pending.command, "skipped, equal to last value");
+                    notify(&pending.waiters, Ok(()));
+                }
+                Command::Scalar(_, _) if pending.actuator.actuator == ActuatorType::Rotate => {
+                    rotations.push(pending)
+                }
+                Command::Scalar(_, _) => scalars.push(pending),
+                Command::Linear(_, _) => moves.push(pending),
+            }
+        }
+        let sent = !(scalars.is_empty() && rotations.is_empty() && moves.is_empty());
+        if let Some(first) = scalars.first() {
+            let device = first.actuator.device.clone();
+            let cmd = ScalarCommand::ScalarMap(
+                scalars
+                    .iter()
+                    .map(|pending| match pending.command {
+                        Command::Scalar(speed, _) => (
+                            pending.actuator.index_in_device,
+                            (speed.as_float(), pending.actuator.actuator),
+                        ),
+                        Command::Linear(_, _) => unreachable!(),
+                    })
+                    .collect(),
+            );
+            let result = device.scalar(&cmd).await;
+            self.complete(scalars, result);
+        }
+        if let Some(first) = rotations.first() {
+            let device = first.actuator.device.clone();
+            let cmd = RotateCommand::RotateMap(
+                rotations
+                    .iter()
+                    .map(|pending| match pending.command {
+                        Command::Scalar(speed, clockwise) => (
+                            pending.actuator.index_in_device,
+                            (speed.as_float(), clockwise),
+                        ),
+                        Command::Linear(_, _) => unreachable!(),
+                    })
+                    .collect(),
+            );
+            let result = device.rotate(&cmd).await;
+            self.complete(rotations, result);
+        }
+        if let Some(first) = moves.first() {
+            let device = first.actuator.device.clone();
+            let cmd = LinearCommand::LinearMap(
+                moves
+                    .iter()
+                    .map(|pending| match pending.command {
+                        Command::Linear(position, duration_ms) => (
+                            pending.actuator.index_in_device,
+                            (duration_ms, position),
+                        ),
+                        Command::Scalar(_, _) => unreachable!(),
+                    })
+                    .collect(),
+            );
+            for pending in moves.iter() {
+                self.last_sent
+                    .insert(pending.actuator.identifier().into(), pending.command);
+            }
+            let completion_sender = self.completion_sender.clone();
+            Handle::current().spawn(async move {
+                let result = device.linear(&cmd).await;
+                // the worker may have stopped in the meantime
+                let _ = completion_sender.send((moves, result));
+            });
+        }
+        sent
+    }
+
+    /// Handles the result of sent commands, commands that failed with a transient
+    /// error are queued again until they run out of retries
+    pub fn complete(&mut self, sent: Vec<Pending>, result: ButtplugClientResult) {
+        for mut pending in sent {
+            let id = pending.actuator.identifier().to_owned();
+            match &result {
+                Ok(_) => {
+                    self.last_sent.insert(id.clone(), pending.command);
+                    if self.failed.remove(&id) {
+                        info!(actuator=%pending.actuator, "actuator recovered");
+                        // nobody listens if the scheduler is gone
+                        let _ = self.recovery_sender.send(ActuatorRecovery {
+                            actuator: pending.actuator.clone(),
+                            time: Instant::now(),
+                        });
+                    }
+                }
+                Err(err) if is_transient(err) && pending.retries < self.retry.max_retries => {
+                    self.last_sent.remove(&id);
+                    pending.retries += 1;
+                    let backoff = self.retry.backoff(pending.retries);
+                    warn!(actuator=%pending.actuator, ?err, ?backoff, "command failed, retrying");
+                    self.retry_at
+                        .insert(pending.actuator.device.index(), Instant::now() + backoff);
+                    self.requeue(pending);
+                    continue;
+                }
+                Err(err) => {
+                    error!(actuator=%pending.actuator, ?err, "command failed");
+                    self.last_sent.remove(&id);
+                    self.failed.insert(id);
+                    report(&pending, &result);
+                }
+            }
+            notify(&pending.waiters, result.as_ref().map_err(copy_error).copied());
+        }
+    }
+
+    /// Queues a failed command again, a newer command of the same actuator replaces it
+    fn requeue(&mut self, pending: Pending) {
+        let queued = self
+            .pending
+            .entry(pending.actuator.device.index())
+            .or_default();
+        match queued.get_mut(pending.actuator.identifier()) {
+            Some(newer) => {
+                trace!(?pending.command, ?newer.command, "retry replaced");
+                newer.waiters.extend(pending.waiters);
+                for reporter in pending.reporters {
+                    if !newer.reporters.iter().any(|x| x.same_channel(&reporter)) {
+                        newer.reporters.push(reporter);
+                    }
+                }
+            }
+            None => {
+                queued.insert(pending.actuator.identifier().into(), pending);
+            }
+        }
+    }
+
+    /// Drops all pending commands and forgets the last sent values
+    pub fn clear(&mut self) {
+        for (_, device) in self.pending.drain() {
+            for (_, pending) in device {
+                notify(&pending.waiters, Ok(()));
+            }
+        }
+        self.last_sent.clear();
+        self.retry_at.clear();
+    }
+}
+
+/// Reports a failed command to all tasks that sent it
+fn report(pending: &Pending, result: &ButtplugClientResult) {
+    let Err(err) = result else {
+        return;
+    };
+    for reporter in pending.reporters.iter() {
+        // the task may have ended in the meantime
+        let _ = reporter.send(ActuatorError {
+            actuator: pending.actuator.clone(),
+            error: copy_error(err),
+            time: Instant::now(),
+        });
+    }
+}
+
+fn notify(waiters: &[UnboundedSender<ButtplugClientResult>], result: ButtplugClientResult) {
+    for waiter in waiters {
+        if let Err(err) = waiter.send(result.as_ref().map_err(copy_error).copied()) {
+            error!("failed sending result {:?}", err)
+        }
+    }
+}
+
+/// Errors that might not occur again when the command is repeated
+fn is_transient(err: &ButtplugClientError) -> bool {
+    matches!(
+        err,
+        ButtplugClientError::ButtplugError(ButtplugError::ButtplugDeviceError(
+            ButtplugDeviceError::DeviceCommunicationError(_)
+                | ButtplugDeviceError::DeviceConnectionError(_)
+                | ButtplugDeviceError::DeviceSpecificError(_)
+                | ButtplugDeviceError::UntypedDeserializedError(_)
+        ))
+    )
+}
+
+/// Client errors are not `Clone`, transport specific errors are copied as generic errors
+fn copy_error(err: &ButtplugClientError) -> ButtplugClientError {
+    match err {
+        ButtplugClientError::ButtplugError(err) => ButtplugClientError::ButtplugError(err.clone()),
+        ButtplugClientError::ButtplugConnectorError(err) => {
+            ButtplugClientError::ButtplugConnectorError(match err {
+                ButtplugConnectorError::ConnectorNotConnected => {
+                    ButtplugConnectorError::ConnectorNotConnected
+                }
+                ButtplugConnectorError::ConnectorChannelClosed => {
+                    ButtplugConnectorError::ConnectorChannelClosed
+                }
+                other => ButtplugConnectorError::ConnectorGenericError(other.to_string()),
+            })
+        }
+    }
+}
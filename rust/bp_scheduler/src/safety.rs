@@ -0,0 +1,292 @@
+use std::{
+    collections::{HashMap, HashSet},
+    fmt::{self, Display},
+    sync::Arc,
+    time::Duration,
+};
+
+use tokio::{sync::broadcast, time::Instant};
+use tracing::{info, warn};
+
+use crate::{actuator::Actuator, settings::SafetyLimits, speed::Speed};
+
+/// Safety limit that caused tasks to be stopped
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SafetyLimit {
+    /// An actuator ran longer than `SafetyLimits::max_run_time_ms`
+    RunTime,
+    /// All actuators together ran longer than `SafetyLimits::max_session_time_ms`
+    SessionTime,
+    /// The task was started during the cooldown after another violation
+    Cooldown,
+}
+
+impl Display for SafetyLimit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SafetyLimit::RunTime => write!(f, "max run time"),
+            SafetyLimit::SessionTime => write!(f, "max session time"),
+            SafetyLimit::Cooldown => write!(f, "cooldown"),
+        }
+    }
+}
+
+/// Sent by the worker when it stops tasks because of a safety limit
+#[derive(Debug, Clone)]
+pub struct SafetyViolation {
+    pub limit: SafetyLimit,
+    pub handles: Vec<i32>,
+}
+
+/// Uninterrupted output of a single actuator
+struct Activity {
+    actuator: Arc<Actuator>,
+    since: Instant,
+    /// End of the last move of linear actuators, None for running scalar actuators
+    until: Option<Instant>,
+}
+
+/// Tracks the output of all actuators in the worker and reports actuators
+/// that exceed the safety limits
+///
+/// A violation only stops the tasks of the actuators that exceeded a limit,
+/// the cooldown and a used up session time refuse new tasks but let running
+/// tasks continue. After the session time was exceeded it starts over with
+/// the cooldown, without a cooldown it is never reset and all new tasks are
+/// refused until the worker is recreated, i.e. on reconnect
+pub struct SafetyMonitor {
+    limits: SafetyLimits,
+    active: HashMap<String, Activity>,
+    /// Session time that was accumulated before `session_since`
+    session_time: Duration,
+    /// Start of the current period in which any actuator was active
+    session_since: Option<Instant>,
+    cooldown_until: Option<Instant>,
+    /// Tasks that were allowed to start and did not finish yet
+    running: HashSet<i32>,
+    /// Tasks that were stopped, their remaining commands are ignored until they finish
+    stopped: HashSet<i32>,
+    violation_sender: broadcast::Sender<SafetyViolation>,
+}
+
+impl SafetyMonitor {
+    pub fn new(limits: SafetyLimits, violation_sender: broadcast::Sender<SafetyViolation>) -> Self {
+        SafetyMonitor {
+            limits,
+            active: HashMap::new(),
+            session_time: Duration::ZERO,
+            session_since: None,
+            cooldown_until: None,
+            running: HashSet::new(),
+            stopped: HashSet::new(),
+            violation_sender,
+        }
+    }
+
+    /// Returns false if the task may not send any output, tasks that try
+    /// to start during the cooldown or after the session is over are stopped
+    pub fn allows(&mut self, handle: i32) -> bool {
+        if self.stopped.contains(&handle) {
+            return false;
+        }
+        if self.running.contains(&handle) {
+            return true;
+        }
+        let session_limit = Duration::from_millis(self.limits.max_session_time_ms.into());
+        if !session_limit.is_zero() && self.session_time >= session_limit {
+            self.stop(SafetyLimit::SessionTime, vec![handle]);
+            return false;
+        }
+        if self.cooldown_until.is_some_and(|until| Instant::now() < until) {
+            self.stop(SafetyLimit::Cooldown, vec![handle]);
+            return false;
+        }
+        self.running.insert(handle);
+        true
+    }
+
+    /// Forgets a task that ended
+    pub fn finish(&mut self, handle: i32) {
+        self.running.remove(&handle);
+        self.stopped.remove(&handle);
+    }
+
+    /// Tracks the speed that is sent to a scalar actuator
+    pub fn scalar(&mut self, actuator: &Arc<Actuator>, speed: Speed) {
+        if !self.limits.is_enabled() {
+            return;
+        }
+        let now = Instant::now();
+        if speed.value == 0 {
+            if self.active.remove(actuator.identifier()).is_some() {
+                self.set_idle(now);
+            }
+            return;
+        }
+        self.active
+            .entry(actuator.identifier().into())
+            .or_insert_with(|| Activity {
+                actuator: actuator.clone(),
+                since: now,
+                until: None,
+            });
+        self.session_since.get_or_insert(now);
+    }
+
+    /// Tracks a move of a linear actuator, it is active until the move ends
+    pub fn linear(&mut self, actuator: &Arc<Actuator>, duration_ms: u32) {
+        if !self.limits.is_enabled() {
+            return;
+        }
+        let now = Instant::now();
+        self.expire(now);
+        let until = now + Duration::from_millis(duration_ms.into());
+        self.active
+            .entry(actuator.identifier().into())
+            .or_insert_with(|| Activity {
+                actuator: actuator.clone(),
+                since: now,
+                until: None,
+            })
+            .until = Some(until);
+        self.session_since.get_or_insert(now);
+    }
+
+    /// Point in time when the next limit would be exceeded
+    pub fn next_check(&self) -> Option<Instant> {
+        let run_time = Duration::from_millis(self.limits.max_run_time_ms.into());
+        let session_time = Duration::from_millis(self.limits.max_session_time_ms.into());
+        let run_time_ends = self
+            .active
+            .values()
+            .filter(|_| !run_time.is_zero())
+            .map(|activity| activity.since + run_time);
+        let session_ends = self
+            .session_since
+            .filter(|_| !session_time.is_zero())
+            .map(|since| since + session_time.saturating_sub(self.session_time));
+        run_time_ends.chain(session_ends).min()
+    }
+
+    /// Returns the actuators that exceeded a limit, they are no longer tracked
+    /// and the cooldown starts
+    pub fn check(&mut self) -> Vec<(SafetyLimit, Vec<Arc<Actuator>>)> {
+        let now = Instant::now();
+        self.expire(now);
+        let mut violations = vec![];
+        let session_limit = Duration::from_millis(self.limits.max_session_time_ms.into());
+        let session_time = self.session_time
+            + self
+                .session_since
+                .map(|since| now.saturating_duration_since(since))
+                .unwrap_or_default();
+        if !session_limit.is_zero() && session_time >= session_limit {
+            let actuators = self.active.drain().map(|(_, x)| x.actuator).collect();
+            violations.push((SafetyLimit::SessionTime, actuators));
+            self.session_since = None;
+            // the cooldown starts a new session, without a cooldown the session is over
+            self.session_time = match self.limits.cooldown_ms {
+                0 => session_time,
+                _ => Duration::ZERO,
+            };
+        }
+        let run_time_limit = Duration::from_millis(self.limits.max_run_time_ms.into());
+        if !run_time_limit.is_zero() {
+            let exceeded = self
+                .active
+                .iter()
+                .filter(|(_, x)| now.saturating_duration_since(x.since) >= run_time_limit)
+                .map(|(id, _)| id.clone())
+                .collect::<Vec<String>>();
+            if !exceeded.is_empty() {
+                let actuators = exceeded
+                    .iter()
+                    .filter_map(|id| self.active.remove(id))
+                    .map(|x| x.actuator)
+                    .collect();
+                violations.push((SafetyLimit::RunTime, actuators));
+                self.set_idle(now);
+            }
+        }
+        if !violations.is_empty() && self.limits.cooldown_ms > 0 {
+            let cooldown = Duration::from_millis(self.limits.cooldown_ms.into());
+            info!(?cooldown, "safety cooldown started");
+            self.cooldown_until = Some(now + cooldown);
+        }
+        violations
+    }
+
+    /// Stops the tasks with the given handles
+    pub fn stop(&mut self, limit: SafetyLimit, handles: Vec<i32>) {
+        for handle in handles.iter() {
+            self.running.remove(handle);
+        }
+        self.stopped.extend(handles.iter());
+        if handles.is_empty() {
+            return;
+        }
+        warn!(%limit, ?handles, "safety limit exceeded, stopping");
+        // nobody listens if no player is alive
+        let _ = self.violation_sender.send(SafetyViolation { limit, handles });
+    }
+
+    /// Forgets the output of all actuators, the session time is kept
+    pub fn clear(&mut self) {
+        self.active.clear();
+        self.set_idle(Instant::now());
+    }
+
+    /// Removes linear actuators whose last move ended
+    fn expire(&mut self, now: Instant) {
+        let ended = self
+            .active
+            .iter()
+            .filter_map(|(id, x)| x.until.filter(|until| *until <= now).map(|until| (id.clone(), until)))
+            .collect::<Vec<(String, Instant)>>();
+        for (id, _) in ended.iter() {
+            self.active.remove(id);
+        }
+        if let Some(last) = ended.iter().map(|(_, until)| *until).max() {
+            self.set_idle(last);
+        }
+    }
+
+    /// Pauses the session time if no actuator is active anymore
+    fn set_idle(&mut self, at: Instant) {
+        if !self.active.is_empty() {
+            return;
+        }
+        if let Some(since) = self.session_since.take() {
+            self.session_time += at.saturating_duration_since(since);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::sync::broadcast;
+
+    use crate::settings::SafetyLimits;
+
+    use super::{SafetyLimit, SafetyMonitor};
+
+    #[tokio::test(start_paused = true)]
+    async fn finished_tasks_are_forgotten() {
+        let (violation_sender, _) = broadcast::channel(1);
+        let limits = SafetyLimits {
+            max_run_time_ms: 100,
+            ..Default::default()
+        };
+        let mut monitor = SafetyMonitor::new(limits, violation_sender);
+
+        assert!(monitor.allows(1));
+        assert!(monitor.allows(2));
+        monitor.stop(SafetyLimit::RunTime, vec![1]);
+        assert!(!monitor.allows(1));
+        monitor.finish(1);
+        monitor.finish(2);
+
+        assert!(monitor.running.is_empty());
+        assert!(monitor.stopped.is_empty());
+    }
+}
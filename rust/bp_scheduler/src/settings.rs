@@ -1,13 +1,63 @@
+use std::{
+    fmt::{self, Display},
+    time::Duration,
+};
+
 use serde::{Deserialize, Serialize};
 
-use crate::speed::Speed;
+use crate::{priority::BlendMode, speed::Speed};
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub enum ScalarScaling {
-    // Note: currently unused
     Linear,            // f(x) = x
     Quadratic,         // f(x) = x^2
     QuadraticFraction, // f(x) = x^(1/2)
+    /// Piecewise-linear curve through the `(input, output)` percentages,
+    /// inputs outside of the points use the output of the nearest point.
+    /// Points are sorted by their unique inputs, see `read_scalar_scaling`
+    Custom(Vec<(u16, u16)>),
+}
+
+impl ScalarScaling {
+    pub fn apply(&self, speed: Speed) -> Speed {
+        let x = speed.as_float();
+        match self {
+            ScalarScaling::Linear => speed,
+            ScalarScaling::Quadratic => Speed::from_float(x.powi(2)),
+            ScalarScaling::QuadraticFraction => Speed::from_float(x.sqrt()),
+            ScalarScaling::Custom(points) => {
+                match points.iter().position(|p| p.0 >= speed.value) {
+                    None => points.last().map(|p| Speed::new(p.1.into())).unwrap_or(speed),
+                    Some(0) => Speed::new(points[0].1.into()),
+                    Some(i) => {
+                        let (x0, y0) = (points[i - 1].0 as f64, points[i - 1].1 as f64);
+                        let (x1, y1) = (points[i].0 as f64, points[i].1 as f64);
+                        let y = y0 + (y1 - y0) * (speed.value as f64 - x0) / (x1 - x0);
+                        Speed::new(y.round() as i64)
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl Display for ScalarScaling {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ScalarScaling::Linear => write!(f, "linear"),
+            ScalarScaling::Quadratic => write!(f, "quadratic"),
+            ScalarScaling::QuadraticFraction => write!(f, "quadraticfraction"),
+            ScalarScaling::Custom(points) => write!(
+                f,
+                "{}",
+                points
+                    .iter()
+                    .map(|(x, y)| format!("{}:{}", x, y))
+                    .collect::<Vec<String>>()
+                    .join(",")
+            ),
+        }
+    }
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -16,6 +66,8 @@ pub struct ScalarRange {
     pub max_speed: i64,
     pub factor: f64,
     pub scaling: ScalarScaling,
+    #[serde(default)]
+    pub blend_mode: BlendMode,
 }
 
 impl Default for ScalarRange {
@@ -25,6 +77,7 @@ impl Default for ScalarRange {
             max_speed: 100,
             factor: 1.0,
             scaling: ScalarScaling::Linear,
+            blend_mode: BlendMode::default(),
         }
     }
 }
@@ -38,6 +91,13 @@ pub enum ActuatorSettings {
 }
 
 impl ActuatorSettings {
+    pub fn blend_mode(&self) -> BlendMode {
+        if let ActuatorSettings::Scalar(settings) = self {
+            return settings.blend_mode;
+        }
+        BlendMode::default()
+    }
+
     pub fn linear_or_max(&self) -> LinearRange {
         if let ActuatorSettings::Linear(settings) = self {
             return settings.clone();
@@ -73,6 +133,9 @@ pub struct LinearRange {
     pub max_pos: f64,
     pub invert: bool,
     pub scaling: LinearSpeedScaling,
+    /// Fastest allowed movement in percent of the full stroke per second, 0 = unlimited
+    #[serde(default)]
+    pub max_velocity: u32,
 }
 
 impl LinearRange {
@@ -84,6 +147,37 @@ impl LinearRange {
             max_pos: 1.0,
             invert: false,
             scaling: LinearSpeedScaling::Linear,
+            max_velocity: 0,
+        }
+    }
+
+    /// Shortest duration of a move over 'distance' that does not exceed the max velocity
+    pub fn min_duration_ms(&self, distance: f64) -> u32 {
+        if self.max_velocity == 0 {
+            return 0;
+        }
+        (distance.abs() * 100_000.0 / self.max_velocity as f64).round() as u32
+    }
+
+    /// Clips a move from 'from' to 'to' that is too fast, so that it ends at the
+    /// farthest position that can be reached within 'duration_ms'
+    pub fn clip_pos(&self, from: f64, to: f64, duration_ms: u32) -> f64 {
+        if self.max_velocity == 0 {
+            return to;
+        }
+        let max_distance = self.max_velocity as f64 * duration_ms as f64 / 100_000.0;
+        from + (to - from).clamp(-max_distance, max_distance)
+    }
+
+    /// Shrinks the stroke towards its center, so that the master intensity also
+    /// limits linear movements without leaving the configured range
+    pub fn scale_stroke(&self, intensity: Speed) -> LinearRange {
+        let center = (self.min_pos + self.max_pos) / 2.0;
+        let half = (self.max_pos - self.min_pos) / 2.0 * intensity.as_float();
+        LinearRange {
+            min_pos: center - half,
+            max_pos: center + half,
+            ..self.clone()
         }
     }
 }
@@ -96,6 +190,53 @@ impl Default for LinearRange {
             max_pos: 1.0,
             invert: false,
             scaling: LinearSpeedScaling::Linear,
+            max_velocity: 0,
+        }
+    }
+}
+
+/// Limits that protect against devices running unattended, all times are in ms
+/// and 0 disables the limit
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SafetyLimits {
+    /// Longest time a single actuator may run without interruption
+    pub max_run_time_ms: u32,
+    /// Longest total time any actuator may run until the next cooldown, without
+    /// a cooldown all new tasks are refused once the session time is used up,
+    /// until the next reconnect
+    pub max_session_time_ms: u32,
+    /// Time after a violated limit during which new tasks are refused, tasks that
+    /// are already running continue
+    pub cooldown_ms: u32,
+}
+
+impl SafetyLimits {
+    pub fn is_enabled(&self) -> bool {
+        self.max_run_time_ms > 0 || self.max_session_time_ms > 0
+    }
+}
+
+/// Retries of device commands that failed with a transient error
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RetrySettings {
+    /// Number of retries after the first failure, 0 disables retries
+    pub max_retries: u32,
+    /// Delay before the first retry in ms, it doubles with every further retry
+    pub backoff_ms: u32,
+}
+
+impl Default for RetrySettings {
+    fn default() -> Self {
+        RetrySettings {
+            max_retries: 3,
+            backoff_ms: 100,
         }
     }
 }
+
+impl RetrySettings {
+    /// Delay before the given retry, starting at 1
+    pub fn backoff(&self, retry: u32) -> Duration {
+        Duration::from_millis(self.backoff_ms.into()) * 2_u32.saturating_pow(retry.saturating_sub(1))
+    }
+}
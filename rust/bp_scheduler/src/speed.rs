@@ -2,7 +2,7 @@ use std::fmt::{Display, self};
 
 use funscript::FSPoint;
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct Speed {
     pub value: u16,
 }
@@ -14,17 +14,21 @@ impl Display for Speed {
 }
 
 impl Speed {
-    pub fn new(mut percentage: i64) -> Speed {
-        if percentage < 0 {
-            percentage = 0;
-        }
-        if percentage > 100 {
-            percentage = 100;
+    pub fn new(percentage: i64) -> Speed {
+        Speed {
+            value: percentage.clamp(0, 100) as u16,
         }
+    }
+    /// Speed that is not capped at 100%, i.e. the playback rate of a linear pattern
+    pub fn new_unbounded(percentage: i64) -> Speed {
         Speed {
-            value: percentage as u16,
+            value: percentage.clamp(0, u16::MAX.into()) as u16,
         }
     }
+    /// Caps speeds created with `new_unbounded` to 100%
+    pub fn bounded(self) -> Speed {
+        Speed::new(self.value.into())
+    }
     pub fn from_float(factor: f64) -> Speed {
         Speed::new((factor * 100.0) as i64)    
     }
@@ -1,11 +1,28 @@
-use buttplug::client::{LinearCommand, ButtplugClientError};
-use std::{collections::HashMap, fmt, sync::Arc, time::Duration};
+use buttplug::{client::ButtplugClientError, core::message::ActuatorType};
+use std::{sync::Arc, time::Duration};
 
-use tokio::{runtime::Handle, sync::mpsc::UnboundedReceiver};
-use tracing::{error, info, trace, warn};
+use tokio::{
+    runtime::Handle,
+    sync::{
+        broadcast,
+        mpsc::{unbounded_channel, UnboundedReceiver},
+        watch,
+    },
+    time::{sleep_until, Instant},
+};
+use tracing::{error, info, trace};
 use tokio::sync::mpsc::UnboundedSender;
 
-use crate::{access::DeviceAccess, actuator::Actuator, speed::Speed};
+use crate::{
+    access::DeviceAccess,
+    actuator::Actuator,
+    player::{ActuatorError, ActuatorRecovery},
+    priority::Arbitration,
+    queue::{CommandQueue, Completion},
+    safety::{SafetyMonitor, SafetyViolation},
+    settings::{RetrySettings, SafetyLimits},
+    speed::Speed,
+};
 
 pub type ButtplugClientResult<T = ()> = Result<T, ButtplugClientError>;
 
@@ -16,71 +33,263 @@ pub type ButtplugClientResult<T = ()> = Result<T, ButtplugClientError>;
 /// its not necessary to introduce Mutex/etc to handle multithreaded access
 pub struct ButtplugWorker {
     pub task_receiver: UnboundedReceiver<WorkerTask>,
+    /// Minimum time between two commands to the same device
+    pub min_command_interval: Duration,
+    /// See `PlayerSettings::handover_velocity`
+    pub handover_velocity: u32,
+    /// See `ButtplugScheduler::set_master_intensity`
+    pub master_intensity: watch::Receiver<Speed>,
+    pub safety_limits: SafetyLimits,
+    /// Notifies the players of tasks that were stopped by a safety limit
+    pub violation_sender: broadcast::Sender<SafetyViolation>,
+    pub retry: RetrySettings,
+    /// See `ButtplugScheduler::subscribe_recoveries`
+    pub recovery_sender: broadcast::Sender<ActuatorRecovery>,
+}
+
+/// Identifies the task that sent a worker task, commands that fail are reported to it
+#[derive(Clone, Debug)]
+pub struct TaskHandle {
+    pub id: i32,
+    pub errors: UnboundedSender<ActuatorError>,
 }
 
 #[derive(Clone, Debug)]
 pub enum WorkerTask {
-    Start(Arc<Actuator>, Speed, bool, i32),
-    Update(Arc<Actuator>, Speed, bool, i32),
+    Start(Arc<Actuator>, Speed, TaskHandle, Arbitration),
+    Update(Arc<Actuator>, Speed, TaskHandle),
+    /// Starts or updates a rotation, with an additional clockwise flag
+    Rotate(Arc<Actuator>, Speed, bool, TaskHandle, Arbitration),
     End(
         Arc<Actuator>,
-        bool,
-        i32,
+        TaskHandle,
         UnboundedSender<ButtplugClientResult>,
     ),
+    /// Moves to a position within a duration in ms, for the task with the given handle
     Move(
         Arc<Actuator>,
         f64,
         u32,
         bool,
+        TaskHandle,
         UnboundedSender<ButtplugClientResult>,
     ),
+    /// Stops a linear actuator at its current position, for the task with the given handle
+    Hold(Arc<Actuator>, TaskHandle),
+    /// The task with the given handle ended and sends no further commands
+    Finish(i32),
     StopAll, // global but required for resetting device state
+    /// Sets all running scalar actuators to 0 and stops the worker
+    Shutdown,
 }
 
 impl ButtplugWorker {
     pub async fn run_worker_thread(&mut self) {
-        let mut device_access = DeviceAccess::default();
+        let (completion_sender, mut completion_receiver) = unbounded_channel::<Completion>();
+        let mut state = WorkerState {
+            device_access: DeviceAccess::new(self.handover_velocity),
+            queue: CommandQueue::new(
+                self.min_command_interval,
+                self.retry,
+                self.recovery_sender.clone(),
+                completion_sender,
+            ),
+            safety: SafetyMonitor::new(self.safety_limits, self.violation_sender.clone()),
+            master_intensity: *self.master_intensity.borrow_and_update(),
+            shutdown: false,
+        };
         loop {
-            if let Some(next_action) = self.task_receiver.recv().await {
-                trace!("worker exec action {:?}", next_action);
-                match next_action {
-                    WorkerTask::Start(actuator, speed, is_pattern, handle) => {
-                        device_access
-                            .start_scalar(&actuator, speed, is_pattern, handle)
-                            .await;
+            let next_flush = state.queue.next_flush();
+            let next_check = state.safety.next_check();
+            tokio::select! {
+                next_action = self.task_receiver.recv() => {
+                    match next_action {
+                        Some(next_action) => state.execute(next_action),
+                        None => break,
                     }
-                    WorkerTask::Update(actuator, speed, is_pattern, handle) => {
-                        device_access.update_scalar(&actuator, speed, is_pattern, handle).await;
+                }
+                Some((sent, result)) = completion_receiver.recv() => {
+                    state.queue.complete(sent, result);
+                }
+                Ok(_) = self.master_intensity.changed() => {
+                    state.set_master_intensity(*self.master_intensity.borrow_and_update());
+                }
+                _ = sleep_until(next_flush.unwrap_or_else(Instant::now)), if next_flush.is_some() => {}
+                _ = sleep_until(next_check.unwrap_or_else(Instant::now)), if next_check.is_some() => {}
+            }
+            // coalesce everything that was queued in the meantime
+            while let Ok(next_action) = self.task_receiver.try_recv() {
+                state.execute(next_action);
+            }
+            state.enforce_safety_limits();
+            state.queue.flush().await;
+            // rate limited and retried commands are sent before stopping
+            if state.shutdown && state.queue.next_flush().is_none() {
+                break;
+            }
+        }
+        // players that still wait for results stop waiting
+        self.task_receiver.close();
+        info!("worker stopped");
+    }
+}
+
+struct WorkerState {
+    device_access: DeviceAccess,
+    queue: CommandQueue,
+    safety: SafetyMonitor,
+    master_intensity: Speed,
+    /// Set after the final commands were queued, the worker stops after all of them were sent
+    shutdown: bool,
+}
+
+impl WorkerState {
+    fn execute(&mut self, next_action: WorkerTask) {
+        trace!("worker exec action {:?}", next_action);
+        match next_action {
+            WorkerTask::Start(actuator, speed, handle, arbitration) => {
+                if !self.safety.allows(handle.id) {
+                    return;
+                }
+                let (speed, clockwise) =
+                    self.device_access.start_scalar(&actuator, speed, handle.id, arbitration);
+                self.scalar(&actuator, speed, clockwise, Some(handle), None);
+            }
+            WorkerTask::Update(actuator, speed, handle) => {
+                if !self.safety.allows(handle.id) {
+                    return;
+                }
+                let (speed, clockwise) =
+                    self.device_access.update_scalar(&actuator, speed, handle.id);
+                self.scalar(&actuator, speed, clockwise, Some(handle), None);
+            }
+            WorkerTask::Rotate(actuator, speed, clockwise, handle, arbitration) => {
+                if !self.safety.allows(handle.id) {
+                    return;
+                }
+                let (speed, clockwise) = self.device_access.rotate(
+                    &actuator,
+                    speed,
+                    clockwise,
+                    handle.id,
+                    arbitration,
+                );
+                self.scalar(&actuator, speed, clockwise, Some(handle), None);
+            }
+            WorkerTask::End(actuator, handle, result_sender) => {
+                match self.device_access.stop_scalar(&actuator, handle.id) {
+                    Some((speed, clockwise)) => {
+                        self.scalar(&actuator, speed, clockwise, Some(handle), Some(result_sender))
                     }
-                    WorkerTask::End(actuator, is_pattern, handle, result_sender) => {
-                        let result = device_access
-                            .stop_scalar(&actuator, is_pattern, handle)
-                            .await;
-                        if let Err(err) = result_sender.send(result) {
+                    None => {
+                        if let Err(err) = result_sender.send(Ok(())) {
                             error!("failed sending scalar result {:?}", err)
                         }
                     }
-                    WorkerTask::Move(actuator, position, duration_ms, finish, result_sender) => {
-                        let cmd = LinearCommand::LinearMap(HashMap::from([(
-                            actuator.index_in_device,
-                            (duration_ms, position),
-                        )]));
-                        Handle::current().spawn(async move {
-                            let result = actuator.device.linear(&cmd).await;
-                            if finish {
-                                if let Err(err) = result_sender.send(result) {
-                                    error!("failed sending linear result {:?}", err)
-                                }
-                            }
-                        });
+                }
+            }
+            WorkerTask::Move(actuator, position, duration_ms, finish, handle, result_sender) => {
+                if !self.safety.allows(handle.id) {
+                    if finish {
+                        let _ = result_sender.send(Ok(()));
                     }
-                    WorkerTask::StopAll => {
-                        device_access.clear_all();
-                        info!("stop all action");
+                    return;
+                }
+                let move_ms =
+                    self.device_access.move_linear(&actuator, position, duration_ms, handle.id);
+                self.safety.linear(&actuator, move_ms);
+                let waiter = finish.then(|| match move_ms > duration_ms {
+                    true => wait_for_move(result_sender, Duration::from_millis(move_ms.into())),
+                    false => result_sender,
+                });
+                self.queue.linear(&actuator, position, move_ms, handle, waiter);
+            }
+            WorkerTask::Hold(actuator, handle) => {
+                if !self.safety.allows(handle.id) {
+                    return;
+                }
+                if let Some(position) = self.device_access.hold_linear(&actuator, handle.id) {
+                    self.safety.linear(&actuator, 0);
+                    self.queue.linear(&actuator, position, 0, handle, None);
+                }
+            }
+            WorkerTask::Finish(handle) => {
+                self.safety.finish(handle);
+            }
+            WorkerTask::StopAll => {
+                self.device_access.clear_all();
+                self.queue.clear();
+                self.safety.clear();
+                info!("stop all action");
+            }
+            WorkerTask::Shutdown => {
+                for (actuator, _, _) in self.device_access.scalars() {
+                    self.queue.scalar(&actuator, Speed::min(), true, None, None);
+                }
+                self.device_access.clear_all();
+                self.safety.clear();
+                self.shutdown = true;
+                info!("shutdown");
+            }
+        }
+    }
+
+    /// Queues the speed of a scalar actuator, scaled by the master intensity
+    fn scalar(
+        &mut self,
+        actuator: &Arc<Actuator>,
+        speed: Speed,
+        clockwise: bool,
+        handle: Option<TaskHandle>,
+        waiter: Option<UnboundedSender<ButtplugClientResult>>,
+    ) {
+        let speed = speed.multiply(&self.master_intensity);
+        self.safety.scalar(actuator, speed);
+        self.queue.scalar(actuator, speed, clockwise, handle, waiter);
+    }
+
+    /// Applies the new master intensity to all running scalar actuators
+    fn set_master_intensity(&mut self, master_intensity: Speed) {
+        info!(%master_intensity, "master intensity changed");
+        self.master_intensity = master_intensity;
+        for (actuator, speed, clockwise) in self.device_access.scalars() {
+            self.scalar(&actuator, speed, clockwise, None, None);
+        }
+    }
+
+    /// Stops all actuators that exceeded a safety limit, and the tasks that used them
+    fn enforce_safety_limits(&mut self) {
+        for (limit, actuators) in self.safety.check() {
+            let mut handles = vec![];
+            for actuator in actuators {
+                for handle in self.device_access.release(&actuator) {
+                    if !handles.contains(&handle) {
+                        handles.push(handle);
                     }
                 }
+                if actuator.actuator != ActuatorType::Position {
+                    self.queue.scalar(&actuator, Speed::min(), true, None, None);
+                }
             }
+            self.safety.stop(limit, handles);
         }
     }
-}
\ No newline at end of file
+}
+
+/// The player only waits for the duration it requested, holds back the result of a
+/// move that was slowed down until the actuator arrived, so the next move does not
+/// replace it early
+fn wait_for_move(
+    result_sender: UnboundedSender<ButtplugClientResult>,
+    duration: Duration,
+) -> UnboundedSender<ButtplugClientResult> {
+    let arrival = Instant::now() + duration;
+    let (sender, mut receiver) = unbounded_channel::<ButtplugClientResult>();
+    Handle::current().spawn(async move {
+        if let Some(result) = receiver.recv().await {
+            sleep_until(arrival).await;
+            let _ = result_sender.send(result);
+        }
+    });
+    sender
+}
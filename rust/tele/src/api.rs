@@ -68,9 +68,11 @@ pub struct ApiQryBool1<State> {
 }
 declare_api_cmd!(ApiQryBool1);
 
+/// Controls accept an optional comma separated list of options
+/// appended to their name, i.e. `vibrate:override`
 pub struct ApiControl<State> {
     pub name: &'static str,
-    pub exec: fn(&mut State, i32, f32, &str, &CxxVector<CxxString>) -> i32,
+    pub exec: fn(&mut State, i32, f32, &str, &CxxVector<CxxString>, &str) -> i32,
     pub default: i32,
 }
 declare_api_cmd!(ApiControl);
@@ -80,6 +82,18 @@ pub struct ApiUpdate<State> {
 }
 
 pub struct ApiStop<State> {
+    pub exec: fn(&mut State, i32, f32) -> bool,
+}
+
+pub struct ApiPause<State> {
+    pub exec: fn(&mut State, i32) -> bool,
+}
+
+pub struct ApiResume<State> {
+    pub exec: fn(&mut State, i32) -> bool,
+}
+
+pub struct ApiKeepAlive<State> {
     pub exec: fn(&mut State, i32) -> bool,
 }
 
@@ -122,6 +136,9 @@ pub struct ApiBuilder<T> {
     pub control: Vec<ApiControl<T>>,
     pub update: ApiUpdate<T>,
     pub stop: ApiStop<T>,
+    pub pause: ApiPause<T>,
+    pub resume: ApiResume<T>,
+    pub keep_alive: ApiKeepAlive<T>,
 }
 
 impl<T> ApiBuilder<T> {
@@ -139,7 +156,10 @@ impl<T> ApiBuilder<T> {
             qry_bool_1: vec![],
             control: vec![],
             update: ApiUpdate { exec: |_,_,_| false },
-            stop: ApiStop { exec: |_,_| true },
+            stop: ApiStop { exec: |_,_,_| true },
+            pause: ApiPause { exec: |_,_| false },
+            resume: ApiResume { exec: |_,_| false },
+            keep_alive: ApiKeepAlive { exec: |_,_| false },
         }
     }
     pub fn def_cmd(mut self, cmd: ApiCmd0<T>) -> Self {
@@ -166,6 +186,18 @@ impl<T> ApiBuilder<T> {
         self.stop = cmd;
         self
     }
+    pub fn def_pause(mut self, cmd: ApiPause<T>) -> Self {
+        self.pause = cmd;
+        self
+    }
+    pub fn def_resume(mut self, cmd: ApiResume<T>) -> Self {
+        self.resume = cmd;
+        self
+    }
+    pub fn def_keep_alive(mut self, cmd: ApiKeepAlive<T>) -> Self {
+        self.keep_alive = cmd;
+        self
+    }
     pub fn def_qry_str(mut self, cmd: ApiQryStr<T>) -> Self {
         self.qry_str.push(cmd);
         self
@@ -213,12 +245,19 @@ pub trait Api<T> {
         }
 
         if cmd_matches(self.destroy().name, cmd) {
-            if let Ok(mut guard) = self.state().try_lock() {
-                if guard.take().is_some() {
-                    let api: ApiCmd0<T> = self.destroy();
-                    return self.try_exec(|tk| (api.exec)(tk), false);
-                }
+            // the state is taken out before it is destroyed, so that other calls
+            // fail right away instead of waiting for the lock, and dropped afterwards
+            let state = match self.state().try_lock() {
+                Ok(mut guard) => guard.take(),
+                Err(_) => None,
+            };
+            if let Some(mut state) = state {
+                let api: ApiCmd0<T> = self.destroy();
+                let result = (api.exec)(&mut state);
+                debug!("result: {:?}", result);
+                return result;
             }
+            return false;
         }
 
         if let Some(api) = self.get_qry(self.fns().cmd, cmd) {
@@ -292,8 +331,9 @@ pub trait Api<T> {
         arg3: &CxxVector<CxxString>,
     ) -> i32 {
         let c = self.fns().control;
-        if let Some(api) = self.get_qry(c, qry) {
-            return self.try_exec(|tk| (api.exec)(tk, arg0, arg1, arg2, arg3), -1);
+        let (name, options) = qry.split_once(':').unwrap_or((qry, ""));
+        if let Some(api) = self.get_qry(c, name) {
+            return self.try_exec(|tk| (api.exec)(tk, arg0, arg1, arg2, arg3, options), -1);
         }
         self.fail_dispatch(-1)
     }
@@ -303,8 +343,23 @@ pub trait Api<T> {
         self.try_exec(|tk| (update.exec)(tk, arg0, arg1), false)
     }
 
-    fn exec_stop(&mut self, arg0: i32) -> bool {
+    fn exec_stop(&mut self, arg0: i32, arg1: f32) -> bool {
         let api = self.fns().stop;
+        self.try_exec(|tk| (api.exec)(tk, arg0, arg1), false)
+    }
+
+    fn exec_pause(&mut self, arg0: i32) -> bool {
+        let api = self.fns().pause;
+        self.try_exec(|tk| (api.exec)(tk, arg0), false)
+    }
+
+    fn exec_resume(&mut self, arg0: i32) -> bool {
+        let api = self.fns().resume;
+        self.try_exec(|tk| (api.exec)(tk, arg0), false)
+    }
+
+    fn exec_keep_alive(&mut self, arg0: i32) -> bool {
+        let api = self.fns().keep_alive;
         self.try_exec(|tk| (api.exec)(tk, arg0), false)
     }
 
@@ -408,12 +463,12 @@ mod tests {
             .def_control(ApiControl {
                 name: "vibrate",
                 default: -1,
-                exec: |_, _, _, _, _| 1,
+                exec: |_, _, _, _, _, _| 1,
             })
             .def_control(ApiControl {
                 name: "move",
                 default: -1,
-                exec: |_, _, _, _, _| 2,
+                exec: |_, _, _, _, _, options| if options == "override" { 3 } else { 2 },
             })
         }
         fn state(&mut self) -> Arc<Mutex<Option<EmptyState>>> {
@@ -422,7 +477,7 @@ mod tests {
         fn destroy(&mut self) -> ApiCmd0<EmptyState> {
             ApiCmd0 {
                 name: "dtor",
-                exec: |_| false,
+                exec: |_| true,
             }
         }
     }
@@ -445,4 +500,25 @@ mod tests {
         assert!(api.exec_qry_bool("existing.query"));
         assert!(api.exec_qry_bool_1("existing.query", "something"));
     }
+
+    #[test]
+    fn test_api_destroy() {
+        let mut api = TestApi::new();
+        assert!(! api.exec_cmd_0("dtor"));
+        api.exec_cmd_0("ctor");
+        assert!(api.exec_cmd_0("dtor"));
+        assert!(! api.exec_cmd_0("existing.command"));
+        assert!(! api.exec_cmd_0("dtor"));
+    }
+
+    #[test]
+    fn test_api_control_options() {
+        let mut api = TestApi::new();
+        api.exec_cmd_0("ctor");
+        let events = CxxVector::<CxxString>::new();
+        assert_eq!(api.exec_control("vibrate", 0, 0.0, "", &events), 1);
+        assert_eq!(api.exec_control("move", 0, 0.0, "", &events), 2);
+        assert_eq!(api.exec_control("move:override", 0, 0.0, "", &events), 3);
+        assert_eq!(api.exec_control("non.existing:override", 0, 0.0, "", &events), -1);
+    }
 }
@@ -1,23 +1,24 @@
 use std::{
     fmt::{self, Display},
     sync::Arc,
-    time::Duration,
+    time::{Duration, Instant},
 };
 
 use bp_scheduler::{
     actuator::{get_actuators, Actuator},
+    safety::SafetyLimit,
     speed::Speed,
 };
 use buttplug::{
-    client::{ButtplugClient, ButtplugClientDevice, ButtplugClientEvent},
-    core::message::ActuatorType,
+    client::{ButtplugClient, ButtplugClientDevice, ButtplugClientEvent, ButtplugClientDeviceEvent},
+    core::message::{ActuatorType, ButtplugCurrentSpecServerMessage, SensorType},
 };
 use crossbeam_channel::Sender;
 use futures::StreamExt;
 use tokio::runtime::Handle;
-use tracing::{debug, error, info};
+use tracing::{debug, error, info, trace, warn};
 
-use crate::settings::TkConnectionType;
+use crate::settings::{TkConnectionType, TkSensorPolling};
 
 /// Global commands on connection level, i.e. connection handling
 /// or emergency stop
@@ -27,6 +28,8 @@ pub enum TkCommand {
     StopScan,
     StopAll,
     Disconect,
+    /// Subscribes (true) or unsubscribes (false) all sensors of the device
+    SubscribeSensors(Arc<ButtplugClientDevice>, bool),
 }
 
 #[derive(Clone, Debug)]
@@ -34,7 +37,25 @@ pub enum Task {
     Scalar(Speed),
     Pattern(Speed, ActuatorType, String),
     Linear(Speed, String),
-    LinearOscillate(Speed, String)
+    LinearOscillate(Speed, String),
+    Rotate(Speed, bool),
+    RotatePattern(Speed, bool, String),
+    /// Steps of a `TkSequence`, played back with 'speed' as a factor
+    Sequence(Speed, String),
+}
+
+impl Task {
+    pub fn speed(&self) -> Speed {
+        match self {
+            Task::Scalar(speed)
+            | Task::Pattern(speed, _, _)
+            | Task::Linear(speed, _)
+            | Task::LinearOscillate(speed, _)
+            | Task::Rotate(speed, _)
+            | Task::RotatePattern(speed, _, _)
+            | Task::Sequence(speed, _) => *speed,
+        }
+    }
 }
 
 #[derive(Clone, Debug)]
@@ -45,7 +66,31 @@ pub enum TkConnectionEvent {
     DeviceRemoved(Arc<ButtplugClientDevice>),
     ActionStarted(Task, Vec<Arc<Actuator>>, Vec<String>, i32),
     ActionDone(Task, Duration, i32),
-    ActionError(Arc<Actuator>, String),
+    /// A command of the actuator failed while the task was running
+    ActionError(Arc<Actuator>, String, i32, Instant),
+    ActionPaused(i32),
+    ActionResumed(i32),
+    /// The task was stopped because no keep-alive arrived in time
+    ActionTimeout(Task, i32),
+    /// The task was stopped because it exceeded a safety limit
+    ActionSafetyLimit(Task, i32, SafetyLimit),
+    /// The actuator accepts commands again after it failed
+    DeviceRecovered(Arc<Actuator>, Instant),
+    /// Latest sensor values of the device, only sent to the status
+    DeviceSensors(Arc<ButtplugClientDevice>, TkDeviceSensors),
+    /// The battery level of the device fell below the threshold (in percent)
+    BatteryLow(Arc<ButtplugClientDevice>, f64),
+    /// Value of a subscribed sensor, i.e. the pressure when the device is squeezed
+    SensorReading(Arc<ButtplugClientDevice>, SensorType, i32),
+}
+
+/// Sensor values that were read from a device, None if the device lacks the sensor
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct TkDeviceSensors {
+    /// Battery level in percent
+    pub battery: Option<f64>,
+    /// Received signal strength in dBm
+    pub rssi: Option<i32>,
 }
 
 pub async fn handle_connection(
@@ -54,6 +99,7 @@ pub async fn handle_connection(
     mut command_receiver: tokio::sync::mpsc::Receiver<TkCommand>,
     client: ButtplugClient,
     connection_type: TkConnectionType,
+    sensor_polling: TkSensorPolling,
 ) {
     let sender_interla_clone = event_sender_internal.clone();
     let mut buttplug_events = client.event_stream();
@@ -94,6 +140,10 @@ pub async fn handle_connection(
                         }
                     }
                     TkCommand::Disconect => {
+                        client
+                            .stop_all_devices()
+                            .await
+                            .unwrap_or_else(|_| error!("failed to stop all devices"));
                         client
                             .disconnect()
                             .await
@@ -106,6 +156,9 @@ pub async fn handle_connection(
                             .await
                             .unwrap_or_else(|_| error!("failed to stop all devices"));
                     }
+                    TkCommand::SubscribeSensors(device, subscribe) => {
+                        subscribe_sensors(&device, subscribe).await;
+                    }
                 }
             } else {
                 break;
@@ -125,9 +178,16 @@ pub async fn handle_connection(
                 let actuators = get_actuators(vec![device.clone()]);
                 info!(name, index, ?actuators, "device connected");
 
-                let added = TkConnectionEvent::DeviceAdded(device);
+                let added = TkConnectionEvent::DeviceAdded(device.clone());
                 try_send_event(&sender_interla_clone, added.clone());
                 try_send_event(&event_sender, added);
+                Handle::current().spawn(poll_sensors(
+                    device.clone(),
+                    sensor_polling,
+                    event_sender.clone(),
+                    sender_interla_clone.clone(),
+                ));
+                Handle::current().spawn(forward_sensor_readings(device, event_sender.clone()));
             }
             ButtplugClientEvent::DeviceRemoved(device) => {
                 let name = device.name();
@@ -146,6 +206,109 @@ pub async fn handle_connection(
     }
 }
 
+/// Reads the battery level and signal strength of the device until it disconnects,
+/// and reports when the battery level falls below the threshold
+async fn poll_sensors(
+    device: Arc<ButtplugClientDevice>,
+    polling: TkSensorPolling,
+    event_sender: Sender<TkConnectionEvent>,
+    event_sender_internal: Sender<TkConnectionEvent>,
+) {
+    if polling.interval_secs == 0 || !(device.has_battery_level() || device.has_rssi_level()) {
+        return;
+    }
+    let mut battery_low = false;
+    let mut interval = tokio::time::interval(Duration::from_secs(polling.interval_secs.into()));
+    loop {
+        interval.tick().await;
+        if !device.connected() {
+            break;
+        }
+        let mut sensors = TkDeviceSensors::default();
+        if device.has_battery_level() {
+            match device.battery_level().await {
+                Ok(level) => sensors.battery = Some((level * 100.0).round()),
+                Err(err) => error!(name = device.name(), ?err, "failed reading battery level"),
+            }
+        }
+        if device.has_rssi_level() {
+            match device.rssi_level().await {
+                Ok(rssi) => sensors.rssi = Some(rssi),
+                Err(err) => error!(name = device.name(), ?err, "failed reading rssi level"),
+            }
+        }
+        if let Some(percent) = sensors.battery {
+            let low = percent < f64::from(polling.battery_low_percent);
+            if low && !battery_low {
+                warn!(name = device.name(), percent, "battery low");
+                let event = TkConnectionEvent::BatteryLow(device.clone(), percent);
+                try_send_event(&event_sender_internal, event.clone());
+                try_send_event(&event_sender, event);
+            }
+            battery_low = low;
+        }
+        trace!(name = device.name(), ?sensors, "sensors read");
+        try_send_event(
+            &event_sender_internal,
+            TkConnectionEvent::DeviceSensors(device.clone(), sensors),
+        );
+    }
+}
+
+/// Subscribed sensors of the device, with their sensor index
+pub fn get_subscribable_sensors(device: &ButtplugClientDevice) -> Vec<(u32, SensorType)> {
+    match device.message_attributes().sensor_subscribe_cmd() {
+        Some(sensors) => sensors
+            .iter()
+            .enumerate()
+            .map(|(index, sensor)| (index as u32, *sensor.sensor_type()))
+            .collect(),
+        None => vec![],
+    }
+}
+
+async fn subscribe_sensors(device: &ButtplugClientDevice, subscribe: bool) {
+    for (index, sensor_type) in get_subscribable_sensors(device) {
+        let result = if subscribe {
+            device.subscribe_sensor(index, sensor_type).await
+        } else {
+            device.unsubscribe_sensor(index, sensor_type).await
+        };
+        let name = device.name();
+        match result {
+            Ok(()) => info!(name, index, %sensor_type, subscribe, "sensor subscription changed"),
+            Err(err) => error!(name, index, %sensor_type, ?err, "failed changing sensor subscription"),
+        }
+    }
+}
+
+/// Forwards the readings of subscribed sensors until the device disconnects
+async fn forward_sensor_readings(
+    device: Arc<ButtplugClientDevice>,
+    event_sender: Sender<TkConnectionEvent>,
+) {
+    let mut device_events = device.event_stream();
+    while let Some(event) = device_events.next().await {
+        let reading = match event {
+            ButtplugClientDeviceEvent::Message(ButtplugCurrentSpecServerMessage::SensorReading(
+                reading,
+            )) => reading,
+            ButtplugClientDeviceEvent::Message(_) => continue,
+            ButtplugClientDeviceEvent::DeviceRemoved
+            | ButtplugClientDeviceEvent::ClientDisconnect => break,
+        };
+        let sensor_type = reading.sensor_type();
+        match reading.data().first() {
+            Some(value) => {
+                trace!(name = device.name(), %sensor_type, value, "sensor reading");
+                let event = TkConnectionEvent::SensorReading(device.clone(), sensor_type, *value);
+                try_send_event(&event_sender, event);
+            }
+            None => warn!(name = device.name(), %sensor_type, "empty sensor reading"),
+        }
+    }
+}
+
 fn try_send_event(sender: &Sender<TkConnectionEvent>, evt: TkConnectionEvent) {
     sender
         .try_send(evt)
@@ -161,6 +324,26 @@ impl Display for Task {
             }
             Task::Linear(speed, pattern) => write!(f, "Linear({}, {})", speed, pattern),
             Task::LinearOscillate(speed, _) => write!(f, "Stroke({})", speed),
+            Task::Rotate(speed, clockwise) => {
+                write!(f, "Constant({}%, {})", speed, direction(*clockwise))
+            }
+            Task::RotatePattern(speed, clockwise, pattern) => write!(
+                f,
+                "Pattern({}, {}, {}, {})",
+                speed,
+                ActuatorType::Rotate,
+                pattern,
+                direction(*clockwise)
+            ),
+            Task::Sequence(speed, steps) => write!(f, "Sequence({}, {})", speed, steps),
         }
     }
 }
+
+fn direction(clockwise: bool) -> &'static str {
+    if clockwise {
+        "clockwise"
+    } else {
+        "counter-clockwise"
+    }
+}
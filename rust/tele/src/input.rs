@@ -1,6 +1,12 @@
 use std::{sync::Arc, time::Duration};
 
-use bp_scheduler::{actuator::Actuator, settings::ActuatorSettings};
+use bp_scheduler::{
+    actuator::Actuator,
+    envelope::Envelope,
+    interpolation::Interpolation,
+    priority::{BlendMode, Priority},
+    settings::ScalarScaling,
+};
 use buttplug::core::message::ActuatorType;
 use cxx::{CxxString, CxxVector};
 use tracing::{debug, error};
@@ -31,6 +37,15 @@ pub fn get_duration_from_secs(secs: f32) -> Duration {
     }
 }
 
+/// Release time of a stop, negative values keep the release of the task
+pub fn get_release_from_secs(secs: f32) -> Option<Duration> {
+    if secs >= 0.0 {
+        Some(Duration::from_millis((secs * 1000.0) as u64))
+    } else {
+        None
+    }
+}
+
 pub fn read_input_string(list: &CxxVector<CxxString>) -> Vec<String> {
     // automatically discards any empty strings to account for papyrus
     // inability to do dynamic array sizes
@@ -54,6 +69,134 @@ pub fn read_scalar_actuator(actuator: &str) -> ActuatorType {
     }
 }
 
+pub fn read_blend_mode(blend_mode: &str) -> BlendMode {
+    let lower = blend_mode.to_ascii_lowercase();
+    match lower.as_str() {
+        "max" => BlendMode::Max,
+        "additive" => BlendMode::Additive,
+        "latest" => BlendMode::Latest,
+        "priority" => BlendMode::HighestPriority,
+        _ => {
+            error!("unknown blend mode {:?}", lower);
+            BlendMode::default()
+        }
+    }
+}
+
+pub fn read_interpolation(interpolation: &str) -> Interpolation {
+    let lower = interpolation.to_ascii_lowercase();
+    match lower.as_str() {
+        "step" => Interpolation::Step,
+        "linear" => Interpolation::Linear,
+        "eased" => Interpolation::Eased,
+        _ => {
+            error!("unknown interpolation {:?}", lower);
+            Interpolation::default()
+        }
+    }
+}
+
+/// Reads one of the named scaling curves or a custom curve given as
+/// comma separated `input:output` percentages, i.e. `0:10,50:80,100:100`.
+/// The points of a custom curve are sorted, curves with duplicate inputs are rejected
+pub fn read_scalar_scaling(scaling: &str) -> ScalarScaling {
+    let lower = scaling.to_ascii_lowercase();
+    match lower.trim() {
+        "linear" => ScalarScaling::Linear,
+        "quadratic" => ScalarScaling::Quadratic,
+        "quadraticfraction" => ScalarScaling::QuadraticFraction,
+        custom => {
+            let points = parse_csv(custom)
+                .iter()
+                .map(|point| {
+                    let (input, output) = point.split_once(':')?;
+                    Some((input.trim().parse().ok()?, output.trim().parse().ok()?))
+                })
+                .collect::<Option<Vec<(u16, u16)>>>()
+                .map(|mut points| {
+                    points.sort_by_key(|p| p.0);
+                    points
+                });
+            match points {
+                Some(points)
+                    if !points.is_empty() && points.windows(2).all(|w| w[0].0 != w[1].0) =>
+                {
+                    ScalarScaling::Custom(points)
+                }
+                _ => {
+                    error!("unknown scaling {:?}", lower);
+                    ScalarScaling::Linear
+                }
+            }
+        }
+    }
+}
+
+/// Options that are appended to the name of a control, i.e. `vibrate:override`
+#[derive(Debug, Clone, Default)]
+pub struct TkOptions {
+    pub priority: Priority,
+    /// Overrides the interpolation of the pattern
+    pub interpolation: Option<Interpolation>,
+    /// Overrides the fade times of the default envelope, i.e. `attack=1.5`
+    pub attack_ms: Option<u32>,
+    pub release_ms: Option<u32>,
+    pub glide_ms: Option<u32>,
+    /// Repeats a sequence until the duration ends
+    pub looped: bool,
+    /// Stops the task if no keep-alive arrives in time, i.e. `keepalive=5`
+    pub keep_alive_ms: Option<u32>,
+}
+
+impl TkOptions {
+    pub fn parse(options: &str) -> TkOptions {
+        let mut result = TkOptions::default();
+        for option in parse_csv(options) {
+            match option.as_str() {
+                "background" => result.priority = Priority::Background,
+                "normal" => result.priority = Priority::Normal,
+                "override" => result.priority = Priority::Override,
+                "loop" => result.looped = true,
+                "step" | "linear" | "eased" => {
+                    result.interpolation = Some(read_interpolation(&option))
+                }
+                _ => match option.split_once('=') {
+                    Some(("attack", secs)) => result.attack_ms = read_ms(secs),
+                    Some(("release", secs)) => result.release_ms = read_ms(secs),
+                    Some(("glide", secs)) => result.glide_ms = read_ms(secs),
+                    Some(("keepalive", secs)) => {
+                        result.keep_alive_ms = read_ms(secs).filter(|ms| *ms > 0)
+                    }
+                    _ => error!("unknown option {:?}", option),
+                },
+            }
+        }
+        result
+    }
+
+    pub fn envelope(&self, default: Envelope) -> Envelope {
+        Envelope {
+            attack_ms: self.attack_ms.unwrap_or(default.attack_ms),
+            release_ms: self.release_ms.unwrap_or(default.release_ms),
+            glide_ms: self.glide_ms.unwrap_or(default.glide_ms),
+        }
+    }
+
+    pub fn keep_alive(&self) -> Option<Duration> {
+        self.keep_alive_ms.map(|ms| Duration::from_millis(ms.into()))
+    }
+}
+
+fn read_ms(secs: &str) -> Option<u32> {
+    match secs.trim().parse::<f32>() {
+        Ok(secs) if secs >= 0.0 => Some((secs * 1000.0) as u32),
+        _ => {
+            error!("invalid time {:?}", secs);
+            None
+        }
+    }
+}
+
 pub struct TkParams {}
 
 impl TkParams {
@@ -83,8 +226,6 @@ impl TkParams {
                 .cloned()
                 .collect::<Vec<Arc<Actuator>>>();
 
-        let actuator_settings = used.iter().map(|x| device_settings.iter().find( |y| y.actuator_id == x.identifier() ) );
-
         debug!("connected: {:?}", actuators.iter().map( |x| x.identifier() ).collect::<Vec<&str>>());
         debug!(?used);
         used
@@ -6,14 +6,18 @@ use bp_scheduler::{
 use buttplug::core::message::ActuatorType;
 use connection::{Task, TkConnectionEvent};
 use ffi::SKSEModEvent;
-use input::{get_duration_from_secs, read_scalar_actuator};
+use input::{
+    get_duration_from_secs, get_release_from_secs, read_blend_mode, read_interpolation, read_scalar_actuator,
+    read_scalar_scaling, TkOptions,
+};
 use itertools::Itertools;
 use pattern::{get_pattern_names, read_pattern};
+use sequence::TkSequence;
 use std::sync::{Arc, Mutex};
-use tracing::{debug, info, instrument};
+use tracing::instrument;
 
 use cxx::{CxxString, CxxVector};
-use telekinesis::{Telekinesis, ERROR_HANDLE};
+use telekinesis::{Telekinesis, ERROR_HANDLE, SCALAR_RESOLUTION_MS};
 
 use crate::{
     input::{parse_csv, read_input_string},
@@ -25,8 +29,11 @@ mod connection;
 mod input;
 mod logging;
 mod pattern;
+mod sequence;
 mod settings;
 mod status;
+mod tasks;
+mod tcode;
 pub mod telekinesis;
 mod util;
 
@@ -72,7 +79,10 @@ mod ffi {
             arg3: &CxxVector<CxxString>,
         ) -> i32;
         fn tk_update(&mut self, arg0: i32, arg1: i32) -> bool;
-        fn tk_stop(&mut self, arg0: i32) -> bool;
+        fn tk_stop(&mut self, arg0: i32, arg1: f32) -> bool;
+        fn tk_pause(&mut self, arg0: i32) -> bool;
+        fn tk_resume(&mut self, arg0: i32) -> bool;
+        fn tk_keep_alive(&mut self, arg0: i32) -> bool;
         // blocking
         fn tk_qry_nxt_evt(&mut self) -> Vec<SKSEModEvent>;
     }
@@ -113,10 +123,7 @@ impl Api<Telekinesis> for TkApi {
     fn destroy(&mut self) -> ApiCmd0<Telekinesis> {
         ApiCmd0 {
             name: "disconnect",
-            exec: |tk| {
-                tk.disconnect();
-                true
-            },
+            exec: |tk| tk.disconnect(),
         }
     }
 }
@@ -185,8 +192,23 @@ impl TkApi {
     }
 
     #[instrument(skip(self))]
-    fn tk_stop(&mut self, arg0: i32) -> bool {
-        self.exec_stop(arg0)
+    fn tk_stop(&mut self, arg0: i32, arg1: f32) -> bool {
+        self.exec_stop(arg0, arg1)
+    }
+
+    #[instrument(skip(self))]
+    fn tk_pause(&mut self, arg0: i32) -> bool {
+        self.exec_pause(arg0)
+    }
+
+    #[instrument(skip(self))]
+    fn tk_resume(&mut self, arg0: i32) -> bool {
+        self.exec_resume(arg0)
+    }
+
+    #[instrument(skip(self))]
+    fn tk_keep_alive(&mut self, arg0: i32) -> bool {
+        self.exec_keep_alive(arg0)
     }
 
     /// Return type Vec cause cxx crate does not support Option
@@ -212,11 +234,6 @@ impl TkApi {
             None => vec![],
         }
     }
-
-    #[instrument]
-    fn tk_destroy(&mut self) {
-        self.destroy();
-    }
 }
 
 pub fn get_next_events_blocking(
@@ -236,6 +253,17 @@ pub fn get_next_events_blocking(
             TkConnectionEvent::DeviceRemoved(device) => {
                 SKSEModEvent::from("Tele_DeviceRemoved", device.name())
             }
+            TkConnectionEvent::DeviceRecovered(actuator, _) => {
+                SKSEModEvent::from("Tele_DeviceRecovered", actuator.identifier())
+            }
+            TkConnectionEvent::BatteryLow(device, percent) => {
+                SKSEModEvent::new("Tele_DeviceBatteryLow", device.name(), percent)
+            }
+            TkConnectionEvent::SensorReading(device, _, value) => {
+                SKSEModEvent::new("Tele_Sensor", device.name(), f64::from(value))
+            }
+            // only processed by the status
+            TkConnectionEvent::DeviceSensors(_, _) => return None,
             TkConnectionEvent::ActionStarted(task, actuators, tags, handle) => {
                 let str_arg = format!(
                     "{}{} on ({})",
@@ -253,8 +281,23 @@ pub fn get_next_events_blocking(
                 let str_arg = format!("{} done after {:.1}s", task, duration.as_secs());
                 SKSEModEvent::new("Tele_DeviceActionDone", &str_arg, f64::from(handle))
             }
-            TkConnectionEvent::ActionError(_, err) => {
-                SKSEModEvent::new("Tele_DeviceError", &err, 0.0)
+            TkConnectionEvent::ActionError(actuator, err, handle, _) => {
+                let str_arg = format!("{}: {}", actuator.identifier(), err);
+                SKSEModEvent::new("Tele_DeviceError", &str_arg, f64::from(handle))
+            }
+            TkConnectionEvent::ActionPaused(handle) => {
+                SKSEModEvent::new("Tele_DeviceActionPaused", "", f64::from(handle))
+            }
+            TkConnectionEvent::ActionResumed(handle) => {
+                SKSEModEvent::new("Tele_DeviceActionResumed", "", f64::from(handle))
+            }
+            TkConnectionEvent::ActionTimeout(task, handle) => {
+                let str_arg = format!("{} stopped, no keep-alive received", task);
+                SKSEModEvent::new("Tele_DeviceActionTimeout", &str_arg, f64::from(handle))
+            }
+            TkConnectionEvent::ActionSafetyLimit(task, handle, limit) => {
+                let str_arg = format!("{} stopped, {} exceeded", task, limit);
+                SKSEModEvent::new("Tele_DeviceSafetyLimit", &str_arg, f64::from(handle))
             }
         };
         return Some(event);
@@ -287,11 +330,112 @@ pub fn build_api() -> ApiBuilder<Telekinesis> {
             true
         },
     })
+    .def_cmd1(ApiCmd1 {
+        name: "connection.tcode",
+        exec: |tk, port| {
+            tk.settings.connection = TkConnectionType::TCode(String::from(port));
+            true
+        },
+    })
     .def_qry_str(ApiQryStr {
         name: "connection.status",
         default: "Not Connected",
         exec: |tk| tk.status.connection_status().to_string(),
     })
+    .def_qry_str(ApiQryStr {
+        name: "connection.min_command_interval_ms",
+        default: "0",
+        exec: |tk| tk.settings.min_command_interval_ms.to_string(),
+    })
+    .def_cmd1(ApiCmd1 {
+        name: "connection.min_command_interval_ms",
+        exec: |tk, ms| {
+            tk.settings.min_command_interval_ms = ms.parse().unwrap_or(0);
+            true
+        },
+    })
+    .def_qry_str(ApiQryStr {
+        name: "connection.max_retries",
+        default: "3",
+        exec: |tk| tk.settings.retry.max_retries.to_string(),
+    })
+    .def_cmd1(ApiCmd1 {
+        name: "connection.max_retries",
+        exec: |tk, retries| match retries.parse() {
+            Ok(retries) => {
+                tk.settings.retry.max_retries = retries;
+                true
+            }
+            Err(_) => false,
+        },
+    })
+    .def_qry_str(ApiQryStr {
+        name: "connection.retry_backoff_ms",
+        default: "100",
+        exec: |tk| tk.settings.retry.backoff_ms.to_string(),
+    })
+    .def_cmd1(ApiCmd1 {
+        name: "connection.retry_backoff_ms",
+        exec: |tk, ms| match ms.parse() {
+            Ok(ms) => {
+                tk.settings.retry.backoff_ms = ms;
+                true
+            }
+            Err(_) => false,
+        },
+    })
+    // safety
+    .def_qry_str(ApiQryStr {
+        name: "master_intensity",
+        default: "100",
+        exec: |tk| tk.master_intensity().to_string(),
+    })
+    .def_cmd1(ApiCmd1 {
+        name: "master_intensity",
+        exec: |tk, percent| match percent.parse() {
+            Ok(percent) => {
+                tk.set_master_intensity(Speed::new(percent));
+                true
+            }
+            Err(_) => false,
+        },
+    })
+    .def_qry_str(ApiQryStr {
+        name: "safety.max_run_time_ms",
+        default: "0",
+        exec: |tk| tk.settings.safety_limits.max_run_time_ms.to_string(),
+    })
+    .def_cmd1(ApiCmd1 {
+        name: "safety.max_run_time_ms",
+        exec: |tk, ms| {
+            tk.settings.safety_limits.max_run_time_ms = ms.parse().unwrap_or(0);
+            true
+        },
+    })
+    .def_qry_str(ApiQryStr {
+        name: "safety.max_session_time_ms",
+        default: "0",
+        exec: |tk| tk.settings.safety_limits.max_session_time_ms.to_string(),
+    })
+    .def_cmd1(ApiCmd1 {
+        name: "safety.max_session_time_ms",
+        exec: |tk, ms| {
+            tk.settings.safety_limits.max_session_time_ms = ms.parse().unwrap_or(0);
+            true
+        },
+    })
+    .def_qry_str(ApiQryStr {
+        name: "safety.cooldown_ms",
+        default: "0",
+        exec: |tk| tk.settings.safety_limits.cooldown_ms.to_string(),
+    })
+    .def_cmd1(ApiCmd1 {
+        name: "safety.cooldown_ms",
+        exec: |tk, ms| {
+            tk.settings.safety_limits.cooldown_ms = ms.parse().unwrap_or(0);
+            true
+        },
+    })
     // scan
     .def_cmd(ApiCmd0 {
         name: "start_scan",
@@ -304,33 +448,35 @@ pub fn build_api() -> ApiBuilder<Telekinesis> {
     // controls
     .def_control(ApiControl {
         name: "vibrate",
-        exec: |tk, speed, time_sec, _, events| {
+        exec: |tk, speed, time_sec, _, events, options| {
             tk.scalar(
                 Task::Scalar(Speed::new(speed.into())),
                 get_duration_from_secs(time_sec),
                 read_input_string(events),
                 None,
                 &[ActuatorType::Vibrate],
+                TkOptions::parse(options),
             )
         },
         default: ERROR_HANDLE,
     })
     .def_control(ApiControl {
         name: "scalar",
-        exec: |tk, speed, time_sec, actuator_type, events| {
+        exec: |tk, speed, time_sec, actuator_type, events, options| {
             tk.scalar(
                 Task::Scalar(Speed::new(speed.into())),
                 get_duration_from_secs(time_sec),
                 read_input_string(events),
                 None,
                 &[read_scalar_actuator(actuator_type)],
+                TkOptions::parse(options),
             )
         },
         default: ERROR_HANDLE,
     })
     .def_control(ApiControl {
         name: "vibrate.pattern",
-        exec: |tk, speed, time_sec, pattern_name, events| match read_pattern(
+        exec: |tk, speed, time_sec, pattern_name, events, options| match read_pattern(
             &tk.settings.pattern_path,
             pattern_name,
             true,
@@ -345,6 +491,68 @@ pub fn build_api() -> ApiBuilder<Telekinesis> {
                 read_input_string(events),
                 Some(fscript),
                 &[ActuatorType::Vibrate],
+                TkOptions::parse(options),
+            ),
+            None => ERROR_HANDLE,
+        },
+        default: ERROR_HANDLE,
+    })
+    .def_control(ApiControl {
+        name: "vibrate.sequence",
+        exec: |tk, speed, time_sec, steps, events, options| {
+            let options = TkOptions::parse(options);
+            let pattern_path = &tk.settings.pattern_path;
+            let sequence = TkSequence::parse(steps);
+            match sequence.as_ref().and_then(|sequence| {
+                sequence.to_fscript(SCALAR_RESOLUTION_MS, |name| {
+                    read_pattern(pattern_path, name, true)
+                })
+            }) {
+                Some(fscript) => tk.scalar(
+                    Task::Sequence(Speed::new(speed.into()), steps.into()),
+                    sequence.unwrap().playback_duration(time_sec, options.looped),
+                    read_input_string(events),
+                    Some(fscript),
+                    &[ActuatorType::Vibrate],
+                    options,
+                ),
+                None => ERROR_HANDLE,
+            }
+        },
+        default: ERROR_HANDLE,
+    })
+    .def_control(ApiControl {
+        name: "rotate",
+        exec: |tk, speed, time_sec, _, events, options| {
+            tk.scalar(
+                Task::Rotate(Speed::new(speed.unsigned_abs().into()), speed >= 0),
+                get_duration_from_secs(time_sec),
+                read_input_string(events),
+                None,
+                &[ActuatorType::Rotate],
+                TkOptions::parse(options),
+            )
+        },
+        default: ERROR_HANDLE,
+    })
+    .def_control(ApiControl {
+        name: "rotate.pattern",
+        exec: |tk, speed, time_sec, pattern_name, events, options| match read_pattern(
+            &tk.settings.pattern_path,
+            pattern_name,
+            true,
+        ) {
+            Some(fscript) => tk.scalar(
+                Task::RotatePattern(
+                    Speed::new(speed.unsigned_abs().into()),
+                    speed >= 0,
+                    pattern_name.into(),
+                ),
+                get_duration_from_secs(time_sec),
+                read_input_string(events),
+                Some(fscript),
+                &[ActuatorType::Rotate],
+                TkOptions::parse(options),
             ),
             None => ERROR_HANDLE,
         },
@@ -352,16 +560,17 @@ pub fn build_api() -> ApiBuilder<Telekinesis> {
     })
     .def_control(ApiControl {
         name: "linear.pattern",
-        exec: |tk, speed, time_sec, pattern_name, events| match read_pattern(
+        exec: |tk, speed, time_sec, pattern_name, events, options| match read_pattern(
             &tk.settings.pattern_path,
             pattern_name,
             false,
         ) {
             Some(fscript) => tk.linear_pattern(
-                Task::Linear(Speed::new(speed.into()), pattern_name.into()),
+                Task::Linear(Speed::new_unbounded(speed.into()), pattern_name.into()),
                 get_duration_from_secs(time_sec),
                 read_input_string(events),
                 fscript,
+                TkOptions::parse(options),
             ),
             None => ERROR_HANDLE,
         },
@@ -369,31 +578,101 @@ pub fn build_api() -> ApiBuilder<Telekinesis> {
     })
     .def_control(ApiControl {
         name: "linear.oscillate",
-        exec: |tk, speed, time_sec, pattern_name, body_parts| {
+        exec: |tk, speed, time_sec, pattern_name, body_parts, options| {
             tk.linear_oscillate(
                 Task::LinearOscillate(Speed::new(speed.into()), pattern_name.into()),
                 get_duration_from_secs(time_sec),
                 pattern_name,
                 read_input_string(body_parts),
+                TkOptions::parse(options),
             )
         },
         default: ERROR_HANDLE,
     })
     .def_update(ApiUpdate {
-        exec: |tk, handle, speed| tk.update(handle, Speed::new(speed.into())),
+        exec: |tk, handle, speed| {
+            // negative speeds reverse the direction of rotations,
+            // speeds above 100 are only used as playback rate of linear patterns
+            if tk.is_rotation(handle) {
+                tk.update_direction(handle, speed >= 0);
+                return tk.update(handle, Speed::new_unbounded(speed.unsigned_abs().into()));
+            }
+            tk.update(handle, Speed::new_unbounded(speed.into()))
+        },
     })
     .def_stop(ApiStop {
-        exec: Telekinesis::stop,
+        exec: |tk, handle, release_sec| tk.stop(handle, get_release_from_secs(release_sec)),
+    })
+    .def_pause(ApiPause {
+        exec: Telekinesis::pause,
+    })
+    .def_resume(ApiResume {
+        exec: Telekinesis::resume,
+    })
+    .def_keep_alive(ApiKeepAlive {
+        exec: Telekinesis::keep_alive,
     })
     .def_cmd(ApiCmd0 {
         name: "stop_all",
         exec: Telekinesis::stop_all,
     })
+    // tasks
+    .def_qry_lst(ApiQryList {
+        name: "tasks",
+        exec: |tk| tk.get_tasks().iter().map(|x| x.to_string()).collect(),
+    })
+    .def_qry_lst_1(ApiQryList1 {
+        name: "task.info",
+        exec: |tk, handle| match handle.parse() {
+            Ok(handle) => tk
+                .get_task_info(handle)
+                .map(|x| x.describe())
+                .unwrap_or_default(),
+            Err(_) => vec![],
+        },
+    })
     // settings
     .def_cmd(ApiCmd0 {
         name: "settings.store",
         exec: |tk| tk.settings.try_write(SETTINGS_PATH, SETTINGS_FILE),
     })
+    // envelope
+    .def_qry_str(ApiQryStr {
+        name: "envelope.attack_ms",
+        default: "0",
+        exec: |tk| tk.settings.envelope.attack_ms.to_string(),
+    })
+    .def_cmd1(ApiCmd1 {
+        name: "envelope.attack_ms",
+        exec: |tk, ms| {
+            tk.settings.envelope.attack_ms = ms.parse().unwrap_or(0);
+            true
+        },
+    })
+    .def_qry_str(ApiQryStr {
+        name: "envelope.release_ms",
+        default: "0",
+        exec: |tk| tk.settings.envelope.release_ms.to_string(),
+    })
+    .def_cmd1(ApiCmd1 {
+        name: "envelope.release_ms",
+        exec: |tk, ms| {
+            tk.settings.envelope.release_ms = ms.parse().unwrap_or(0);
+            true
+        },
+    })
+    .def_qry_str(ApiQryStr {
+        name: "envelope.glide_ms",
+        default: "0",
+        exec: |tk| tk.settings.envelope.glide_ms.to_string(),
+    })
+    .def_cmd1(ApiCmd1 {
+        name: "envelope.glide_ms",
+        exec: |tk, ms| {
+            tk.settings.envelope.glide_ms = ms.parse().unwrap_or(0);
+            true
+        },
+    })
     // devices settings
     .def_qry_lst(ApiQryList {
         name: "devices",
@@ -518,6 +797,52 @@ pub fn build_api() -> ApiBuilder<Telekinesis> {
             true
         },
     })
+    .def_qry_str1(ApiQryStr1 {
+        name: "device.scalar.scaling",
+        default: "",
+        exec: |tk, actuator_id| {
+            tk.settings
+                .access_scalar(actuator_id, |x| x.scaling.to_string())
+        },
+    })
+    .def_cmd2(ApiCmd2 {
+        name: "device.scalar.scaling",
+        exec: |tk, actuator_id, scaling| {
+            tk.settings.access_scalar(actuator_id, |x| {
+                x.scaling = read_scalar_scaling(scaling);
+            });
+            true
+        },
+    })
+    .def_qry_str1(ApiQryStr1 {
+        name: "device.scalar.blend_mode",
+        default: "",
+        exec: |tk, actuator_id| {
+            tk.settings
+                .access_scalar(actuator_id, |x| x.blend_mode.to_string())
+        },
+    })
+    .def_cmd2(ApiCmd2 {
+        name: "device.scalar.blend_mode",
+        exec: |tk, actuator_id, blend_mode| {
+            tk.settings.access_scalar(actuator_id, |x| {
+                x.blend_mode = read_blend_mode(blend_mode);
+            });
+            true
+        },
+    })
+    .def_qry_str(ApiQryStr {
+        name: "linear.handover_velocity",
+        default: "0",
+        exec: |tk| tk.settings.linear_handover_velocity.to_string(),
+    })
+    .def_cmd1(ApiCmd1 {
+        name: "linear.handover_velocity",
+        exec: |tk, velocity| {
+            tk.settings.linear_handover_velocity = velocity.parse().unwrap_or(0);
+            true
+        },
+    })
     .def_qry_str1(ApiQryStr1 {
         name: "device.linear.min_ms",
         default: "",
@@ -584,6 +909,22 @@ pub fn build_api() -> ApiBuilder<Telekinesis> {
             true
         },
     })
+    .def_qry_str1(ApiQryStr1 {
+        name: "device.linear.max_velocity",
+        default: "",
+        exec: |tk, actuator_id| {
+            tk.settings
+                .access_linear(actuator_id, |x| x.max_velocity.to_string())
+        },
+    })
+    .def_cmd2(ApiCmd2 {
+        name: "device.linear.max_velocity",
+        exec: |tk, actuator_id, velocity| {
+            tk.settings
+                .access_linear(actuator_id, |x| x.max_velocity = velocity.parse().unwrap_or(0));
+            true
+        },
+    })
     .def_qry_bool_1(ApiQryBool1 {
         name: "device.linear.invert",
         exec: |tk, actuator_id| tk.settings.access_linear(actuator_id, |x| x.invert),
@@ -613,6 +954,70 @@ pub fn build_api() -> ApiBuilder<Telekinesis> {
         default: "Not Connected",
         exec: |tk, actuator_id| tk.status.get_actuator_status(actuator_id).to_string(),
     })
+    .def_qry_str1(ApiQryStr1 {
+        name: "device.tcode.axis",
+        default: "",
+        exec: |tk, actuator_id| tk.get_tcode_axis(actuator_id).unwrap_or_default(),
+    })
+    .def_cmd2(ApiCmd2 {
+        name: "device.tcode.axis",
+        exec: |tk, actuator_id, axis| tk.set_tcode_axis(actuator_id, axis),
+    })
+    // sensors
+    .def_qry_str1(ApiQryStr1 {
+        name: "device.battery",
+        default: "",
+        exec: |tk, actuator_id| match tk.status.get_sensors(actuator_id).battery {
+            Some(percent) => format!("{:.0}", percent),
+            None => String::default(),
+        },
+    })
+    .def_qry_str1(ApiQryStr1 {
+        name: "device.rssi",
+        default: "",
+        exec: |tk, actuator_id| match tk.status.get_sensors(actuator_id).rssi {
+            Some(rssi) => rssi.to_string(),
+            None => String::default(),
+        },
+    })
+    .def_cmd1(ApiCmd1 {
+        name: "sensors.subscribe",
+        exec: |tk, actuator_id| tk.subscribe_sensors(actuator_id, true),
+    })
+    .def_cmd1(ApiCmd1 {
+        name: "sensors.unsubscribe",
+        exec: |tk, actuator_id| tk.subscribe_sensors(actuator_id, false),
+    })
+    .def_qry_str(ApiQryStr {
+        name: "sensors.interval_secs",
+        default: "60",
+        exec: |tk| tk.settings.sensor_polling.interval_secs.to_string(),
+    })
+    .def_cmd1(ApiCmd1 {
+        name: "sensors.interval_secs",
+        exec: |tk, secs| match secs.parse() {
+            Ok(secs) => {
+                tk.settings.sensor_polling.interval_secs = secs;
+                true
+            }
+            Err(_) => false,
+        },
+    })
+    .def_qry_str(ApiQryStr {
+        name: "sensors.battery_low_percent",
+        default: "20",
+        exec: |tk| tk.settings.sensor_polling.battery_low_percent.to_string(),
+    })
+    .def_cmd1(ApiCmd1 {
+        name: "sensors.battery_low_percent",
+        exec: |tk, percent| match percent.parse() {
+            Ok(percent) => {
+                tk.settings.sensor_polling.battery_low_percent = percent;
+                true
+            }
+            Err(_) => false,
+        },
+    })
     // patterns
     .def_qry_lst(ApiQryList {
         name: "patterns.vibrator",
@@ -622,4 +1027,21 @@ pub fn build_api() -> ApiBuilder<Telekinesis> {
         name: "patterns.stroker",
         exec: |tk| get_pattern_names(&tk.settings.pattern_path, false),
     })
+    .def_qry_str1(ApiQryStr1 {
+        name: "pattern.interpolation",
+        default: "",
+        exec: |tk, pattern_name| {
+            tk.settings
+                .get_pattern_interpolation(pattern_name)
+                .to_string()
+        },
+    })
+    .def_cmd2(ApiCmd2 {
+        name: "pattern.interpolation",
+        exec: |tk, pattern_name, interpolation| {
+            tk.settings
+                .set_pattern_interpolation(pattern_name, read_interpolation(interpolation));
+            true
+        },
+    })
 }
@@ -1,5 +1,6 @@
 use std::{path::PathBuf, time::Instant, fs};
 use anyhow::anyhow;
+use bp_scheduler::generator::PatternGenerator;
 use funscript::FScript;
 use tracing::{error, debug};
 
@@ -78,6 +79,11 @@ pub fn read_pattern_name(
     pattern_name: &str,
     vibration_pattern: bool,
 ) -> Result<FScript, anyhow::Error> {
+    if PatternGenerator::is_generator(pattern_name) {
+        return PatternGenerator::parse(pattern_name)
+            .map(|generator| generator.generate())
+            .ok_or_else(|| anyhow!("Invalid generator '{}'", pattern_name));
+    }
     let now = Instant::now();
     let patterns: Vec<TkPatternFile> = get_pattern_paths(pattern_path)?;
     let pattern = patterns
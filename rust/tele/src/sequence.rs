@@ -0,0 +1,231 @@
+use std::time::Duration;
+
+use funscript::{FSPoint, FScript};
+use tracing::error;
+
+use crate::input::{get_duration_from_secs, parse_csv};
+
+/// A single step of a sequence
+#[derive(Clone, Debug, PartialEq)]
+pub enum TkStep {
+    Constant(u16, Duration),
+    Pattern(String, Duration),
+    Pause(Duration),
+}
+
+impl TkStep {
+    pub fn duration(&self) -> Duration {
+        match self {
+            TkStep::Constant(_, duration)
+            | TkStep::Pattern(_, duration)
+            | TkStep::Pause(duration) => *duration,
+        }
+    }
+}
+
+/// Ordered list of steps that are played back under a single handle
+///
+/// Written as comma separated `value@secs` steps, where value is a constant
+/// speed (0-100), the name of a vibration pattern or `pause`, i.e.
+/// `20@5,02_Cruel-Tease@10,pause@2,100@3`
+#[derive(Clone, Debug, PartialEq)]
+pub struct TkSequence {
+    pub steps: Vec<TkStep>,
+}
+
+impl TkSequence {
+    pub fn parse(input: &str) -> Option<TkSequence> {
+        let steps = parse_csv(input)
+            .iter()
+            .map(|step| {
+                let result = read_step(step);
+                if result.is_none() {
+                    error!("invalid sequence step {:?}", step);
+                }
+                result
+            })
+            .collect::<Option<Vec<TkStep>>>()?;
+        if steps.is_empty() {
+            error!("empty sequence {:?}", input);
+            return None;
+        }
+        Some(TkSequence { steps })
+    }
+
+    /// Length of a single run through all steps
+    pub fn duration(&self) -> Duration {
+        self.steps.iter().map(|x| x.duration()).sum()
+    }
+
+    /// Plays the sequence once unless it is looped, 'secs' limits the
+    /// playback in both cases, values <= 0 don't limit it
+    pub fn playback_duration(&self, secs: f32, looped: bool) -> Duration {
+        let limit = get_duration_from_secs(secs);
+        if looped {
+            limit
+        } else {
+            limit.min(self.duration())
+        }
+    }
+
+    /// Concatenates all steps into one scalar funscript, patterns are repeated
+    /// until their step ends, returns None if any pattern can't be loaded
+    ///
+    /// Each step holds its last value until 'resolution_ms' before the next step
+    /// starts, so that interpolated players don't blend over the whole step
+    pub fn to_fscript<F>(&self, resolution_ms: i32, read_pattern: F) -> Option<FScript>
+    where
+        F: Fn(&str) -> Option<FScript>,
+    {
+        let mut fscript = FScript::default();
+        let mut at = 0;
+        for step in &self.steps {
+            let duration = step.duration().as_millis() as i32;
+            let hold = at + (duration - resolution_ms).max(0);
+            match step {
+                TkStep::Constant(speed, _) => {
+                    push_constant(&mut fscript, at, hold, *speed as i32)
+                }
+                TkStep::Pause(_) => push_constant(&mut fscript, at, hold, 0),
+                TkStep::Pattern(name, _) => {
+                    let pattern = read_pattern(name)?;
+                    push_pattern(&mut fscript, at, hold, &pattern.actions);
+                }
+            }
+            at += duration;
+        }
+        // the last point is replaced immediately when the sequence loops
+        fscript.actions.push(FSPoint { pos: 0, at });
+        Some(fscript)
+    }
+}
+
+fn read_step(step: &str) -> Option<TkStep> {
+    let (value, secs) = step.split_once('@')?;
+    let secs = secs.trim().parse::<f32>().ok().filter(|x| *x > 0.0)?;
+    let duration = Duration::from_millis((secs * 1000.0) as u64);
+    let value = value.trim();
+    if value == "pause" {
+        return Some(TkStep::Pause(duration));
+    }
+    match value.parse::<u16>() {
+        Ok(speed) => Some(TkStep::Constant(speed.min(100), duration)),
+        Err(_) if !value.is_empty() => Some(TkStep::Pattern(value.into(), duration)),
+        Err(_) => None,
+    }
+}
+
+/// Holds 'pos' from 'at' until 'hold'
+fn push_constant(fscript: &mut FScript, at: i32, hold: i32, pos: i32) {
+    fscript.actions.push(FSPoint { pos, at });
+    fscript.actions.push(FSPoint { pos, at: hold });
+}
+
+/// Repeats 'actions' from 'at' and holds the last value until 'hold'
+fn push_pattern(fscript: &mut FScript, at: i32, hold: i32, actions: &[FSPoint]) {
+    let length = actions.last().map(|x| x.at).unwrap_or(0);
+    if length <= 0 {
+        push_constant(fscript, at, hold, actions.first().map(|x| x.pos).unwrap_or(0));
+        return;
+    }
+    let mut offset = 0;
+    let mut last_pos = actions[0].pos;
+    while at + offset < hold {
+        for action in actions {
+            // the first point of a repetition equals the end of the previous one
+            if offset > 0 && action.at == 0 {
+                continue;
+            }
+            if at + offset + action.at >= hold {
+                break;
+            }
+            fscript.actions.push(FSPoint {
+                pos: action.pos,
+                at: at + offset + action.at,
+            });
+            last_pos = action.pos;
+        }
+        offset += length;
+    }
+    fscript.actions.push(FSPoint {
+        pos: last_pos,
+        at: hold,
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_steps() {
+        let sequence = TkSequence::parse("20@5, Tease@1.5,pause@2,150@3").unwrap();
+        assert_eq!(
+            sequence.steps,
+            vec![
+                TkStep::Constant(20, Duration::from_secs(5)),
+                TkStep::Pattern("tease".into(), Duration::from_millis(1500)),
+                TkStep::Pause(Duration::from_secs(2)),
+                TkStep::Constant(100, Duration::from_secs(3)),
+            ]
+        );
+        assert_eq!(sequence.duration(), Duration::from_millis(11500));
+    }
+
+    #[test]
+    fn parse_invalid_steps() {
+        assert_eq!(TkSequence::parse(""), None);
+        assert_eq!(TkSequence::parse("20"), None);
+        assert_eq!(TkSequence::parse("20@0"), None);
+        assert_eq!(TkSequence::parse("20@5,@5"), None);
+    }
+
+    #[test]
+    fn playback_duration() {
+        let sequence = TkSequence::parse("20@5").unwrap();
+        assert_eq!(sequence.playback_duration(0.0, false), Duration::from_secs(5));
+        assert_eq!(sequence.playback_duration(2.0, false), Duration::from_secs(2));
+        assert_eq!(sequence.playback_duration(0.0, true), Duration::MAX);
+        assert_eq!(sequence.playback_duration(12.0, true), Duration::from_secs(12));
+    }
+
+    #[test]
+    fn steps_are_concatenated_and_patterns_repeated() {
+        let sequence = TkSequence::parse("20@1,pattern@1,pause@0.5").unwrap();
+        let fscript = sequence
+            .to_fscript(100, |_| {
+                let mut pattern = FScript::default();
+                pattern.actions.push(FSPoint { pos: 10, at: 0 });
+                pattern.actions.push(FSPoint { pos: 90, at: 300 });
+                pattern.actions.push(FSPoint { pos: 10, at: 600 });
+                Some(pattern)
+            })
+            .unwrap();
+
+        let points = fscript
+            .actions
+            .iter()
+            .map(|x| (x.at, x.pos))
+            .collect::<Vec<(i32, i32)>>();
+        assert_eq!(
+            points,
+            vec![
+                (0, 20),
+                (900, 20),
+                (1000, 10),
+                (1300, 90),
+                (1600, 10),
+                (1900, 10),
+                (2000, 0),
+                (2400, 0),
+                (2500, 0),
+            ]
+        );
+    }
+
+    #[test]
+    fn missing_pattern_fails() {
+        let sequence = TkSequence::parse("20@1,pattern@1").unwrap();
+        assert!(sequence.to_fscript(100, |_| None).is_none());
+    }
+}
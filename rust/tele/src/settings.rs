@@ -1,10 +1,11 @@
 use std::{
+    collections::HashMap,
     fmt::{self, Display},
     fs::{self},
     path::PathBuf,
 };
 
-use bp_scheduler::{actuator::Actuator, settings::{ActuatorSettings, LinearRange, LinearSpeedScaling, ScalarRange}};
+use bp_scheduler::{actuator::Actuator, envelope::Envelope, interpolation::Interpolation, settings::{ActuatorSettings, LinearRange, LinearSpeedScaling, RetrySettings, SafetyLimits, ScalarRange}};
 use buttplug::core::message::ActuatorType;
 use itertools::Itertools;
 use serde::{Deserialize, Serialize};
@@ -20,6 +21,8 @@ pub static SETTINGS_FILE: &str = "Telekinesis.v2.json";
 pub enum TkConnectionType {
     InProcess,
     WebSocket(String),
+    /// TCode lines written to a serial device or file
+    TCode(String),
     Test,
 }
 
@@ -28,6 +31,7 @@ impl Display for TkConnectionType {
         match self {
             TkConnectionType::InProcess => write!(f, "In-Process"),
             TkConnectionType::WebSocket(host) => write!(f, "WebSocket {}", host),
+            TkConnectionType::TCode(port) => write!(f, "TCode {}", port),
             TkConnectionType::Test => write!(f, "Test"),
         }
     }
@@ -60,10 +64,82 @@ pub struct TkSettings {
     pub log_level: TkLogLevel,
     pub connection: TkConnectionType,
     pub devices: Vec<TkDeviceSettings>,
+    /// Interpolation of scalar patterns by pattern name, unlisted patterns are not interpolated
+    #[serde(default)]
+    pub pattern_interpolation: HashMap<String, Interpolation>,
+    /// Fade times of scalar and pattern tasks that don't specify their own
+    #[serde(default)]
+    pub envelope: Envelope,
+    /// Minimum time between two commands to the same device, takes effect on the next connect
+    #[serde(default)]
+    pub min_command_interval_ms: u32,
+    /// Maximum velocity of the first move of a linear task in percent of the full stroke
+    /// per second, 0 = unlimited, takes effect on the next connect
+    #[serde(default)]
+    pub linear_handover_velocity: u32,
+    /// Stops tasks that run too long, takes effect on the next connect
+    #[serde(default)]
+    pub safety_limits: SafetyLimits,
+    /// Retries of device commands that failed with a transient error, takes effect
+    /// on the next connect
+    #[serde(default)]
+    pub retry: RetrySettings,
+    /// Reading of battery levels and signal strengths, takes effect on the next connect
+    #[serde(default)]
+    pub sensor_polling: TkSensorPolling,
+    /// Axes of the TCode connection, takes effect on the next connect
+    #[serde(default)]
+    pub tcode: TkTCodeSettings,
     #[serde(skip)]
     pub pattern_path: String,
 }
 
+/// Background reading of the battery level and signal strength of all devices
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TkSensorPolling {
+    /// Time between two reads of the same device in seconds, 0 disables polling
+    pub interval_secs: u32,
+    /// `Tele_DeviceBatteryLow` is sent when the battery level falls below this percentage
+    pub battery_low_percent: u32,
+}
+
+impl Default for TkSensorPolling {
+    fn default() -> Self {
+        TkSensorPolling {
+            interval_secs: 60,
+            battery_low_percent: 20,
+        }
+    }
+}
+
+/// Axis mapping of the TCode device, the n-th linear (rotate) actuator of the
+/// device moves the n-th axis of `linear_axes` (`rotate_axes`)
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct TkTCodeSettings {
+    pub linear_axes: Vec<String>,
+    pub rotate_axes: Vec<String>,
+}
+
+impl Default for TkTCodeSettings {
+    fn default() -> Self {
+        TkTCodeSettings {
+            linear_axes: vec![String::from("L0"), String::from("R1"), String::from("R2")],
+            rotate_axes: vec![String::from("R0")],
+        }
+    }
+}
+
+impl TkTCodeSettings {
+    /// Axes of the linear or rotate actuators
+    pub fn axes(&mut self, actuator: ActuatorType) -> Option<&mut Vec<String>> {
+        match actuator {
+            ActuatorType::Position => Some(&mut self.linear_axes),
+            ActuatorType::Rotate => Some(&mut self.rotate_axes),
+            _ => None,
+        }
+    }
+}
+
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct TkDeviceSettings {
     pub actuator_id: String,
@@ -107,6 +183,14 @@ impl TkSettings {
             log_level: TkLogLevel::Debug,
             connection: TkConnectionType::InProcess,
             devices: vec![],
+            pattern_interpolation: HashMap::new(),
+            envelope: Envelope::default(),
+            min_command_interval_ms: 0,
+            linear_handover_velocity: 0,
+            safety_limits: SafetyLimits::default(),
+            retry: RetrySettings::default(),
+            sensor_polling: TkSensorPolling::default(),
+            tcode: TkTCodeSettings::default(),
             pattern_path: String::from(DEFAULT_PATTERN_PATH),
         }
     }
@@ -255,11 +339,24 @@ impl TkSettings {
     pub fn get_enabled(&mut self, actuator_id: &str) -> bool {
         self.get_or_create(actuator_id).enabled
     }
+
+    pub fn set_pattern_interpolation(&mut self, pattern_name: &str, interpolation: Interpolation) {
+        self.pattern_interpolation
+            .insert(pattern_name.trim().to_lowercase(), interpolation);
+    }
+
+    pub fn get_pattern_interpolation(&self, pattern_name: &str) -> Interpolation {
+        self.pattern_interpolation
+            .get(&pattern_name.trim().to_lowercase())
+            .cloned()
+            .unwrap_or_default()
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::input::read_scalar_scaling;
     use tempfile::{tempdir, TempDir};
     use tokio_test::assert_ok;
 
@@ -280,6 +377,38 @@ mod tests {
         );
     }
 
+    #[test]
+    fn pattern_interpolation_ignores_casing() {
+        let mut setting = TkSettings::default();
+        setting.set_pattern_interpolation("Slow Fade ", Interpolation::Eased);
+
+        let serialized = serde_json::to_string_pretty(&setting).unwrap();
+        let deserialized: TkSettings = serde_json::from_str(&serialized).unwrap();
+        assert_eq!(deserialized.get_pattern_interpolation("slow fade"), Interpolation::Eased);
+        assert_eq!(deserialized.get_pattern_interpolation("other"), Interpolation::Step);
+    }
+
+    #[test]
+    fn custom_scalar_scaling_roundtrip() {
+        let mut setting = TkSettings::default();
+        setting.access_scalar("vib1", |x| x.scaling = read_scalar_scaling("0:10, 50:80,100:100"));
+        assert_eq!(
+            setting.access_scalar("vib1", |x| x.scaling.to_string()),
+            "0:10,50:80,100:100"
+        );
+        setting.access_scalar("vib1", |x| x.scaling = read_scalar_scaling("Quadratic"));
+        assert_eq!(setting.access_scalar("vib1", |x| x.scaling.to_string()), "quadratic");
+        setting.access_scalar("vib1", |x| x.scaling = read_scalar_scaling("50:"));
+        assert_eq!(setting.access_scalar("vib1", |x| x.scaling.to_string()), "linear");
+    }
+
+    #[test]
+    fn custom_scalar_scaling_is_sorted_and_validated() {
+        assert_eq!(read_scalar_scaling("100:100,0:10,50:80").to_string(), "0:10,50:80,100:100");
+        assert_eq!(read_scalar_scaling("50:10,50:80").to_string(), "linear");
+        assert_eq!(read_scalar_scaling("").to_string(), "linear");
+    }
+
     #[test]
     fn file_existing_returns_parsed_content() {
         // Arrange
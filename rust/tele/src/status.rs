@@ -1,6 +1,8 @@
 use std::{
+    collections::HashMap,
     fmt::{self, Display},
     sync::Arc,
+    time::Instant,
 };
 
 use bp_scheduler::actuator::{get_actuators, Actuator};
@@ -9,13 +11,20 @@ use crossbeam_channel::Receiver;
 use itertools::Itertools;
 use tracing::debug;
 
-use crate::{connection::TkConnectionEvent, settings::TkSettings};
+use crate::{
+    connection::{TkConnectionEvent, TkDeviceSensors},
+    settings::TkSettings,
+};
 
 pub struct Status {
     status_events: Receiver<TkConnectionEvent>,
     connection: TkConnectionStatus,
     actuators: Vec<(Arc<Actuator>, TkConnectionStatus)>,
     known_actuators: Vec<String>,
+    /// Last recovery of each device after a failure
+    recovered: HashMap<u32, Instant>,
+    /// Last sensor values of each device
+    sensors: HashMap<u32, TkDeviceSensors>,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -36,6 +45,8 @@ impl Status {
                 .iter()
                 .map(|x| x.actuator_id.clone())
                 .collect(),
+            recovered: HashMap::new(),
+            sensors: HashMap::new(),
         }
     }
 
@@ -82,6 +93,13 @@ impl Status {
         TkConnectionStatus::NotConnected
     }
 
+    /// Last sensor values of the device of the actuator
+    pub fn get_sensors(&mut self, actuator_id: &str) -> TkDeviceSensors {
+        self.get_actuator(actuator_id)
+            .and_then(|actuator| self.sensors.get(&actuator.device.index()).cloned())
+            .unwrap_or_default()
+    }
+
     pub fn get_known_actuator_ids(&mut self) -> Vec<String> {
         let known_ids = self.known_actuators.clone();
         self.actuators()
@@ -104,13 +122,31 @@ impl Status {
                     self.set_status(device.clone(), TkConnectionStatus::Connected);
                 }
                 TkConnectionEvent::DeviceRemoved(device) => {
+                    self.sensors.remove(&device.index());
                     self.set_status(device.clone(), TkConnectionStatus::NotConnected)
                 }
-                TkConnectionEvent::ActionError(actuator, err) => {
-                    self.set_status(actuator.device.clone(), TkConnectionStatus::Failed(err))
+                TkConnectionEvent::DeviceSensors(device, sensors) => {
+                    self.sensors.insert(device.index(), sensors);
+                }
+                TkConnectionEvent::BatteryLow(_, _) => {}
+                TkConnectionEvent::SensorReading(_, _, _) => {}
+                TkConnectionEvent::ActionError(actuator, err, _, time) => {
+                    // errors are reported when the task ends, the device may have recovered since
+                    let index = actuator.device.index();
+                    if self.recovered.get(&index).is_none_or(|recovered| *recovered < time) {
+                        self.set_status(actuator.device.clone(), TkConnectionStatus::Failed(err))
+                    }
+                }
+                TkConnectionEvent::DeviceRecovered(actuator, time) => {
+                    self.recovered.insert(actuator.device.index(), time);
+                    self.set_status(actuator.device.clone(), TkConnectionStatus::Connected)
                 }
                 TkConnectionEvent::ActionStarted(_, _, _, _) => {}
                 TkConnectionEvent::ActionDone(_, _, _) => {}
+                TkConnectionEvent::ActionPaused(_) => {}
+                TkConnectionEvent::ActionResumed(_) => {}
+                TkConnectionEvent::ActionTimeout(_, _) => {}
+                TkConnectionEvent::ActionSafetyLimit(_, _, _) => {}
             };
         }
     }
@@ -0,0 +1,88 @@
+use std::{
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use bp_scheduler::{actuator::Actuator, speed::Speed};
+use itertools::Itertools;
+
+use crate::connection::Task;
+
+/// Metadata of a running task, used to display live activity
+#[derive(Clone, Debug)]
+pub struct TkTaskInfo {
+    pub task: Task,
+    pub actuators: Vec<Arc<Actuator>>,
+    pub body_parts: Vec<String>,
+    pub speed: Speed,
+    pub duration: Duration,
+    started: Instant,
+    paused_since: Option<Instant>,
+    paused: Duration,
+}
+
+impl TkTaskInfo {
+    pub fn new(
+        task: Task,
+        actuators: Vec<Arc<Actuator>>,
+        body_parts: Vec<String>,
+        duration: Duration,
+    ) -> Self {
+        TkTaskInfo {
+            speed: task.speed(),
+            task,
+            actuators,
+            body_parts,
+            duration,
+            started: Instant::now(),
+            paused_since: None,
+            paused: Duration::ZERO,
+        }
+    }
+
+    pub fn pause(&mut self) {
+        if self.paused_since.is_none() {
+            self.paused_since = Some(Instant::now());
+        }
+    }
+
+    pub fn resume(&mut self) {
+        if let Some(since) = self.paused_since.take() {
+            self.paused += since.elapsed();
+        }
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused_since.is_some()
+    }
+
+    /// Time the task has been playing, without the time spent paused
+    pub fn elapsed(&self) -> Duration {
+        let paused = self.paused + self.paused_since.map(|x| x.elapsed()).unwrap_or_default();
+        self.started.elapsed().saturating_sub(paused)
+    }
+
+    /// Remaining play time, `None` if the task runs until it is stopped
+    pub fn remaining(&self) -> Option<Duration> {
+        if self.duration == Duration::MAX {
+            return None;
+        }
+        Some(self.duration.saturating_sub(self.elapsed()))
+    }
+
+    /// Lists the task, actuators, body parts, speed, elapsed seconds and
+    /// remaining seconds (-1 if infinite) in that order
+    pub fn describe(&self) -> Vec<String> {
+        vec![
+            self.task.to_string(),
+            self.actuators.iter().map(|x| x.identifier()).join(","),
+            self.body_parts.join(","),
+            self.speed.to_string(),
+            format!("{:.1}", self.elapsed().as_secs_f32()),
+            match self.remaining() {
+                Some(remaining) => format!("{:.1}", remaining.as_secs_f32()),
+                None => String::from("-1"),
+            },
+        ]
+    }
+}
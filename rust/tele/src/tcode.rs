@@ -0,0 +1,329 @@
+use std::{
+    fs::{File, OpenOptions},
+    io::{Seek, SeekFrom, Write},
+    ops::RangeInclusive,
+    sync::{
+        mpsc::{channel, Receiver, Sender as LineSender},
+        Mutex, PoisonError,
+    },
+    thread,
+};
+
+use buttplug::{
+    core::{
+        connector::{ButtplugConnector, ButtplugConnectorError, ButtplugConnectorResultFuture},
+        errors::{ButtplugDeviceError, ButtplugError},
+        message::{
+            self, ActuatorType, ButtplugCurrentSpecClientMessage, ButtplugCurrentSpecServerMessage,
+            ButtplugMessage, ButtplugMessageSpecVersion, ClientDeviceMessageAttributes, DeviceList,
+            DeviceMessageInfo, LinearCmd, RotateCmd, ServerInfo,
+        },
+    },
+    server::device::configuration::{
+        ServerDeviceMessageAttributesBuilder, ServerGenericDeviceMessageAttributes,
+    },
+};
+use futures::{
+    future::{ready, BoxFuture},
+    FutureExt,
+};
+use tokio::sync::{mpsc::Sender, oneshot};
+use tracing::{error, info, trace};
+
+use crate::settings::TkTCodeSettings;
+
+/// Name of the virtual device that represents the TCode output
+pub static TCODE_DEVICE_NAME: &str = "TCode";
+
+/// Highest value of an axis, TCode values are fractions with 4 digits
+static TCODE_MAX: f64 = 9999.0;
+
+/// Stops all moves of the device
+static TCODE_STOP: &str = "DSTOP";
+
+/// Line to write and the receiver of the result
+type Line = (String, oneshot::Sender<Result<(), ButtplugError>>);
+
+/// Connector that drives OSR2/SR6 style multi-axis devices with TCode (v0.3) lines
+/// instead of a buttplug server. It provides a single device with one linear actuator
+/// per linear axis and one rotate actuator per rotate axis, and writes each command
+/// to the serial device or file at `port`
+///
+/// The port is opened as a file and not configured, serial devices must already
+/// use the baud rate of the device (usually 115200), i.e. with `mode COM3 BAUD=115200`
+pub struct TCodeConnector {
+    port: String,
+    settings: TkTCodeSettings,
+    /// Queues the lines for the writer thread, None while disconnected
+    output: Mutex<Option<LineSender<Line>>>,
+    server_sender: Option<Sender<ButtplugCurrentSpecServerMessage>>,
+}
+
+impl TCodeConnector {
+    pub fn new(port: &str, settings: TkTCodeSettings) -> Self {
+        TCodeConnector {
+            port: String::from(port),
+            settings,
+            output: Mutex::new(None),
+            server_sender: None,
+        }
+    }
+
+    fn device_info(&self) -> DeviceMessageInfo {
+        let axes = |axes: &[String], actuator: ActuatorType| {
+            axes.iter()
+                .map(|axis| {
+                    ServerGenericDeviceMessageAttributes::new(
+                        axis,
+                        &RangeInclusive::new(0, TCODE_MAX as u32),
+                        actuator,
+                    )
+                })
+                .collect::<Vec<ServerGenericDeviceMessageAttributes>>()
+        };
+        let mut builder = ServerDeviceMessageAttributesBuilder::default();
+        if !self.settings.linear_axes.is_empty() {
+            builder.linear_cmd(&axes(&self.settings.linear_axes, ActuatorType::Position));
+        }
+        if !self.settings.rotate_axes.is_empty() {
+            builder.rotate_cmd(&axes(&self.settings.rotate_axes, ActuatorType::Rotate));
+        }
+        DeviceMessageInfo::new(
+            0,
+            TCODE_DEVICE_NAME,
+            &None,
+            &None,
+            ClientDeviceMessageAttributes::from(builder.finish()),
+        )
+    }
+
+    /// Queues the line for the writer thread, returns the receiver of the result
+    fn write_line(
+        &self,
+        line: String,
+    ) -> Result<oneshot::Receiver<Result<(), ButtplugError>>, ButtplugError> {
+        trace!(line, "tcode");
+        let output = self.output.lock().map_err(|_| {
+            ButtplugDeviceError::DeviceCommunicationError(String::from("tcode output lock poisoned"))
+        })?;
+        let (result_sender, result_receiver) = oneshot::channel();
+        output
+            .as_ref()
+            .and_then(|output| output.send((line, result_sender)).ok())
+            .ok_or(ButtplugDeviceError::DeviceNotConnected(String::from(TCODE_DEVICE_NAME)))?;
+        Ok(result_receiver)
+    }
+
+    fn execute(
+        &self,
+        msg: ButtplugCurrentSpecClientMessage,
+    ) -> BoxFuture<'static, ButtplugCurrentSpecServerMessage> {
+        let line = match msg {
+            ButtplugCurrentSpecClientMessage::RequestServerInfo(_) => {
+                return ready(ButtplugCurrentSpecServerMessage::ServerInfo(ServerInfo::new(
+                    "Telekinesis TCode",
+                    ButtplugMessageSpecVersion::Version3,
+                    0,
+                )))
+                .boxed()
+            }
+            ButtplugCurrentSpecClientMessage::RequestDeviceList(_) => {
+                return ready(ButtplugCurrentSpecServerMessage::DeviceList(DeviceList::new(vec![
+                    self.device_info(),
+                ])))
+                .boxed()
+            }
+            ButtplugCurrentSpecClientMessage::LinearCmd(cmd) => linear_line(&self.settings, &cmd),
+            ButtplugCurrentSpecClientMessage::RotateCmd(cmd) => rotate_line(&self.settings, &cmd),
+            ButtplugCurrentSpecClientMessage::StopDeviceCmd(_)
+            | ButtplugCurrentSpecClientMessage::StopAllDevices(_) => Ok(String::from(TCODE_STOP)),
+            ButtplugCurrentSpecClientMessage::StartScanning(_)
+            | ButtplugCurrentSpecClientMessage::StopScanning(_)
+            | ButtplugCurrentSpecClientMessage::Ping(_) => {
+                return ready(ButtplugCurrentSpecServerMessage::Ok(message::Ok::default())).boxed()
+            }
+            msg => Err(ButtplugDeviceError::UnhandledCommand(format!("{:?}", msg)).into()),
+        };
+        let written = line.and_then(|line| self.write_line(line));
+        async move {
+            let result = match written {
+                Ok(result_receiver) => result_receiver.await.unwrap_or_else(|_| {
+                    Err(ButtplugDeviceError::DeviceNotConnected(String::from(TCODE_DEVICE_NAME)).into())
+                }),
+                Err(err) => Err(err),
+            };
+            match result {
+                Ok(()) => ButtplugCurrentSpecServerMessage::Ok(message::Ok::default()),
+                Err(err) => {
+                    error!(?err, "tcode command failed");
+                    ButtplugCurrentSpecServerMessage::Error(err.into())
+                }
+            }
+        }
+        .boxed()
+    }
+}
+
+/// Writes the queued lines in order until the connector is disconnected, runs on its
+/// own thread because writes to serial devices block
+fn write_lines(mut file: File, lines: Receiver<Line>) {
+    for (line, result_sender) in lines {
+        let result = file
+            .write_all(format!("{}\n", line).as_bytes())
+            .and_then(|_| file.flush())
+            .map_err(|err| ButtplugDeviceError::DeviceCommunicationError(err.to_string()).into());
+        // the command may have been dropped in the meantime
+        let _ = result_sender.send(result);
+    }
+}
+
+impl ButtplugConnector<ButtplugCurrentSpecClientMessage, ButtplugCurrentSpecServerMessage>
+    for TCodeConnector
+{
+    fn connect(
+        &mut self,
+        message_sender: Sender<ButtplugCurrentSpecServerMessage>,
+    ) -> BoxFuture<'static, Result<(), ButtplugConnectorError>> {
+        let result = OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(&self.port)
+            .map(|mut file| {
+                // appends to files, serial devices cannot seek
+                let _ = file.seek(SeekFrom::End(0));
+                let (line_sender, line_receiver) = channel();
+                thread::spawn(move || write_lines(file, line_receiver));
+                *self.output.get_mut().unwrap_or_else(PoisonError::into_inner) = Some(line_sender);
+                self.server_sender = Some(message_sender);
+                info!(port = self.port, "tcode output opened");
+            })
+            .map_err(|err| {
+                error!(port = self.port, ?err, "failed opening tcode output");
+                ButtplugConnectorError::ConnectorGenericError(err.to_string())
+            });
+        async move { result }.boxed()
+    }
+
+    fn disconnect(&self) -> ButtplugConnectorResultFuture {
+        // the writer thread ends after it wrote the remaining lines
+        *self.output.lock().unwrap_or_else(PoisonError::into_inner) = None;
+        async move { Ok(()) }.boxed()
+    }
+
+    fn send(&self, msg: ButtplugCurrentSpecClientMessage) -> ButtplugConnectorResultFuture {
+        let msg_id = msg.id();
+        let sender = self.server_sender.clone();
+        let response = self.execute(msg);
+        async move {
+            let mut response = response.await;
+            response.set_id(msg_id);
+            sender
+                .ok_or(ButtplugConnectorError::ConnectorNotConnected)?
+                .send(response)
+                .await
+                .map_err(|_| ButtplugConnectorError::ConnectorNotConnected)
+        }
+        .boxed()
+    }
+}
+
+/// Returns true if 'axis' is a TCode axis, i.e. `L0` or `R2`
+pub fn is_valid_axis(axis: &str) -> bool {
+    let mut chars = axis.chars();
+    matches!(
+        (chars.next(), chars.next(), chars.next()),
+        (Some('L' | 'R' | 'V' | 'A'), Some('0'..='9'), None)
+    )
+}
+
+/// Moves the linear axes to their positions within the duration of the command,
+/// i.e. `L09999I500 R10000I500`
+pub fn linear_line(settings: &TkTCodeSettings, cmd: &LinearCmd) -> Result<String, ButtplugError> {
+    let mut commands = vec![];
+    for vector in cmd.vectors() {
+        let axis = get_axis(&settings.linear_axes, vector.index())?;
+        commands.push(format!(
+            "{}I{}",
+            axis_value(axis, vector.position()),
+            vector.duration()
+        ));
+    }
+    Ok(commands.join(" "))
+}
+
+/// Deflects the rotate axes from their center, clockwise rotations move towards the
+/// maximum and counter-clockwise rotations towards the minimum, i.e. `R07500`
+pub fn rotate_line(settings: &TkTCodeSettings, cmd: &RotateCmd) -> Result<String, ButtplugError> {
+    let mut commands = vec![];
+    for rotation in cmd.rotations() {
+        let axis = get_axis(&settings.rotate_axes, rotation.index())?;
+        let deflection = rotation.speed() / 2.0;
+        let value = match rotation.clockwise() {
+            true => 0.5 + deflection,
+            false => 0.5 - deflection,
+        };
+        commands.push(axis_value(axis, value));
+    }
+    Ok(commands.join(" "))
+}
+
+fn get_axis(axes: &[String], index: u32) -> Result<&String, ButtplugError> {
+    axes.get(index as usize).ok_or_else(|| {
+        ButtplugDeviceError::DeviceFeatureIndexError(axes.len() as u32, index).into()
+    })
+}
+
+fn axis_value(axis: &str, value: f64) -> String {
+    format!("{}{:04}", axis, (value.clamp(0.0, 1.0) * TCODE_MAX).round() as u32)
+}
+
+#[cfg(test)]
+mod tests {
+    use buttplug::core::message::{RotationSubcommand, VectorSubcommand};
+
+    use super::*;
+
+    #[test]
+    fn linear_moves_are_written_with_interval() {
+        let cmd = LinearCmd::new(
+            0,
+            vec![
+                VectorSubcommand::new(0, 500, 1.0),
+                VectorSubcommand::new(2, 250, 0.0),
+            ],
+        );
+        let line = linear_line(&TkTCodeSettings::default(), &cmd).unwrap();
+        assert_eq!(line, "L09999I500 R20000I250");
+    }
+
+    #[test]
+    fn rotations_deflect_from_center() {
+        let settings = TkTCodeSettings::default();
+        let clockwise = RotateCmd::new(0, vec![RotationSubcommand::new(0, 0.5, true)]);
+        let counter_clockwise = RotateCmd::new(0, vec![RotationSubcommand::new(0, 1.0, false)]);
+        assert_eq!(rotate_line(&settings, &clockwise).unwrap(), "R07499");
+        assert_eq!(rotate_line(&settings, &counter_clockwise).unwrap(), "R00000");
+    }
+
+    #[test]
+    fn unmapped_axis_fails() {
+        let cmd = LinearCmd::new(0, vec![VectorSubcommand::new(5, 500, 1.0)]);
+        assert!(linear_line(&TkTCodeSettings::default(), &cmd).is_err());
+    }
+
+    #[test]
+    fn writing_without_connection_fails() {
+        let connector = TCodeConnector::new("unused", TkTCodeSettings::default());
+        assert!(connector.write_line(String::from(TCODE_STOP)).is_err());
+    }
+
+    #[test]
+    fn axis_names_are_validated() {
+        assert!(is_valid_axis("L0"));
+        assert!(is_valid_axis("R2"));
+        assert!(!is_valid_axis("X0"));
+        assert!(!is_valid_axis("L"));
+        assert!(!is_valid_axis("L10"));
+    }
+}
@@ -4,6 +4,8 @@ use bp_scheduler::settings::LinearRange;
 use bp_scheduler::speed::Speed;
 use bp_scheduler::ButtplugScheduler;
 use bp_scheduler::PlayerSettings;
+use bp_scheduler::player::{ActuatorRecovery, TaskReport, TaskResult};
+use bp_scheduler::safety::SafetyLimit;
 use buttplug::core::message::ActuatorType;
 use buttplug::{
     client::ButtplugClient,
@@ -21,36 +23,57 @@ use buttplug::{
 };
 use funscript::FScript;
 use futures::Future;
+use itertools::Itertools;
 use tracing::instrument;
 
+use std::collections::HashMap;
 use std::time::Duration;
 use std::{
     fmt::{self},
     time::Instant,
 };
+use tokio::sync::broadcast::{self, error::RecvError};
 use tokio::sync::mpsc::Sender;
-use tokio::{runtime::Runtime, sync::mpsc::channel};
+use tokio::sync::watch;
+use tokio::{
+    runtime::{Handle, Runtime},
+    sync::mpsc::channel,
+    task::JoinHandle,
+    time::timeout,
+};
+use tokio_util::sync::CancellationToken;
 use tracing::{debug, error, info};
 
 use crate::connection::Task;
-use crate::input::TkParams;
+use crate::input::{TkOptions, TkParams};
 use crate::status::Status;
+use crate::tasks::TkTaskInfo;
+use crate::tcode::{is_valid_axis, TCodeConnector, TCODE_DEVICE_NAME};
 use crate::{
-    connection::{handle_connection, TkCommand, TkConnectionEvent},
+    connection::{get_subscribable_sensors, handle_connection, TkCommand, TkConnectionEvent},
     settings::{TkConnectionType, TkSettings},
 };
 
 pub static ERROR_HANDLE: i32 = -1;
+pub static SCALAR_RESOLUTION_MS: i32 = 100;
+/// Maximum time to wait for the worker and the connection to stop on disconnect
+pub static SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(5);
 
 pub struct Telekinesis {
     pub settings: TkSettings,
     pub connection_events: crossbeam_channel::Receiver<TkConnectionEvent>,
     pub status: Status,
-    runtime: Runtime,
+    /// Spawns all background tasks, nothing is spawned after disconnect
+    runtime: Handle,
+    /// Shut down on disconnect, so no background task outlives the connection
+    owned_runtime: Option<Runtime>,
     command_sender: Sender<TkCommand>,
     scheduler: ButtplugScheduler,
+    tasks: HashMap<i32, TkTaskInfo>,
     client_event_sender: crossbeam_channel::Sender<TkConnectionEvent>,
     status_event_sender: crossbeam_channel::Sender<TkConnectionEvent>,
+    worker_task: Option<JoinHandle<()>>,
+    connection_task: Option<JoinHandle<()>>,
 }
 
 impl Telekinesis {
@@ -70,21 +93,32 @@ impl Telekinesis {
         let (event_sender_internal, event_receiver_internal) = crossbeam_channel::unbounded();
         let (command_sender, command_receiver) = channel(256);
         let (scheduler, mut worker) = ButtplugScheduler::create(PlayerSettings {
-            scalar_resolution_ms: 100,
+            scalar_resolution_ms: SCALAR_RESOLUTION_MS,
+            min_command_interval_ms: settings.min_command_interval_ms,
+            handover_velocity: settings.linear_handover_velocity,
+            safety: settings.safety_limits,
+            retry: settings.retry,
         });
 
-        let telekinesis = Telekinesis {
+        let recoveries = scheduler.subscribe_recoveries();
+        let sensor_polling = settings.sensor_polling;
+        let runtime = Runtime::new()?;
+        let mut telekinesis = Telekinesis {
             command_sender,
             connection_events: event_receiver,
-            runtime: Runtime::new()?,
+            runtime: runtime.handle().clone(),
+            owned_runtime: Some(runtime),
             settings: settings.clone(),
             scheduler,
+            tasks: HashMap::new(),
             client_event_sender: event_sender_client.clone(),
             status_event_sender: event_sender_internal.clone(),
             status: Status::new(event_receiver_internal, &settings),
+            worker_task: None,
+            connection_task: None,
         };
         info!(?telekinesis, "connecting...");    
-        telekinesis.runtime.spawn(async move {
+        let connection_task = telekinesis.runtime.spawn(async move {
             let client = with_connector(connect_action().await).await;
             handle_connection(
                 event_sender_client,
@@ -92,15 +126,23 @@ impl Telekinesis {
                 command_receiver,
                 client,
                 type_name,
+                sensor_polling,
             )
             .await;
             debug!("connection handling stopped");
         });
-        telekinesis.runtime.spawn(async move {
+        telekinesis.runtime.spawn(forward_recoveries(
+            recoveries,
+            telekinesis.client_event_sender.clone(),
+            telekinesis.status_event_sender.clone(),
+        ));
+        let worker_task = telekinesis.runtime.spawn(async move {
             debug!("starting worker thread");
             worker.run_worker_thread().await;
             debug!("worked thread stopped");
         });
+        telekinesis.connection_task = Some(connection_task);
+        telekinesis.worker_task = Some(worker_task);
         Ok(telekinesis)
     }
 }
@@ -122,6 +164,14 @@ impl Telekinesis {
                 Some(settings),
                 TkConnectionType::InProcess,
             ),
+            TkConnectionType::TCode(port) => {
+                let (path, tcode) = (port.clone(), settings.tcode.clone());
+                Telekinesis::connect_with(
+                    || async move { TCodeConnector::new(&path, tcode) },
+                    Some(settings_clone),
+                    TkConnectionType::TCode(port),
+                )
+            }
             TkConnectionType::Test => Telekinesis::connect_with(
                 || async move { FakeDeviceConnector::device_demo().0 },
                 Some(settings),
@@ -148,6 +198,69 @@ impl Telekinesis {
         true
     }
 
+    /// Subscribes to the sensors of the actuator's device, their readings are
+    /// sent as `TkConnectionEvent::SensorReading`. Returns false if the device
+    /// has no sensors that can be subscribed
+    pub fn subscribe_sensors(&mut self, actuator_id: &str, subscribe: bool) -> bool {
+        let device = match self.status.get_actuator(actuator_id) {
+            Some(actuator) => actuator.device.clone(),
+            None => {
+                error!(actuator_id, "unknown actuator");
+                return false;
+            }
+        };
+        if get_subscribable_sensors(&device).is_empty() {
+            error!(actuator_id, "device has no sensors to subscribe");
+            return false;
+        }
+        if self
+            .command_sender
+            .try_send(TkCommand::SubscribeSensors(device, subscribe))
+            .is_err()
+        {
+            error!("Failed to change sensor subscription");
+            return false;
+        }
+        true
+    }
+
+    /// TCode axis that is moved by the actuator, None if the actuator
+    /// does not belong to a TCode connection
+    pub fn get_tcode_axis(&mut self, actuator_id: &str) -> Option<String> {
+        let actuator = self.status.get_actuator(actuator_id)?;
+        if actuator.device.name() != TCODE_DEVICE_NAME {
+            return None;
+        }
+        self.settings
+            .tcode
+            .axes(actuator.actuator)?
+            .get(actuator.index_in_device as usize)
+            .cloned()
+    }
+
+    /// Maps the actuator to another TCode axis, takes effect on the next connect
+    pub fn set_tcode_axis(&mut self, actuator_id: &str, axis: &str) -> bool {
+        if !is_valid_axis(axis) {
+            error!(axis, "invalid tcode axis");
+            return false;
+        }
+        let actuator = match self.status.get_actuator(actuator_id) {
+            Some(actuator) if actuator.device.name() == TCODE_DEVICE_NAME => actuator,
+            _ => {
+                error!(actuator_id, "not a tcode actuator");
+                return false;
+            }
+        };
+        let index = actuator.index_in_device as usize;
+        match self.settings.tcode.axes(actuator.actuator) {
+            Some(axes) if index < axes.len() => {
+                axes[index] = String::from(axis);
+                true
+            }
+            _ => false,
+        }
+    }
+
     pub fn scalar(
         &mut self,
         task: Task,
@@ -155,9 +268,26 @@ impl Telekinesis {
         body_parts: Vec<String>,
         fscript: Option<FScript>,
         actuator_types: &[ActuatorType],
+        options: TkOptions,
     ) -> i32 {
         info!("scalar");
-        self.scheduler.clean_finished_tasks();
+        if self.is_disconnected() {
+            error!("already disconnected");
+            return ERROR_HANDLE;
+        }
+        match (&task, &fscript) {
+            (Task::Linear(_, _) | Task::LinearOscillate(_, _), _) => {
+                error!(?task, "not a scalar task");
+                return ERROR_HANDLE;
+            }
+            (Task::Pattern(_, _, _) | Task::RotatePattern(_, _, _) | Task::Sequence(_, _), None) => {
+                error!(?task, "missing pattern");
+                return ERROR_HANDLE;
+            }
+            _ => {}
+        }
+        let fscript = fscript.unwrap_or_default();
+        self.clean_finished_tasks();
 
         let task_clone = task.clone();
         let actuators = self.status.connected_actuators();
@@ -169,11 +299,28 @@ impl Telekinesis {
             &self.settings.devices,
         );
         let settings = devices.iter().map(|x| self.settings.get_or_create(x.identifier()).actuator_settings ).collect();
-        let player = self.scheduler.create_player_with_settings(devices, settings);
+        let mut player = self.scheduler.create_player_with_settings(devices, settings);
+        player.priority = options.priority;
+        player.envelope = options.envelope(self.settings.envelope);
+        player.keep_alive = options.keep_alive();
+        if let Task::Pattern(_, _, pattern_name) | Task::RotatePattern(_, _, pattern_name) = &task {
+            player.interpolation = options
+                .interpolation
+                .unwrap_or_else(|| self.settings.get_pattern_interpolation(pattern_name));
+        }
+        if let Task::Sequence(_, _) = &task {
+            player.interpolation = options.interpolation.unwrap_or_default();
+        }
 
         let handle = player.handle;
+        self.tasks.insert(
+            handle,
+            TkTaskInfo::new(task.clone(), player.actuators.clone(), body_parts.clone(), duration),
+        );
         let client_sender_clone = self.client_event_sender.clone();
         let status_sender_clone = self.status_event_sender.clone();
+        let expired = player.expired_token.clone();
+        let violated_limit = player.violated_limit.subscribe();
         self.runtime.spawn(async move {
             let now = Instant::now();
             client_sender_clone
@@ -187,19 +334,32 @@ impl Telekinesis {
             let result = match task {
                 Task::Scalar(speed) => player.play_scalar(duration, speed).await,
                 Task::Pattern(speed, _, _) => {
+                    player.play_scalar_pattern(duration, fscript, speed).await
+                }
+                Task::Rotate(speed, clockwise) => {
+                    player.play_rotate(duration, speed, clockwise).await
+                }
+                Task::RotatePattern(speed, clockwise, _) => {
                     player
-                        .play_scalar_pattern(duration, fscript.unwrap(), speed)
+                        .play_rotate_pattern(duration, fscript, speed, clockwise)
                         .await
                 }
-                Task::Linear(_, _) => panic!(),
-                Task::LinearOscillate(_, _) => todo!(),
-            };
-            let event = match result {
-                Ok(_) => TkConnectionEvent::ActionDone(task_clone, now.elapsed(), handle),
-                Err(err) => TkConnectionEvent::ActionError(actuators[0].clone(), err.to_string()),
+                Task::Sequence(speed, _) => {
+                    player.play_scalar_pattern(duration, fscript, speed).await
+                }
+                // rejected before the player was created
+                Task::Linear(_, _) | Task::LinearOscillate(_, _) => Ok(()),
             };
-            client_sender_clone.send(event.clone()).expect("never full");
-            status_sender_clone.send(event.clone()).expect("never full");
+            finish_task(
+                result,
+                expired,
+                violated_limit,
+                task_clone,
+                handle,
+                now,
+                client_sender_clone,
+                status_sender_clone,
+            );
         });
         handle
     }
@@ -210,10 +370,19 @@ impl Telekinesis {
         duration: Duration,
         body_parts: Vec<String>,
         fscript: FScript,
+        options: TkOptions,
     ) -> i32 {
         info!("linear pattern");
+        if self.is_disconnected() {
+            error!("already disconnected");
+            return ERROR_HANDLE;
+        }
+        let Task::Linear(speed, _) = task else {
+            error!(?task, "not a linear pattern");
+            return ERROR_HANDLE;
+        };
 
-        self.scheduler.clean_finished_tasks();
+        self.clean_finished_tasks();
         let task_clone = task.clone();
 
         let actuators = self.status.connected_actuators();
@@ -225,11 +394,18 @@ impl Telekinesis {
             &self.settings.devices,
         );
         let settings = devices.iter().map(|x| self.settings.get_or_create(x.identifier()).actuator_settings ).collect();
-        let player = self.scheduler.create_player_with_settings(devices, settings);
+        let mut player = self.scheduler.create_player_with_settings(devices, settings);
+        player.keep_alive = options.keep_alive();
         let handle = player.handle;
+        self.tasks.insert(
+            handle,
+            TkTaskInfo::new(task.clone(), player.actuators.clone(), body_parts.clone(), duration),
+        );
 
         let client_sender_clone = self.client_event_sender.clone();
         let status_sender_clone = self.status_event_sender.clone();
+        let expired = player.expired_token.clone();
+        let violated_limit = player.violated_limit.subscribe();
         self.runtime.spawn(async move {
             let now = Instant::now();
             client_sender_clone
@@ -240,16 +416,17 @@ impl Telekinesis {
                     player.handle,
                 ))
                 .expect("never full");
-            let result = match task {
-                Task::Linear(speed, _) => player.play_linear(duration, fscript).await,
-                _ => panic!(),
-            };
-            let event = match result {
-                Ok(_) => TkConnectionEvent::ActionDone(task_clone, now.elapsed(), handle),
-                Err(err) => TkConnectionEvent::ActionError(actuators[0].clone(), err.to_string()),
-            };
-            client_sender_clone.send(event.clone()).expect("never full");
-            status_sender_clone.send(event.clone()).expect("never full");
+            let result = player.play_linear(duration, fscript, speed).await;
+            finish_task(
+                result,
+                expired,
+                violated_limit,
+                task_clone,
+                handle,
+                now,
+                client_sender_clone,
+                status_sender_clone,
+            );
         });
         handle
     }
@@ -258,12 +435,21 @@ impl Telekinesis {
         &mut self,
         task: Task,
         duration: Duration,
-        range: &str,
+        _range: &str,
         body_parts: Vec<String>,
+        options: TkOptions,
     ) -> i32 {
         info!("linear oscillate");
+        if self.is_disconnected() {
+            error!("already disconnected");
+            return ERROR_HANDLE;
+        }
+        let Task::LinearOscillate(speed, _) = task else {
+            error!(?task, "not a linear oscillation");
+            return ERROR_HANDLE;
+        };
 
-        self.scheduler.clean_finished_tasks();
+        self.clean_finished_tasks();
         let task_clone = task.clone();
 
         let actuators = self.status.connected_actuators();
@@ -275,11 +461,18 @@ impl Telekinesis {
             &self.settings.devices,
         );
         let settings = devices.iter().map(|x| self.settings.get_or_create(x.identifier()).actuator_settings ).collect();
-        let player = self.scheduler.create_player_with_settings(devices, settings);
+        let mut player = self.scheduler.create_player_with_settings(devices, settings);
+        player.keep_alive = options.keep_alive();
         let handle = player.handle;
+        self.tasks.insert(
+            handle,
+            TkTaskInfo::new(task.clone(), player.actuators.clone(), body_parts.clone(), duration),
+        );
 
         let client_sender_clone = self.client_event_sender.clone();
         let status_sender_clone = self.status_event_sender.clone();
+        let expired = player.expired_token.clone();
+        let violated_limit = player.violated_limit.subscribe();
         self.runtime.spawn(async move {
             let now = Instant::now();
             client_sender_clone
@@ -290,31 +483,128 @@ impl Telekinesis {
                     player.handle,
                 ))
                 .expect("never full");
-            let result = match task {
-                Task::LinearOscillate(speed, _) => player.play_oscillate_linear(duration, speed, LinearRange::max()).await,
-                _ => panic!(),
-            };
-            let event = match result {
-                Ok(_) => TkConnectionEvent::ActionDone(task_clone, now.elapsed(), handle),
-                Err(err) => TkConnectionEvent::ActionError(actuators[0].clone(), err.to_string()),
-            };
-            client_sender_clone.send(event.clone()).expect("never full");
-            status_sender_clone.send(event.clone()).expect("never full");
+            let result = player
+                .play_oscillate_linear(duration, speed, LinearRange::max())
+                .await;
+            finish_task(
+                result,
+                expired,
+                violated_limit,
+                task_clone,
+                handle,
+                now,
+                client_sender_clone,
+                status_sender_clone,
+            );
         });
         handle
     }
 
+    /// True after `disconnect`, no new tasks can be started
+    pub fn is_disconnected(&self) -> bool {
+        self.owned_runtime.is_none()
+    }
+
+    /// Handles of all running tasks
+    pub fn get_tasks(&mut self) -> Vec<i32> {
+        self.clean_finished_tasks();
+        self.tasks.keys().copied().sorted().collect()
+    }
+
+    pub fn get_task_info(&mut self, handle: i32) -> Option<&TkTaskInfo> {
+        self.clean_finished_tasks();
+        self.tasks.get(&handle)
+    }
+
+    fn clean_finished_tasks(&mut self) {
+        self.scheduler.clean_finished_tasks();
+        let scheduler = &self.scheduler;
+        self.tasks.retain(|handle, _| scheduler.is_running(*handle));
+    }
+
     #[instrument(skip(self))]
     pub fn update(&mut self, handle: i32, speed: Speed) -> bool {
         info!("update");
-        self.scheduler.clean_finished_tasks();
+        self.clean_finished_tasks();
+        if let Some(info) = self.tasks.get_mut(&handle) {
+            info.speed = speed;
+        }
         self.scheduler.update_task(handle, speed)
     }
 
+    /// Whether the task is a rotation that can change its direction
+    pub fn is_rotation(&self, handle: i32) -> bool {
+        matches!(
+            self.tasks.get(&handle).map(|x| &x.task),
+            Some(Task::Rotate(_, _) | Task::RotatePattern(_, _, _))
+        )
+    }
+
+    /// Changes the direction of a running rotation, has no effect on other tasks
+    #[instrument(skip(self))]
+    pub fn update_direction(&mut self, handle: i32, clockwise: bool) -> bool {
+        info!("update direction");
+        self.clean_finished_tasks();
+        self.scheduler.update_task_direction(handle, clockwise)
+    }
+
+    /// Pauses a running task, it can be continued with `resume`
+    #[instrument(skip(self))]
+    pub fn pause(&mut self, handle: i32) -> bool {
+        info!("pause");
+        self.clean_finished_tasks();
+        if !self.scheduler.pause_task(handle) {
+            return false;
+        }
+        if let Some(info) = self.tasks.get_mut(&handle) {
+            info.pause();
+        }
+        self.client_event_sender
+            .send(TkConnectionEvent::ActionPaused(handle))
+            .expect("never full");
+        true
+    }
+
+    #[instrument(skip(self))]
+    pub fn resume(&mut self, handle: i32) -> bool {
+        info!("resume");
+        self.clean_finished_tasks();
+        if !self.scheduler.resume_task(handle) {
+            return false;
+        }
+        if let Some(info) = self.tasks.get_mut(&handle) {
+            info.resume();
+        }
+        self.client_event_sender
+            .send(TkConnectionEvent::ActionResumed(handle))
+            .expect("never full");
+        true
+    }
+
+    /// Keeps a task with a keep-alive timeout running, updates also count as keep-alive
     #[instrument(skip(self))]
-    pub fn stop(&mut self, handle: i32) -> bool {
+    pub fn keep_alive(&mut self, handle: i32) -> bool {
+        self.clean_finished_tasks();
+        self.scheduler.keep_alive(handle)
+    }
+
+    /// Scales the output of all devices, see `ButtplugScheduler::set_master_intensity`
+    #[instrument(skip(self))]
+    pub fn set_master_intensity(&mut self, intensity: Speed) {
+        info!("master intensity");
+        self.scheduler.set_master_intensity(intensity);
+    }
+
+    pub fn master_intensity(&self) -> Speed {
+        self.scheduler.master_intensity()
+    }
+
+    /// Stops a task, scalar tasks fade out over 'release' instead of the release they were started with
+    #[instrument(skip(self))]
+    pub fn stop(&mut self, handle: i32, release: Option<Duration>) -> bool {
         info!("stop");
-        self.scheduler.stop_task(handle);
+        self.scheduler.stop_task(handle, release);
+        self.tasks.remove(&handle);
         true
     }
 
@@ -322,6 +612,7 @@ impl Telekinesis {
     pub fn stop_all(&mut self) -> bool {
         info!("stop all");
         self.scheduler.stop_all();
+        self.tasks.clear();
         if self.command_sender.try_send(TkCommand::StopAll).is_err() {
             error!("Failed to queue stop_all");
             return false;
@@ -329,16 +620,106 @@ impl Telekinesis {
         true
     }
 
+    /// Stops all tasks and the worker, stops all devices and disconnects the client,
+    /// returns false if the worker or the connection did not stop within the timeout
     #[instrument(skip(self))]
-    pub fn disconnect(&mut self) {
+    pub fn disconnect(&mut self) -> bool {
         info!("disconnect");
-        if self.command_sender.try_send(TkCommand::Disconect).is_err() {
-            error!("Failed to send disconnect");
+        self.scheduler.shutdown();
+        self.tasks.clear();
+        let worker_task = self.worker_task.take();
+        let connection_task = self.connection_task.take();
+        let command_sender = self.command_sender.clone();
+        let shutdown = async move {
+            // the worker sends its last commands before the client is disconnected
+            if let Some(worker_task) = worker_task {
+                let _ = worker_task.await;
+            }
+            if command_sender.send(TkCommand::Disconect).await.is_err() {
+                error!("Failed to send disconnect");
+            }
+            if let Some(connection_task) = connection_task {
+                let _ = connection_task.await;
+            }
+        };
+        let result = self
+            .runtime
+            .block_on(async move { timeout(SHUTDOWN_TIMEOUT, shutdown).await });
+        // cancels everything that is still running, i.e. waiters and sensor forwarders
+        if let Some(runtime) = self.owned_runtime.take() {
+            runtime.shutdown_timeout(SHUTDOWN_TIMEOUT);
+        }
+        match result {
+            Ok(_) => {
+                info!("disconnected");
+                true
+            }
+            Err(_) => {
+                error!("disconnect timed out after {:?}", SHUTDOWN_TIMEOUT);
+                false
+            }
         }
     }
 
 }
 
+/// Notifies Papyrus and the status of actuators that work again after they failed
+async fn forward_recoveries(
+    mut recoveries: broadcast::Receiver<ActuatorRecovery>,
+    client_sender: crossbeam_channel::Sender<TkConnectionEvent>,
+    status_sender: crossbeam_channel::Sender<TkConnectionEvent>,
+) {
+    loop {
+        match recoveries.recv().await {
+            Ok(recovery) => {
+                let event =
+                    TkConnectionEvent::DeviceRecovered(recovery.actuator, recovery.time.into_std());
+                client_sender.send(event.clone()).expect("never full");
+                status_sender.send(event).expect("never full");
+            }
+            Err(RecvError::Lagged(_)) => continue,
+            Err(RecvError::Closed) => break,
+        }
+    }
+}
+
+/// Notifies Papyrus and the status how a task ended
+#[allow(clippy::too_many_arguments)]
+fn finish_task(
+    result: TaskResult,
+    expired: CancellationToken,
+    violated_limit: watch::Receiver<Option<SafetyLimit>>,
+    task: Task,
+    handle: i32,
+    now: Instant,
+    client_sender: crossbeam_channel::Sender<TkConnectionEvent>,
+    status_sender: crossbeam_channel::Sender<TkConnectionEvent>,
+) {
+    let events = match result {
+        Ok(_) if expired.is_cancelled() => vec![TkConnectionEvent::ActionTimeout(task, handle)],
+        Ok(_) => match *violated_limit.borrow() {
+            Some(limit) => vec![TkConnectionEvent::ActionSafetyLimit(task, handle, limit)],
+            None => vec![TkConnectionEvent::ActionDone(task, now.elapsed(), handle)],
+        },
+        Err(report) => error_events(report, handle),
+    };
+    for event in events {
+        client_sender.send(event.clone()).expect("never full");
+        status_sender.send(event).expect("never full");
+    }
+}
+
+/// One error event for each actuator that failed during the task
+fn error_events(report: TaskReport, handle: i32) -> Vec<TkConnectionEvent> {
+    report
+        .errors
+        .into_iter()
+        .map(|x| {
+            TkConnectionEvent::ActionError(x.actuator, x.error.to_string(), handle, x.time.into_std())
+        })
+        .collect()
+}
+
 pub fn in_process_connector(
 ) -> impl ButtplugConnector<ButtplugCurrentSpecClientMessage, ButtplugCurrentSpecServerMessage> {
     ButtplugInProcessClientConnectorBuilder::default()
@@ -374,14 +755,24 @@ impl fmt::Debug for Telekinesis {
 #[cfg(test)]
 mod tests {
     use crate::pattern::read_pattern;
+    use crate::connection::TkDeviceSensors;
     use crate::status::TkConnectionStatus;
-    use crate::telekinesis::in_process_connector;
+    use crate::sequence::TkSequence;
+    use crate::telekinesis::{in_process_connector, SCALAR_RESOLUTION_MS, SHUTDOWN_TIMEOUT};
     use crate::*;
-    use bp_fakes::{scalar, FakeConnectorCallRegistry, FakeDeviceConnector};
+    use bp_fakes::{
+        rotate, scalar, vibrator_with_pressure, vibrator_with_sensors, FakeConnectorCallRegistry,
+        FakeDeviceConnector, FakeMessage,
+    };
+    use bp_scheduler::safety::SafetyLimit;
     use bp_scheduler::speed::Speed;
-    use buttplug::core::message::{ActuatorType, DeviceAdded};
+    use buttplug::core::message::{
+        ActuatorType, ButtplugSpecV3ClientMessage, DeviceAdded, SensorType,
+    };
     use std::time::Instant;
+    use funscript::{FSPoint, FScript};
     use std::{thread, time::Duration, vec};
+    use tempfile::tempdir;
 
     use super::Telekinesis;
 
@@ -419,14 +810,511 @@ mod tests {
             vec![],
             None,
             &[ActuatorType::Vibrate],
+            TkOptions::default(),
         );
 
         thread::sleep(Duration::from_secs(1));
         call_registry.get_device(1)[0].assert_strenth(1.0);
 
-        tk.stop(handle);
+        tk.stop(handle, None);
+        thread::sleep(Duration::from_secs(1));
+        call_registry.get_device(1)[1].assert_strenth(0.0);
+    }
+
+    #[test]
+    fn vibrate_pause_and_resume() {
+        // arrange
+        let (mut tk, call_registry) =
+            wait_for_connection(vec![scalar(1, "vib1", ActuatorType::Vibrate)], None);
+
+        // act
+        let handle = tk.scalar(
+            Task::Scalar(Speed::new(50)),
+            Duration::MAX,
+            vec![],
+            None,
+            &[ActuatorType::Vibrate],
+            TkOptions::default(),
+        );
+        thread::sleep(Duration::from_millis(500));
+        assert!(tk.pause(handle));
+        thread::sleep(Duration::from_millis(500));
+        assert!(tk.resume(handle));
+        thread::sleep(Duration::from_millis(500));
+        tk.stop(handle, None);
+        thread::sleep(Duration::from_millis(500));
+
+        // assert
+        call_registry.get_device(1)[0].assert_strenth(0.5);
+        call_registry.get_device(1)[1].assert_strenth(0.0);
+        call_registry.get_device(1)[2].assert_strenth(0.5);
+        call_registry.get_device(1)[3].assert_strenth(0.0);
+        let events: Vec<TkConnectionEvent> = tk.connection_events.try_iter().collect();
+        assert!(events
+            .iter()
+            .any(|e| matches!(e, TkConnectionEvent::ActionPaused(h) if *h == handle)));
+        assert!(events
+            .iter()
+            .any(|e| matches!(e, TkConnectionEvent::ActionResumed(h) if *h == handle)));
+        assert!(!tk.pause(-1));
+    }
+
+    #[test]
+    fn vibrate_fades_out_on_stop() {
+        // arrange
+        let (mut tk, call_registry) =
+            wait_for_connection(vec![scalar(1, "vib1", ActuatorType::Vibrate)], None);
+
+        // act
+        let handle = tk.scalar(
+            Task::Scalar(Speed::max()),
+            Duration::MAX,
+            vec![],
+            None,
+            &[ActuatorType::Vibrate],
+            TkOptions::parse("release=0.5"),
+        );
+        thread::sleep(Duration::from_millis(200));
+        tk.stop(handle, None);
+        thread::sleep(Duration::from_secs(1));
+
+        // assert
+        let calls = call_registry.get_device(1);
+        calls[0].assert_strenth(1.0);
+        assert!(calls.len() > 3, "fades out in several steps");
+        calls.last().unwrap().assert_strenth(0.0);
+    }
+
+    #[test]
+    fn vibrate_fades_out_with_release_of_stop() {
+        // arrange
+        let (mut tk, call_registry) =
+            wait_for_connection(vec![scalar(1, "vib1", ActuatorType::Vibrate)], None);
+
+        // act
+        let handle = tk.scalar(
+            Task::Scalar(Speed::max()),
+            Duration::MAX,
+            vec![],
+            None,
+            &[ActuatorType::Vibrate],
+            TkOptions::default(),
+        );
+        thread::sleep(Duration::from_millis(200));
+        tk.stop(handle, Some(Duration::from_millis(500)));
         thread::sleep(Duration::from_secs(1));
+
+        // assert
+        let calls = call_registry.get_device(1);
+        calls[0].assert_strenth(1.0);
+        assert!(calls.len() > 3, "fades out in several steps");
+        calls.last().unwrap().assert_strenth(0.0);
+    }
+
+    #[test]
+    fn vibrate_sequence_loops_under_one_handle() {
+        // arrange
+        let (mut tk, call_registry) =
+            wait_for_connection(vec![scalar(1, "vib1", ActuatorType::Vibrate)], None);
+        let steps = "50@0.3,pause@0.3,100@0.3";
+        let sequence = TkSequence::parse(steps).unwrap();
+        let options = TkOptions::parse("loop");
+
+        // act
+        let handle = tk.scalar(
+            Task::Sequence(Speed::max(), steps.into()),
+            sequence.playback_duration(1.2, options.looped),
+            vec![],
+            sequence.to_fscript(SCALAR_RESOLUTION_MS, |_| None),
+            &[ActuatorType::Vibrate],
+            options,
+        );
+        thread::sleep(Duration::from_millis(1500));
+
+        // assert
+        let strengths = call_registry
+            .get_device(1)
+            .iter()
+            .map(get_strength)
+            .collect::<Vec<f64>>();
+        let loop_start = strengths.iter().position(|x| *x == 1.0).unwrap();
+        assert_eq!(strengths[0], 0.5);
+        assert!(strengths[1..loop_start].contains(&0.0));
+        assert!(strengths[loop_start..].contains(&0.5), "restarts after the last step");
+        assert_eq!(*strengths.last().unwrap(), 0.0);
+        assert!(!tk.get_tasks().contains(&handle));
+    }
+
+    #[test]
+    fn vibrate_stops_without_keep_alive() {
+        // arrange
+        let (mut tk, call_registry) =
+            wait_for_connection(vec![scalar(1, "vib1", ActuatorType::Vibrate)], None);
+
+        // act
+        let handle = tk.scalar(
+            Task::Scalar(Speed::new(50)),
+            Duration::MAX,
+            vec![],
+            None,
+            &[ActuatorType::Vibrate],
+            TkOptions::parse("keepalive=0.3"),
+        );
+        thread::sleep(Duration::from_millis(200));
+        assert!(tk.keep_alive(handle));
+        thread::sleep(Duration::from_millis(200));
+        assert!(tk.get_tasks().contains(&handle));
+        thread::sleep(Duration::from_millis(500));
+
+        // assert
+        assert!(!tk.get_tasks().contains(&handle));
+        call_registry.get_device(1)[0].assert_strenth(0.5);
         call_registry.get_device(1)[1].assert_strenth(0.0);
+        let events: Vec<TkConnectionEvent> = tk.connection_events.try_iter().collect();
+        assert!(events
+            .iter()
+            .any(|x| matches!(x, TkConnectionEvent::ActionTimeout(_, h) if *h == handle)));
+    }
+
+    #[test]
+    fn vibrate_stops_at_max_run_time() {
+        // arrange
+        let mut settings = TkSettings::default();
+        settings.safety_limits.max_run_time_ms = 300;
+        let (mut tk, call_registry) =
+            wait_for_connection(vec![scalar(1, "vib1", ActuatorType::Vibrate)], Some(settings));
+
+        // act
+        let handle = tk.scalar(
+            Task::Scalar(Speed::new(50)),
+            Duration::MAX,
+            vec![],
+            None,
+            &[ActuatorType::Vibrate],
+            TkOptions::default(),
+        );
+        thread::sleep(Duration::from_millis(500));
+
+        // assert
+        assert!(!tk.get_tasks().contains(&handle));
+        call_registry.get_device(1)[0].assert_strenth(0.5);
+        call_registry.get_device(1)[1].assert_strenth(0.0);
+        let events: Vec<TkConnectionEvent> = tk.connection_events.try_iter().collect();
+        assert!(events.iter().any(|x| matches!(x,
+            TkConnectionEvent::ActionSafetyLimit(_, h, SafetyLimit::RunTime) if *h == handle)));
+    }
+
+    #[test]
+    fn master_intensity_scales_vibration() {
+        // arrange
+        let (mut tk, call_registry) =
+            wait_for_connection(vec![scalar(1, "vib1", ActuatorType::Vibrate)], None);
+
+        // act
+        tk.set_master_intensity(Speed::new(25));
+        tk.scalar(
+            Task::Scalar(Speed::new(80)),
+            Duration::from_millis(100),
+            vec![],
+            None,
+            &[ActuatorType::Vibrate],
+            TkOptions::default(),
+        );
+        thread::sleep(Duration::from_millis(200));
+
+        // assert
+        assert_eq!(tk.master_intensity(), Speed::new(25));
+        call_registry.get_device(1)[0].assert_strenth(0.2);
+    }
+
+    #[test]
+    fn failed_device_recovers_after_successful_command() {
+        // arrange
+        let mut settings = TkSettings::default();
+        settings.retry.max_retries = 0;
+        let (mut tk, call_registry) =
+            wait_for_connection(vec![scalar(1, "vib1", ActuatorType::Vibrate)], Some(settings));
+        call_registry.fail_next(1, 1);
+
+        // act
+        let handle = tk.scalar(
+            Task::Scalar(Speed::new(50)),
+            Duration::from_millis(200),
+            vec![],
+            None,
+            &[ActuatorType::Vibrate],
+            TkOptions::default(),
+        );
+        thread::sleep(Duration::from_millis(500));
+
+        // assert
+        let events: Vec<TkConnectionEvent> = tk.connection_events.try_iter().collect();
+        assert!(events.iter().any(|x| matches!(x,
+            TkConnectionEvent::ActionError(_, _, h, _) if *h == handle)));
+        assert!(events.iter().any(|x| matches!(x, TkConnectionEvent::DeviceRecovered(_, _))));
+        let actuator_id = tk.status.actuators()[0].identifier().to_owned();
+        assert_eq!(
+            tk.status.get_actuator_status(&actuator_id),
+            TkConnectionStatus::Connected
+        );
+    }
+
+    #[test]
+    fn disconnect_stops_devices_and_joins_background_tasks() {
+        // arrange
+        let (mut tk, call_registry) =
+            wait_for_connection(vec![scalar(1, "vib1", ActuatorType::Vibrate)], None);
+        let handle = tk.scalar(
+            Task::Scalar(Speed::max()),
+            Duration::MAX,
+            vec![],
+            None,
+            &[ActuatorType::Vibrate],
+            TkOptions::default(),
+        );
+        tk.runtime.spawn(std::future::pending::<()>());
+        thread::sleep(Duration::from_millis(200));
+        let runtime = tk.runtime.clone();
+
+        // act
+        let start = Instant::now();
+        let disconnected = tk.disconnect();
+
+        // assert
+        assert!(disconnected);
+        assert!(start.elapsed() < SHUTDOWN_TIMEOUT);
+        assert_eq!(runtime.metrics().num_alive_tasks(), 0);
+        assert!(!tk.get_tasks().contains(&handle));
+        let calls = call_registry.get_device(1);
+        calls[0].assert_strenth(1.0);
+        calls[calls.len() - 1].assert_strenth(0.0);
+    }
+
+    #[test]
+    fn mismatching_tasks_return_error_handle() {
+        // arrange
+        let (mut tk, call_registry) =
+            wait_for_connection(vec![scalar(1, "vib1", ActuatorType::Vibrate)], None);
+
+        // act
+        let linear_as_scalar = tk.scalar(
+            Task::Linear(Speed::max(), String::from("test")),
+            Duration::MAX,
+            vec![],
+            None,
+            &[ActuatorType::Vibrate],
+            TkOptions::default(),
+        );
+        let pattern_without_script = tk.scalar(
+            Task::Pattern(Speed::max(), ActuatorType::Vibrate, String::from("test")),
+            Duration::MAX,
+            vec![],
+            None,
+            &[ActuatorType::Vibrate],
+            TkOptions::default(),
+        );
+        let scalar_as_linear = tk.linear_pattern(
+            Task::Scalar(Speed::max()),
+            Duration::MAX,
+            vec![],
+            FScript::default(),
+            TkOptions::default(),
+        );
+        let scalar_as_oscillation = tk.linear_oscillate(
+            Task::Scalar(Speed::max()),
+            Duration::MAX,
+            "default",
+            vec![],
+            TkOptions::default(),
+        );
+        thread::sleep(Duration::from_millis(100));
+
+        // assert
+        assert_eq!(linear_as_scalar, ERROR_HANDLE);
+        assert_eq!(pattern_without_script, ERROR_HANDLE);
+        assert_eq!(scalar_as_linear, ERROR_HANDLE);
+        assert_eq!(scalar_as_oscillation, ERROR_HANDLE);
+        assert!(tk.get_tasks().is_empty());
+        call_registry.assert_unused(1);
+    }
+
+    #[test]
+    fn controls_after_disconnect_return_error_handle() {
+        // arrange
+        let (mut tk, call_registry) =
+            wait_for_connection(vec![scalar(1, "vib1", ActuatorType::Vibrate)], None);
+        assert!(tk.disconnect());
+
+        // act
+        let scalar = tk.scalar(
+            Task::Scalar(Speed::max()),
+            Duration::MAX,
+            vec![],
+            None,
+            &[ActuatorType::Vibrate],
+            TkOptions::default(),
+        );
+        let linear = tk.linear_pattern(
+            Task::Linear(Speed::max(), String::from("test")),
+            Duration::MAX,
+            vec![],
+            FScript::default(),
+            TkOptions::default(),
+        );
+        let oscillate = tk.linear_oscillate(
+            Task::LinearOscillate(Speed::max(), String::from("default")),
+            Duration::MAX,
+            "default",
+            vec![],
+            TkOptions::default(),
+        );
+
+        // assert
+        assert!(tk.is_disconnected());
+        assert_eq!((scalar, linear, oscillate), (ERROR_HANDLE, ERROR_HANDLE, ERROR_HANDLE));
+        assert!(tk.get_tasks().is_empty());
+        call_registry.assert_unused(1);
+    }
+
+    #[test]
+    fn subscribed_sensor_readings_are_forwarded() {
+        // arrange
+        let (connector, call_registry) = FakeDeviceConnector::new(vec![
+            vibrator_with_pressure(1, "vib1"),
+            scalar(2, "vib2", ActuatorType::Vibrate),
+        ]);
+        let mut tk = wait_for_connector(connector, None);
+
+        // act
+        assert!(tk.subscribe_sensors("vib1 (Vibrate)", true));
+        assert!(!tk.subscribe_sensors("vib2 (Vibrate)", true));
+        thread::sleep(Duration::from_millis(100));
+        call_registry.send_sensor_reading(1, 0, SensorType::Pressure, 420);
+        thread::sleep(Duration::from_millis(100));
+        assert!(tk.subscribe_sensors("vib1 (Vibrate)", false));
+        thread::sleep(Duration::from_millis(100));
+
+        // assert
+        let calls = call_registry.get_device(1);
+        assert!(matches!(
+            calls[0].message,
+            ButtplugSpecV3ClientMessage::SensorSubscribeCmd(_)
+        ));
+        assert!(matches!(
+            calls[1].message,
+            ButtplugSpecV3ClientMessage::SensorUnsubscribeCmd(_)
+        ));
+        let readings: Vec<(String, SensorType, i32)> = tk
+            .connection_events
+            .try_iter()
+            .filter_map(|x| match x {
+                TkConnectionEvent::SensorReading(device, sensor_type, value) => {
+                    Some((device.name().clone(), sensor_type, value))
+                }
+                _ => None,
+            })
+            .collect();
+        assert_eq!(readings, vec![(String::from("vib1"), SensorType::Pressure, 420)]);
+    }
+
+    #[test]
+    fn battery_low_is_reported_when_threshold_is_crossed() {
+        // arrange
+        let (connector, call_registry) =
+            FakeDeviceConnector::new(vec![vibrator_with_sensors(1, "vib1")]);
+        call_registry.set_sensor(1, 0, 50);
+        call_registry.set_sensor(1, 1, -60);
+        let mut settings = TkSettings::default();
+        settings.sensor_polling.interval_secs = 1;
+        settings.sensor_polling.battery_low_percent = 20;
+        let mut tk = wait_for_connector(connector, Some(settings));
+        thread::sleep(Duration::from_millis(200));
+        let actuator_id = tk.status.actuators()[0].identifier().to_owned();
+        assert_eq!(
+            tk.status.get_sensors(&actuator_id),
+            TkDeviceSensors {
+                battery: Some(50.0),
+                rssi: Some(-60)
+            }
+        );
+
+        // act
+        call_registry.set_sensor(1, 0, 10);
+        thread::sleep(Duration::from_millis(2200));
+
+        // assert
+        assert_eq!(tk.status.get_sensors(&actuator_id).battery, Some(10.0));
+        let events: Vec<TkConnectionEvent> = tk.connection_events.try_iter().collect();
+        let battery_low = events
+            .iter()
+            .filter(|x| matches!(x, TkConnectionEvent::BatteryLow(_, percent) if *percent == 10.0))
+            .count();
+        assert_eq!(battery_low, 1);
+    }
+
+    #[test]
+    fn running_tasks_are_listed_with_info() {
+        // arrange
+        let (mut tk, _) =
+            wait_for_connection(vec![scalar(1, "vib1", ActuatorType::Vibrate)], None);
+
+        // act
+        let infinite = tk.scalar(
+            Task::Scalar(Speed::new(40)),
+            Duration::MAX,
+            vec![String::from("nipple")],
+            None,
+            &[ActuatorType::Vibrate],
+            TkOptions::default(),
+        );
+        let finite = tk.scalar(
+            Task::Scalar(Speed::new(20)),
+            Duration::from_secs(10),
+            vec![],
+            None,
+            &[ActuatorType::Vibrate],
+            TkOptions::default(),
+        );
+        tk.update(finite, Speed::new(30));
+
+        // assert
+        assert_eq!(tk.get_tasks(), vec![infinite, finite]);
+        let info = tk.get_task_info(infinite).unwrap().describe();
+        assert_eq!(info[0], "Constant(40%)");
+        assert_eq!(info[2], "nipple");
+        assert_eq!(info[3], "40");
+        assert_eq!(info[5], "-1");
+        let info = tk.get_task_info(finite).unwrap();
+        assert_eq!(info.speed.value, 30);
+        assert!(info.remaining().unwrap() <= Duration::from_secs(10));
+
+        tk.stop(finite, None);
+        assert_eq!(tk.get_tasks(), vec![infinite]);
+        assert!(tk.get_task_info(finite).is_none());
+    }
+
+    #[test]
+    fn rotations_are_described_like_scalar_tasks() {
+        let constant = Task::Rotate(Speed::new(40), true);
+        let pattern = Task::RotatePattern(Speed::new(40), false, String::from("wave"));
+        assert_eq!(constant.to_string(), "Constant(40%, clockwise)");
+        assert_eq!(pattern.to_string(), "Pattern(40, Rotate, wave, counter-clockwise)");
+    }
+
+    #[test]
+    fn finished_tasks_are_not_listed() {
+        let (mut tk, _) =
+            wait_for_connection(vec![scalar(1, "vib1", ActuatorType::Vibrate)], None);
+        tk.scalar(
+            Task::Scalar(Speed::new(40)),
+            Duration::from_millis(100),
+            vec![],
+            None,
+            &[ActuatorType::Vibrate],
+            TkOptions::default(),
+        );
+        thread::sleep(Duration::from_millis(500));
+        assert!(tk.get_tasks().is_empty());
     }
 
     #[test]
@@ -443,6 +1331,7 @@ mod tests {
             vec![],
             None,
             &[ActuatorType::Vibrate],
+            TkOptions::default(),
         );
         thread::sleep(Duration::from_secs(2));
         call_registry.get_device(1)[0].assert_strenth(1.0);
@@ -472,6 +1361,7 @@ mod tests {
             vec![],
             None,
             &[ActuatorType::Vibrate],
+            TkOptions::default(),
         );
 
         // assert
@@ -495,6 +1385,7 @@ mod tests {
             vec![String::from("does not exist")],
             None,
             &[ActuatorType::Vibrate],
+            TkOptions::default(),
         );
         thread::sleep(Duration::from_millis(50));
 
@@ -522,6 +1413,7 @@ mod tests {
             vec![],
             None,
             &[ActuatorType::Vibrate],
+            TkOptions::default(),
         );
         thread::sleep(Duration::from_secs(1));
 
@@ -533,6 +1425,131 @@ mod tests {
         call_registry.assert_unused(2);
     }
 
+    /// Rotate
+
+    #[test]
+    fn rotate_reverse_direction_and_then_stop() {
+        // arrange
+        let (mut tk, call_registry) = wait_for_connection(vec![rotate(1, "rot1")], None);
+
+        // act
+        let handle = tk.scalar(
+            Task::Rotate(Speed::new(40), false),
+            Duration::MAX,
+            vec![],
+            None,
+            &[ActuatorType::Rotate],
+            TkOptions::default(),
+        );
+        thread::sleep(Duration::from_millis(500));
+        tk.update_direction(handle, true);
+        thread::sleep(Duration::from_millis(500));
+        tk.stop(handle, None);
+        thread::sleep(Duration::from_millis(500));
+
+        // assert
+        call_registry.get_device(1)[0]
+            .assert_rotation(0.4)
+            .assert_direction(false);
+        call_registry.get_device(1)[1]
+            .assert_rotation(0.4)
+            .assert_direction(true);
+        call_registry.get_device(1)[2].assert_rotation(0.0);
+    }
+
+    #[test]
+    fn only_rotations_change_direction() {
+        let (mut tk, _) = wait_for_connection(
+            vec![rotate(1, "rot1"), scalar(2, "vib1", ActuatorType::Vibrate)],
+            None,
+        );
+
+        let rotation = tk.scalar(
+            Task::Rotate(Speed::new(40), false),
+            Duration::MAX,
+            vec![],
+            None,
+            &[ActuatorType::Rotate],
+            TkOptions::default(),
+        );
+        let vibration = tk.scalar(
+            Task::Scalar(Speed::new(40)),
+            Duration::MAX,
+            vec![],
+            None,
+            &[ActuatorType::Vibrate],
+            TkOptions::default(),
+        );
+
+        assert!(tk.is_rotation(rotation));
+        assert!(!tk.is_rotation(vibration));
+        assert!(!tk.is_rotation(-1));
+    }
+
+    /// TCode
+
+    #[test]
+    fn tcode_lines_are_written_to_output() {
+        // arrange
+        let dir = tempdir().unwrap();
+        let output = dir.path().join("tcode.txt");
+        let mut settings = TkSettings::default();
+        settings.connection = TkConnectionType::TCode(output.to_string_lossy().into());
+        let mut tk = Telekinesis::connect(settings).unwrap();
+        tk.scan_for_devices();
+        tk.await_connect(4);
+        tk.settings.set_enabled("TCode (Position #1)", true);
+        tk.settings.set_enabled("TCode (Rotate)", true);
+
+        // act
+        let mut fscript = FScript::default();
+        fscript.actions.push(FSPoint { pos: 0, at: 0 });
+        fscript.actions.push(FSPoint { pos: 100, at: 200 });
+        tk.linear_pattern(
+            Task::Linear(Speed::max(), String::from("test")),
+            Duration::from_millis(300),
+            vec![],
+            fscript,
+            TkOptions::default(),
+        );
+        tk.scalar(
+            Task::Rotate(Speed::new(50), true),
+            Duration::from_millis(300),
+            vec![],
+            None,
+            &[ActuatorType::Rotate],
+            TkOptions::default(),
+        );
+        thread::sleep(Duration::from_millis(600));
+        assert!(tk.disconnect());
+
+        // assert
+        let lines = std::fs::read_to_string(output).unwrap();
+        let lines: Vec<&str> = lines.lines().collect();
+        assert!(lines.iter().any(|x| x.starts_with("R1") && x.contains('I')));
+        assert!(lines.iter().any(|x| x.starts_with("R07")));
+        assert!(lines.iter().all(|x| !x.starts_with("L0") && !x.starts_with("R2")));
+        assert_eq!(lines.last(), Some(&"DSTOP"));
+    }
+
+    #[test]
+    fn tcode_axis_is_mapped_per_actuator() {
+        let dir = tempdir().unwrap();
+        let mut settings = TkSettings::default();
+        settings.connection =
+            TkConnectionType::TCode(dir.path().join("tcode.txt").to_string_lossy().into());
+        let mut tk = Telekinesis::connect(settings).unwrap();
+        tk.scan_for_devices();
+        tk.await_connect(4);
+
+        assert_eq!(tk.get_tcode_axis("TCode (Position #2)"), Some(String::from("R2")));
+        assert!(tk.set_tcode_axis("TCode (Position #2)", "L1"));
+        assert!(!tk.set_tcode_axis("TCode (Position #2)", "X9"));
+        assert!(!tk.set_tcode_axis("unknown", "L2"));
+        assert_eq!(tk.get_tcode_axis("TCode (Position #2)"), Some(String::from("L1")));
+        assert_eq!(tk.settings.tcode.linear_axes, vec!["L0", "R1", "L1"]);
+    }
+
     /// Vibrate (E2E)
 
     #[test]
@@ -540,7 +1557,7 @@ mod tests {
     fn vibrate_pattern() {
         let (mut tk, handle) = test_pattern("02_Cruel-Tease", Duration::from_secs(10), true);
         thread::sleep(Duration::from_secs(2)); // dont disconnect
-        tk.stop(handle);
+        tk.stop(handle, None);
         thread::sleep(Duration::from_secs(10));
     }
 
@@ -572,6 +1589,7 @@ mod tests {
             vec![],
             Some(fscript),
             &[ActuatorType::Vibrate],
+            TkOptions::default(),
         );
         (tk, handle)
     }
@@ -602,6 +1620,7 @@ mod tests {
             vec![],
             None,
             &[ActuatorType::Vibrate],
+            TkOptions::default(),
         );
         thread::sleep(Duration::from_secs(5));
     }
@@ -636,6 +1655,7 @@ mod tests {
             vec![String::from("some event")],
             None,
             &[ActuatorType::Vibrate],
+            TkOptions::default(),
         );
 
         thread::sleep(Duration::from_millis(500));
@@ -725,6 +1745,7 @@ mod tests {
             vec![String::from("selected_event")],
             None,
             &[ActuatorType::Vibrate],
+            TkOptions::default(),
         );
         thread::sleep(Duration::from_secs(1));
 
@@ -745,6 +1766,7 @@ mod tests {
             vec![String::from(" SoMe EvEnT    ")],
             None,
             &[ActuatorType::Vibrate],
+            TkOptions::default(),
         );
 
         thread::sleep(Duration::from_millis(500));
@@ -785,6 +1807,7 @@ mod tests {
             vec![],
             None,
             &[ActuatorType::Vibrate],
+            TkOptions::default(),
         );
         get_next_events_blocking(&tk.connection_events);
     }
@@ -803,6 +1826,7 @@ mod tests {
             vec![],
             None,
             &[ActuatorType::Vibrate],
+            TkOptions::default(),
         );
         tk.scalar(
             Task::Scalar(Speed::new(20)),
@@ -810,16 +1834,28 @@ mod tests {
             vec![],
             None,
             &[ActuatorType::Vibrate],
+            TkOptions::default(),
         );
         get_next_events_blocking(&tk.connection_events);
         get_next_events_blocking(&tk.connection_events);
     }
 
+    fn get_strength(call: &FakeMessage) -> f64 {
+        match &call.message {
+            ButtplugSpecV3ClientMessage::ScalarCmd(cmd) => cmd.scalars()[0].scalar(),
+            _ => panic!("Message is not scalar cmd"),
+        }
+    }
+
     fn wait_for_connection(
         devices: Vec<DeviceAdded>,
         settings: Option<TkSettings>,
     ) -> (Telekinesis, FakeConnectorCallRegistry) {
         let (connector, call_registry) = FakeDeviceConnector::new(devices);
+        (wait_for_connector(connector, settings), call_registry)
+    }
+
+    fn wait_for_connector(connector: FakeDeviceConnector, settings: Option<TkSettings>) -> Telekinesis {
         let count = connector.devices.len();
 
         // act
@@ -837,6 +1873,6 @@ mod tests {
         for actuator in tk.status.actuators() {
             tk.settings.set_enabled(actuator.identifier(), true);
         }
-        (tk, call_registry)
+        tk
     }
 }
@@ -47,7 +47,10 @@ namespace Tele {
     int Tele_Update(SFT, int handle, int speed) {
         return tk->tk_update(handle, speed);
     }
-    bool Tele_Stop(SFT, int handle) { return tk->tk_stop(handle); }
+    bool Tele_Stop(SFT, int handle, float release_sec) { return tk->tk_stop(handle, release_sec); }
+    bool Tele_Pause(SFT, int handle) { return tk->tk_pause(handle); }
+    bool Tele_Resume(SFT, int handle) { return tk->tk_resume(handle); }
+    bool Tele_KeepAlive(SFT, int handle) { return tk->tk_keep_alive(handle); }
 }
 
 void Tele_Event_Thread() {
@@ -87,6 +90,9 @@ bool RegisterPapyrusCalls(IVirtualMachine* vm) {
     vm->RegisterFunction("Tele_Control", PapyrusClass, Tele::Tele_Control);
     vm->RegisterFunction("Tele_Update", PapyrusClass, Tele::Tele_Update);
     vm->RegisterFunction("Tele_Stop", PapyrusClass, Tele::Tele_Stop);
+    vm->RegisterFunction("Tele_Pause", PapyrusClass, Tele::Tele_Pause);
+    vm->RegisterFunction("Tele_Resume", PapyrusClass, Tele::Tele_Resume);
+    vm->RegisterFunction("Tele_KeepAlive", PapyrusClass, Tele::Tele_KeepAlive);
     return true;
 }
 